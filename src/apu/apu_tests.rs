@@ -0,0 +1,480 @@
+#[cfg(test)]
+mod test {
+    use crate::apu::*;
+    use crate::cpu::Mem;
+    use crate::region::Region;
+
+    // Runs the pulse timers until the duty step has moved `steps` times.
+    fn run_steps(apu: &mut Apu, period: u16, steps: usize) {
+        for _ in 0..steps * (period as usize + 1) * 2 {
+            apu.tick();
+        }
+    }
+
+    #[test]
+    fn test_pulse_duty_sequence() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4015, 0b01);
+        // 25% duty, constant volume 9.
+        apu.write_register(0x4000, 0b0101_1001);
+        apu.write_register(0x4002, 0x10);
+        apu.write_register(0x4003, 0x08);
+
+        let mut wave = Vec::new();
+        for _ in 0..8 {
+            wave.push(apu.pulse_output(1));
+            run_steps(&mut apu, 0x10, 1);
+        }
+        assert_eq!(wave, vec![0, 9, 9, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_length_counter() {
+        let mut apu = Apu::new();
+        // Loading while disabled does nothing.
+        apu.write_register(0x4003, 0b0000_1000);
+        assert_eq!(apu.read_status(), 0);
+
+        apu.write_register(0x4015, 0b11);
+        // Index 1 loads 254, index 3 loads 2.
+        apu.write_register(0x4003, 0b0000_1000);
+        apu.write_register(0x4007, 0b0001_1000);
+        assert_eq!(apu.read_status(), 0b11);
+
+        apu.clock_half_frame();
+        apu.clock_half_frame();
+        assert_eq!(apu.read_status(), 0b01);
+
+        apu.write_register(0x4015, 0b00);
+        assert_eq!(apu.read_status(), 0);
+    }
+
+    #[test]
+    fn test_length_counter_halt() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4015, 0b01);
+        apu.write_register(0x4000, 0b0010_0000);
+        apu.write_register(0x4003, 0b0001_1000);
+        for _ in 0..10 {
+            apu.clock_half_frame();
+        }
+        assert_eq!(apu.read_status(), 0b01);
+    }
+
+    #[test]
+    fn test_envelope_decay() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4015, 0b01);
+        // 50% duty, length halted, envelope period 2.
+        apu.write_register(0x4000, 0b1010_0010);
+        apu.write_register(0x4002, 0x20);
+        apu.write_register(0x4003, 0x08);
+        run_steps(&mut apu, 0x20, 1);
+
+        apu.clock_quarter_frame();
+        assert_eq!(apu.pulse_output(1), 15);
+        for _ in 0..3 {
+            apu.clock_quarter_frame();
+        }
+        assert_eq!(apu.pulse_output(1), 14);
+        for _ in 0..14 * 3 {
+            apu.clock_quarter_frame();
+        }
+        assert_eq!(apu.pulse_output(1), 0);
+        // The loop flag restarts it at 15.
+        for _ in 0..3 {
+            apu.clock_quarter_frame();
+        }
+        assert_eq!(apu.pulse_output(1), 15);
+    }
+
+    fn swept_period(negate_channel: Option<usize>, channel: usize) -> u16 {
+        let mut apu = Apu::new();
+        let base = 0x4000 + (channel as u16 - 1) * 4;
+        apu.write_register(0x4015, 0b11);
+        apu.write_register(base, 0b1011_1111);
+        // Sweep enabled, period 0, shift 1.
+        let negate = if negate_channel.is_some() { 0b1000 } else { 0 };
+        apu.write_register(base + 1, 0b1000_0001 | negate);
+        apu.write_register(base + 2, 0x00);
+        apu.write_register(base + 3, 0x09);
+        apu.clock_half_frame();
+        apu.pulses[channel - 1].timer_period
+    }
+
+    #[test]
+    fn test_sweep() {
+        assert_eq!(swept_period(None, 1), 0x180);
+        assert_eq!(swept_period(None, 2), 0x180);
+        // Pulse 1 subtracts the ones' complement, pulse 2 the two's.
+        assert_eq!(swept_period(Some(1), 1), 0x7F);
+        assert_eq!(swept_period(Some(2), 2), 0x80);
+    }
+
+    #[test]
+    fn test_sweep_mutes_out_of_range_periods() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4015, 0b01);
+        apu.write_register(0x4000, 0b1011_1111);
+        // Shift 1 on a period of $600 targets $900, even with the sweep off.
+        apu.write_register(0x4001, 0b0000_0001);
+        apu.write_register(0x4002, 0x00);
+        apu.write_register(0x4003, 0x0E);
+        run_steps(&mut apu, 0x600, 1);
+        assert_eq!(apu.pulse_output(1), 0);
+
+        apu.write_register(0x4001, 0b0000_0011);
+        assert_eq!(apu.pulse_output(1), 15);
+
+        // Periods under 8 are muted too.
+        apu.write_register(0x4002, 0x07);
+        apu.write_register(0x4003, 0x08);
+        run_steps(&mut apu, 0x07, 1);
+        assert_eq!(apu.pulse_output(1), 0);
+    }
+
+    #[test]
+    fn test_triangle_linear_counter() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4015, 0b0100);
+        // Linear counter of 2, period 0.
+        apu.write_register(0x4008, 0x02);
+        apu.write_register(0x400A, 0x00);
+        apu.write_register(0x400B, 0x08);
+
+        // Nothing moves until the linear counter has been loaded.
+        apu.tick();
+        assert_eq!(apu.triangle_output(), 15);
+
+        apu.clock_quarter_frame();
+        let mut steps = Vec::new();
+        for _ in 0..4 {
+            apu.tick();
+            steps.push(apu.triangle_output());
+        }
+        assert_eq!(steps, vec![14, 13, 12, 11]);
+
+        apu.clock_quarter_frame();
+        apu.clock_quarter_frame();
+        apu.tick();
+        // Silenced channels hold their last step.
+        assert_eq!(apu.triangle_output(), 11);
+    }
+
+    fn noise_sequence_length(mode: u8) -> usize {
+        let mut noise = noise::Noise::new();
+        noise.write(2, mode);
+        let start = noise.shift;
+        let mut steps = 0;
+        loop {
+            for _ in 0..4 {
+                noise.clock_timer();
+            }
+            steps += 1;
+            if noise.shift == start {
+                return steps;
+            }
+        }
+    }
+
+    #[test]
+    fn test_noise_lfsr_modes() {
+        assert_eq!(noise_sequence_length(0x00), 32767);
+        assert_eq!(noise_sequence_length(0x80), 93);
+    }
+
+    #[test]
+    fn test_noise_periods_follow_the_region() {
+        let mut noise = noise::Noise::new();
+        noise.write(2, 0x0F);
+        assert_eq!(noise.timer_period, 4068);
+        noise.set_region(Region::Pal);
+        noise.write(2, 0x0F);
+        assert_eq!(noise.timer_period, 3778);
+    }
+
+    #[test]
+    fn test_dmc_sample_playback() {
+        let mut apu = Apu::new();
+        // IRQ enabled, fastest rate; $C040, 17 bytes.
+        apu.write_register(0x4010, 0x8F);
+        apu.write_register(0x4011, 0x40);
+        apu.write_register(0x4012, 0x01);
+        apu.write_register(0x4013, 0x01);
+        apu.write_register(0x4015, 0b1_0000);
+        assert_eq!(apu.read_status(), 0b1_0000);
+
+        let mut addresses = Vec::new();
+        for _ in 0..17 * 8 * 54 + 54 {
+            apu.tick();
+            if let Some(addr) = apu.dmc_dma_request() {
+                addresses.push(addr);
+                // All ones: the level climbs by 2 per bit until it tops out.
+                apu.dmc_dma_complete(0xFF);
+            }
+        }
+        let expected: Vec<u16> = (0xC040..0xC051).collect();
+        assert_eq!(addresses, expected);
+        assert_eq!(apu.dmc_output(), 126);
+        assert_eq!(apu.read_status(), 0b1000_0000);
+        assert!(apu.irq());
+
+        // Writing $4015 acknowledges the interrupt.
+        apu.write_register(0x4015, 0);
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_dmc_looping_and_address_wrap() {
+        let mut apu = Apu::new();
+        // This runs past the first frame interrupt.
+        apu.write_register(0x4017, 0x40);
+        apu.write_register(0x4010, 0x40);
+        // 65 bytes from $FFC0: the last one comes from $8000.
+        apu.write_register(0x4012, 0xFF);
+        apu.write_register(0x4013, 0x04);
+        apu.write_register(0x4015, 0b1_0000);
+
+        let mut addresses = Vec::new();
+        while addresses.len() < 66 {
+            if let Some(addr) = apu.dmc_dma_request() {
+                addresses.push(addr);
+                apu.dmc_dma_complete(0);
+                // The buffer stays full until the output unit takes the byte.
+                assert_eq!(apu.dmc_dma_request(), None);
+            }
+            apu.tick();
+        }
+        let mut expected: Vec<u16> = (0xFFC0..=0xFFFF).collect();
+        expected.push(0x8000);
+        // A looping sample starts over without an interrupt.
+        expected.push(0xFFC0);
+        assert_eq!(addresses, expected);
+        assert!(!apu.irq());
+        assert_eq!(apu.read_status(), 0b1_0000);
+    }
+
+    // Cycles (counted from the first clock) at which the frame counter
+    // clocks quarter and half frames, over `cycles` CPU cycles.
+    fn frame_clocks(counter: &mut frame_counter::FrameCounter, cycles: u32) -> Vec<(u32, bool)> {
+        (1..=cycles)
+            .filter_map(|cycle| {
+                let clock = counter.clock();
+                assert!(!clock.half || clock.quarter);
+                clock.quarter.then_some((cycle, clock.half))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_frame_counter_four_step() {
+        let mut counter = frame_counter::FrameCounter::new();
+        let clocks = frame_clocks(&mut counter, 29830 + 7457);
+        assert_eq!(
+            clocks,
+            vec![(7457, false), (14913, true), (22371, false), (29829, true), (29830 + 7457, false)]
+        );
+        assert!(counter.irq);
+    }
+
+    #[test]
+    fn test_frame_counter_five_step() {
+        let mut counter = frame_counter::FrameCounter::new();
+        // Written in the first half of an APU cycle: takes effect 3 cycles
+        // later, with an immediate quarter and half frame.
+        counter.write(0x80, false);
+        let clocks = frame_clocks(&mut counter, 3 + 37282 + 7457);
+        assert_eq!(
+            clocks,
+            vec![
+                (3, true),
+                (3 + 7457, false),
+                (3 + 14913, true),
+                (3 + 22371, false),
+                (3 + 37281, true),
+                (3 + 37282 + 7457, false),
+            ]
+        );
+        assert!(!counter.irq);
+
+        counter.write(0x80, true);
+        assert_eq!(frame_clocks(&mut counter, 4), vec![(4, true)]);
+    }
+
+    #[test]
+    fn test_frame_counter_pal() {
+        let mut counter = frame_counter::FrameCounter::new();
+        counter.set_region(Region::Pal);
+        let clocks = frame_clocks(&mut counter, 33254);
+        assert_eq!(clocks, vec![(8313, false), (16627, true), (24939, false), (33253, true)]);
+    }
+
+    #[test]
+    fn test_frame_irq() {
+        let mut apu = Apu::new();
+        for _ in 0..29827 {
+            apu.tick();
+        }
+        assert!(!apu.irq());
+        apu.tick();
+        assert!(apu.irq());
+
+        // Reading $4015 returns and acknowledges it.
+        assert_eq!(apu.read_status() & 0x40, 0x40);
+        assert!(!apu.irq());
+        assert_eq!(apu.read_status() & 0x40, 0);
+
+        // It is raised again on the following two cycles.
+        apu.tick();
+        assert!(apu.irq());
+
+        // Setting the inhibit flag clears it and keeps it clear.
+        apu.write_register(0x4017, 0x40);
+        assert!(!apu.irq());
+        for _ in 0..2 * 29830 {
+            apu.tick();
+        }
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn test_frame_counter_clocks_length_counters() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4015, 0b01);
+        // Length 2: gone after the second half frame.
+        apu.write_register(0x4003, 0b0001_1000);
+        for _ in 0..29828 {
+            apu.tick();
+        }
+        assert_eq!(apu.read_status() & 0b01, 0b01);
+        apu.tick();
+        assert_eq!(apu.read_status() & 0b01, 0);
+    }
+
+    #[test]
+    fn test_mixer() {
+        assert_eq!(mixer::mix(0, 0, 0, 0, 0), 0.0);
+        assert!((mixer::mix(15, 15, 0, 0, 0) - 0.2575).abs() < 1e-3);
+        assert!((mixer::mix(0, 0, 15, 15, 127) - 0.7425).abs() < 1e-3);
+        // Two pulses together are quieter than twice one.
+        assert!(mixer::mix(15, 15, 0, 0, 0) < 2.0 * mixer::mix(15, 0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_filters_remove_dc() {
+        let mut filters = filter::FilterChain::new(48000.0);
+        let mut output = 1.0;
+        for _ in 0..48000 {
+            output = filters.process(0.5);
+        }
+        assert!(output.abs() < 1e-3);
+    }
+
+    const CLOCK: f64 = 1_789_773.0;
+
+    // Feeds a square wave of `period` CPU cycles through the resampler for
+    // a tenth of a second, and returns the samples.
+    fn resample_square(period: usize, sample_rate: u32) -> Vec<f32> {
+        let mut resampler = resampler::Resampler::new(CLOCK, sample_rate);
+        let mut samples = Vec::new();
+        for cycle in 0..CLOCK as usize / 10 {
+            let high = cycle % period < period / 2;
+            resampler.clock(if high { 0.25 } else { 0.0 });
+            if cycle % 1000 == 0 {
+                resampler.read_samples(&mut samples);
+            }
+        }
+        resampler.read_samples(&mut samples);
+        samples
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_resampler_rates() {
+        for rate in [44100, 48000] {
+            let samples = resample_square(1790, rate);
+            let expected = rate as usize / 10;
+            assert!(samples.len().abs_diff(expected) <= 16, "{} samples", samples.len());
+        }
+    }
+
+    #[test]
+    fn test_resampler_rate_adjustment() {
+        let mut resampler = resampler::Resampler::new(CLOCK, 48000);
+        resampler.set_rate_adjustment(1.005);
+        let mut samples = Vec::new();
+        for _ in 0..CLOCK as usize {
+            resampler.clock(0.0);
+        }
+        resampler.read_samples(&mut samples);
+        assert!(samples.len().abs_diff(48240) <= 16, "{} samples", samples.len());
+    }
+
+    #[test]
+    fn test_resampler_passes_audible_tones() {
+        // ~1 kHz: a 0.25 square wave has an RMS of 0.125.
+        let samples = resample_square(1790, 48000);
+        assert!(rms(&samples[1000..]) > 0.1);
+    }
+
+    #[test]
+    fn test_resampler_does_not_alias() {
+        // ~44.7 kHz, the fastest a pulse channel goes with a period of 19.
+        // Point sampling would fold it down to an audible 3.3 kHz.
+        let samples = resample_square(40, 48000);
+        assert!(rms(&samples[1000..]) < 0.01);
+    }
+
+    #[test]
+    fn test_bus_resamples_apu_output() {
+        let mut bus = crate::bus::Bus::new();
+        bus.enable_audio(44100);
+        bus.mem_write(0x4015, 0b01);
+        bus.mem_write(0x4000, 0b1011_1111);
+        bus.mem_write(0x4002, 0xfd);
+        bus.mem_write(0x4003, 0x08);
+        for _ in 0..CLOCK as usize / 100 {
+            bus.tick(1);
+        }
+        let mut samples = Vec::new();
+        bus.read_audio(&mut samples);
+        assert!(samples.len() > 400);
+        assert!(rms(&samples[100..]) > 0.05);
+    }
+
+    #[test]
+    fn test_bus_channel_audio() {
+        let mut bus = crate::bus::Bus::new();
+        bus.enable_audio(48000);
+        bus.enable_channel_audio(48000);
+        bus.mem_write(0x4015, 0b10);
+        bus.mem_write(0x4004, 0b1011_1111);
+        bus.mem_write(0x4006, 0xfd);
+        bus.mem_write(0x4007, 0x08);
+        for _ in 0..CLOCK as usize / 100 {
+            bus.tick(1);
+        }
+        let mut mix = Vec::new();
+        let mut channels = Vec::new();
+        bus.read_audio(&mut mix);
+        bus.read_channel_audio(&mut channels);
+
+        let tracks = crate::bus::AUDIO_CHANNELS.len();
+        assert_eq!(channels.len(), mix.len() * tracks);
+        let track = |index: usize| -> Vec<f32> { channels.iter().skip(index).step_by(tracks).copied().collect() };
+        // Only pulse 2 is playing, besides the triangle holding its first
+        // step. The pulse and TND halves of the mixer add up linearly.
+        let (pulse, triangle) = (track(1), track(2));
+        for (frame, &sample) in mix.iter().enumerate() {
+            assert!((pulse[frame] + triangle[frame] - sample).abs() < 1e-5);
+        }
+        assert!(rms(&pulse[100..]) > 0.05);
+        for index in [0, 3, 4, 5] {
+            assert!(track(index).iter().all(|&sample| sample == 0.0));
+        }
+    }
+}
+
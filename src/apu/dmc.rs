@@ -0,0 +1,177 @@
+use crate::region::Region;
+
+/// Output rates in CPU cycles per bit. Dendy uses the NTSC table.
+#[rustfmt::skip]
+const RATES_NTSC: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+#[rustfmt::skip]
+const RATES_PAL: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+/// # APU delta modulation channel https://www.nesdev.org/wiki/APU_DMC
+///
+/// | Register | Bits        | Function                                  |
+/// |----------|-------------|-------------------------------------------|
+/// | $4010    | `IL-- RRRR` | IRQ enable, loop, rate index              |
+/// | $4011    | `-DDD DDDD` | direct load of the output level           |
+/// | $4012    | `AAAA AAAA` | sample address, $C000 + A * 64            |
+/// | $4013    | `LLLL LLLL` | sample length, L * 16 + 1 bytes           |
+///
+/// Plays 1-bit deltas from a sample in CPU memory: each bit moves the 7-bit
+/// output level up or down by 2. The channel cannot read memory itself; the
+/// bus fetches the next byte by DMA when `dma_request` asks for one and hands
+/// it back through `dma_complete`.
+pub struct Dmc {
+    region: Region,
+    pub irq_enabled: bool,
+    pub irq: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+    level: u8,
+
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    fetching: bool,
+
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Dmc {
+    pub fn new() -> Self {
+        Dmc {
+            region: Region::Ntsc,
+            irq_enabled: false,
+            irq: false,
+            looping: false,
+            timer_period: RATES_NTSC[0],
+            timer: 0,
+            level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            buffer: None,
+            fetching: false,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = data & 0b1000_0000 != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.looping = data & 0b0100_0000 != 0;
+                let rates = match self.region {
+                    Region::Pal => &RATES_PAL,
+                    Region::Ntsc | Region::Dendy => &RATES_NTSC,
+                };
+                self.timer_period = rates[(data & 0b1111) as usize];
+            }
+            1 => self.level = data & 0b0111_1111,
+            2 => self.sample_address = 0xC000 | (data as u16) << 6,
+            _ => self.sample_length = (data as u16) << 4 | 1,
+        }
+    }
+
+    /// Bit 4 of $4015: disabling drops the rest of the sample, enabling
+    /// restarts it unless it is still playing.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// The address of the next sample byte, once the buffer has run empty.
+    /// Returns it only once per fetch.
+    pub fn dma_request(&mut self) -> Option<u16> {
+        if self.buffer.is_some() || self.bytes_remaining == 0 || self.fetching {
+            return None;
+        }
+        self.fetching = true;
+        Some(self.current_address)
+    }
+
+    pub fn dma_complete(&mut self, data: u8) {
+        self.fetching = false;
+        self.buffer = Some(data);
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(data) => {
+                    self.silence = false;
+                    self.shift = data;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+}
+
+impl Default for Dmc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
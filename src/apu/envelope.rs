@@ -0,0 +1,53 @@
+/// # APU envelope https://www.nesdev.org/wiki/APU_Envelope
+///
+/// Shared by the pulse and noise channels. Either a constant volume, or a
+/// sawtooth that decays from 15 to 0 once per `volume + 1` quarter frames,
+/// starting over at 15 when the loop flag is set.
+#[derive(Default)]
+pub struct Envelope {
+    start: bool,
+    divider: u8,
+    decay: u8,
+    constant_volume: bool,
+    looping: bool,
+    volume: u8,
+}
+
+impl Envelope {
+    /// Bits 0-5 of $4000/$4004/$400C: `--LC VVVV`.
+    pub fn write_control(&mut self, data: u8) {
+        self.looping = data & 0b0010_0000 != 0;
+        self.constant_volume = data & 0b0001_0000 != 0;
+        self.volume = data & 0b0000_1111;
+    }
+
+    /// Writing the channel's length register restarts the envelope.
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
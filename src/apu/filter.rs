@@ -0,0 +1,68 @@
+use std::f32::consts::PI;
+
+/// First-order high-pass filter.
+pub struct HighPass {
+    alpha: f32,
+    prev_input: f32,
+    prev_output: f32,
+}
+
+impl HighPass {
+    pub fn new(cutoff: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        HighPass { alpha: rc / (rc + dt), prev_input: 0.0, prev_output: 0.0 }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.prev_output = self.alpha * (self.prev_output + input - self.prev_input);
+        self.prev_input = input;
+        self.prev_output
+    }
+}
+
+/// First-order low-pass filter.
+pub struct LowPass {
+    alpha: f32,
+    prev_output: f32,
+}
+
+impl LowPass {
+    pub fn new(cutoff: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        LowPass { alpha: dt / (rc + dt), prev_output: 0.0 }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.prev_output += self.alpha * (input - self.prev_output);
+        self.prev_output
+    }
+}
+
+/// # NES audio filters https://www.nesdev.org/wiki/APU_Mixer
+///
+/// What sits between the 2A03 and the RCA jack of a front-loader: a 90 Hz
+/// and a 440 Hz high-pass, which take out the DC offset of the mixer, and a
+/// 14 kHz low-pass.
+pub struct FilterChain {
+    high_pass_90: HighPass,
+    high_pass_440: HighPass,
+    low_pass_14k: LowPass,
+}
+
+impl FilterChain {
+    pub fn new(sample_rate: f32) -> Self {
+        FilterChain {
+            high_pass_90: HighPass::new(90.0, sample_rate),
+            high_pass_440: HighPass::new(440.0, sample_rate),
+            low_pass_14k: LowPass::new(14_000.0, sample_rate),
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output = self.high_pass_90.process(input);
+        let output = self.high_pass_440.process(output);
+        self.low_pass_14k.process(output)
+    }
+}
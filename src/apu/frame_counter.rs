@@ -0,0 +1,123 @@
+use crate::region::Region;
+
+/// CPU cycles after a reset at which the steps of the sequence fall. The
+/// last entry is where the sequence starts over.
+const STEPS_NTSC: [u16; 6] = [7457, 14913, 22371, 29828, 29829, 29830];
+const STEPS_PAL: [u16; 6] = [8313, 16627, 24939, 33252, 33253, 33254];
+const FIVE_STEP_END_NTSC: u16 = 37282;
+const FIVE_STEP_END_PAL: u16 = 41566;
+
+/// Which of the channels' slow units to clock on a cycle.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct FrameClock {
+    pub quarter: bool,
+    pub half: bool,
+}
+
+impl FrameClock {
+    // Half-frame steps clock the quarter-frame units too.
+    const QUARTER: FrameClock = FrameClock { quarter: true, half: false };
+    const HALF: FrameClock = FrameClock { quarter: true, half: true };
+}
+
+/// # APU frame counter https://www.nesdev.org/wiki/APU_Frame_Counter
+///
+/// $4017 `MI-- ----`: sequencer mode (0 = 4-step, 1 = 5-step) and IRQ
+/// inhibit.
+///
+/// ```text
+/// 4-step: Q . QH . Q . QH+IRQ    (~240 Hz quarter frames, ~60 Hz IRQ)
+/// 5-step: Q . QH . Q . -  . QH   (no IRQ)
+/// ```
+///
+/// A write restarts the sequence 3 or 4 CPU cycles later, depending on
+/// where in the APU cycle it lands; in 5-step mode the restart also clocks
+/// a quarter and a half frame right away.
+pub struct FrameCounter {
+    region: Region,
+    five_step: bool,
+    irq_inhibit: bool,
+    pub irq: bool,
+    cycle: u16,
+    // CPU cycles until a $4017 write takes effect.
+    reset_delay: u8,
+}
+
+impl FrameCounter {
+    pub fn new() -> Self {
+        FrameCounter {
+            region: Region::Ntsc,
+            five_step: false,
+            irq_inhibit: false,
+            irq: false,
+            cycle: 0,
+            reset_delay: 0,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// `odd_cycle` is whether the write lands in the second half of an APU
+    /// cycle.
+    pub fn write(&mut self, data: u8, odd_cycle: bool) {
+        self.five_step = data & 0b1000_0000 != 0;
+        self.irq_inhibit = data & 0b0100_0000 != 0;
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+        self.reset_delay = if odd_cycle { 4 } else { 3 };
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock(&mut self) -> FrameClock {
+        if self.reset_delay > 0 {
+            self.reset_delay -= 1;
+            if self.reset_delay == 0 {
+                self.cycle = 0;
+                if self.five_step {
+                    return FrameClock::HALF;
+                }
+                return FrameClock::default();
+            }
+        }
+
+        self.cycle += 1;
+        let (steps, five_step_end) = match self.region {
+            Region::Pal => (&STEPS_PAL, FIVE_STEP_END_PAL),
+            Region::Ntsc | Region::Dendy => (&STEPS_NTSC, FIVE_STEP_END_NTSC),
+        };
+        let cycle = self.cycle;
+        if self.five_step {
+            match cycle {
+                c if c == steps[0] || c == steps[2] => FrameClock::QUARTER,
+                c if c == steps[1] || c == five_step_end - 1 => FrameClock::HALF,
+                c if c == five_step_end => {
+                    self.cycle = 0;
+                    FrameClock::default()
+                }
+                _ => FrameClock::default(),
+            }
+        } else {
+            if cycle >= steps[3] && !self.irq_inhibit {
+                self.irq = true;
+            }
+            match cycle {
+                c if c == steps[0] || c == steps[2] => FrameClock::QUARTER,
+                c if c == steps[1] || c == steps[4] => FrameClock::HALF,
+                c if c == steps[5] => {
+                    self.cycle = 0;
+                    FrameClock::default()
+                }
+                _ => FrameClock::default(),
+            }
+        }
+    }
+}
+
+impl Default for FrameCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,21 @@
+lazy_static! {
+    /// Pulse 1 + pulse 2 output levels (0-30).
+    static ref PULSE_TABLE: Vec<f32> = (0..31)
+        .map(|n| if n == 0 { 0.0 } else { 95.52 / (8128.0 / n as f32 + 100.0) })
+        .collect();
+    /// 3 * triangle + 2 * noise + DMC output levels (0-202).
+    static ref TND_TABLE: Vec<f32> = (0..203)
+        .map(|n| if n == 0 { 0.0 } else { 163.67 / (24329.0 / n as f32 + 100.0) })
+        .collect();
+}
+
+/// # APU mixer https://www.nesdev.org/wiki/APU_Mixer
+///
+/// The channels are mixed by resistor networks whose output is not linear
+/// in the channel levels: the two pulses share one, triangle, noise and DMC
+/// another. The result lies between 0.0 and about 1.0.
+pub fn mix(pulse1: u8, pulse2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+    let pulse = PULSE_TABLE[(pulse1 + pulse2) as usize];
+    let tnd = TND_TABLE[3 * triangle as usize + 2 * noise as usize + dmc as usize];
+    pulse + tnd
+}
@@ -0,0 +1,188 @@
+pub mod dmc;
+pub mod envelope;
+pub mod filter;
+pub mod frame_counter;
+pub mod length_counter;
+pub mod mixer;
+pub mod noise;
+pub mod pulse;
+pub mod resampler;
+pub mod triangle;
+
+use crate::region::Region;
+use dmc::Dmc;
+use frame_counter::FrameCounter;
+use noise::Noise;
+use pulse::Pulse;
+use triangle::Triangle;
+
+/// # APU https://www.nesdev.org/wiki/APU
+///
+/// The 2A03's sound generator, clocked once per CPU cycle by the bus. The
+/// pulse timers run at half that rate, on every other CPU cycle; the
+/// triangle, noise and DMC timers count CPU cycles. The frame counter clocks
+/// the envelopes, length counters and sweeps at roughly 240 and 120 Hz.
+pub struct Apu {
+    pulses: [Pulse; 2],
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+    // The pulse timers are clocked on the second of each pair of CPU cycles.
+    odd_cycle: bool,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            pulses: [Pulse::new(1), Pulse::new(2)],
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            frame_counter: FrameCounter::new(),
+            odd_cycle: false,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.noise.set_region(region);
+        self.dmc.set_region(region);
+        self.frame_counter.set_region(region);
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulses[0].write(addr - 0x4000, data),
+            0x4004..=0x4007 => self.pulses[1].write(addr - 0x4004, data),
+            0x4008..=0x400B => self.triangle.write(addr - 0x4008, data),
+            0x400C..=0x400F => self.noise.write(addr - 0x400C, data),
+            0x4010..=0x4013 => self.dmc.write(addr - 0x4010, data),
+            0x4015 => {
+                self.pulses[0].length.set_enabled(data & 0b0_0001 != 0);
+                self.pulses[1].length.set_enabled(data & 0b0_0010 != 0);
+                self.triangle.length.set_enabled(data & 0b0_0100 != 0);
+                self.noise.length.set_enabled(data & 0b0_1000 != 0);
+                self.dmc.set_enabled(data & 0b1_0000 != 0);
+            }
+            0x4017 => self.frame_counter.write(data, self.odd_cycle),
+            _ => {}
+        }
+    }
+
+    /// $4015: which channels still have a non-zero length counter, whether
+    /// the DMC has bytes left to play, and the frame and DMC interrupts in
+    /// bits 6 and 7. Reading acknowledges the frame interrupt.
+    pub fn read_status(&mut self) -> u8 {
+        let frame_irq = std::mem::replace(&mut self.frame_counter.irq, false);
+        self.pulses[0].length.active() as u8
+            | (self.pulses[1].length.active() as u8) << 1
+            | (self.triangle.length.active() as u8) << 2
+            | (self.noise.length.active() as u8) << 3
+            | (self.dmc.active() as u8) << 4
+            | (frame_irq as u8) << 6
+            | (self.dmc.irq as u8) << 7
+    }
+
+    pub fn tick(&mut self) {
+        self.odd_cycle = !self.odd_cycle;
+        if !self.odd_cycle {
+            for pulse in self.pulses.iter_mut() {
+                pulse.clock_timer();
+            }
+        }
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        let clock = self.frame_counter.clock();
+        if clock.quarter {
+            self.clock_quarter_frame();
+        }
+        if clock.half {
+            self.clock_half_frame();
+        }
+    }
+
+    /// Clocks the envelopes and the triangle's linear counter.
+    pub fn clock_quarter_frame(&mut self) {
+        for pulse in self.pulses.iter_mut() {
+            pulse.clock_quarter_frame();
+        }
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    /// Clocks the length counters and sweep units.
+    pub fn clock_half_frame(&mut self) {
+        for pulse in self.pulses.iter_mut() {
+            pulse.clock_half_frame();
+        }
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    pub fn irq(&self) -> bool {
+        self.frame_counter.irq || self.dmc.irq
+    }
+
+    /// Address of the sample byte the DMC wants fetched, see `Dmc`.
+    pub fn dmc_dma_request(&mut self) -> Option<u16> {
+        self.dmc.dma_request()
+    }
+
+    pub fn dmc_dma_complete(&mut self, data: u8) {
+        self.dmc.dma_complete(data);
+    }
+
+    /// The mixed level of all five channels, between 0.0 and about 1.0.
+    pub fn output(&self) -> f32 {
+        mixer::mix(
+            self.pulses[0].output(),
+            self.pulses[1].output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        )
+    }
+
+    /// What each channel would put out on its own: pulse 1, pulse 2,
+    /// triangle, noise and DMC, each through the mixer with the others
+    /// silent.
+    pub fn channel_outputs(&self) -> [f32; 5] {
+        [
+            mixer::mix(self.pulses[0].output(), 0, 0, 0, 0),
+            mixer::mix(0, self.pulses[1].output(), 0, 0, 0),
+            mixer::mix(0, 0, self.triangle.output(), 0, 0),
+            mixer::mix(0, 0, 0, self.noise.output(), 0),
+            mixer::mix(0, 0, 0, 0, self.dmc.output()),
+        ]
+    }
+
+    /// Current 4-bit output of pulse channel 1 or 2.
+    pub fn pulse_output(&self, channel: usize) -> u8 {
+        self.pulses[channel - 1].output()
+    }
+
+    pub fn triangle_output(&self) -> u8 {
+        self.triangle.output()
+    }
+
+    pub fn noise_output(&self) -> u8 {
+        self.noise.output()
+    }
+
+    /// Current 7-bit DMC output level.
+    pub fn dmc_output(&self) -> u8 {
+        self.dmc.output()
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[path = "apu_tests.rs"]
+mod apu_tests;
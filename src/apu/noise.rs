@@ -0,0 +1,108 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+use crate::region::Region;
+
+/// Timer periods in CPU cycles. Dendy uses the NTSC table.
+#[rustfmt::skip]
+const PERIODS_NTSC: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+#[rustfmt::skip]
+const PERIODS_PAL: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+/// # APU noise channel https://www.nesdev.org/wiki/APU_Noise
+///
+/// | Register | Bits        | Function                                  |
+/// |----------|-------------|-------------------------------------------|
+/// | $400C    | `--LC VVVV` | length halt / envelope loop, volume       |
+/// | $400E    | `M--- PPPP` | mode, period index                        |
+/// | $400F    | `LLLL L---` | length counter load                       |
+///
+/// A 15-bit linear feedback shift register clocked by the timer. Mode 1
+/// takes the feedback from bit 6 instead of bit 1, which gives a 93-step
+/// metallic loop instead of a 32767-step hiss.
+pub struct Noise {
+    region: Region,
+    mode: bool,
+    pub timer_period: u16,
+    timer: u16,
+    pub shift: u16,
+    envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Self {
+        Noise {
+            region: Region::Ntsc,
+            mode: false,
+            timer_period: PERIODS_NTSC[0],
+            timer: 0,
+            shift: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.length.halt = data & 0b0010_0000 != 0;
+                self.envelope.write_control(data);
+            }
+            2 => {
+                self.mode = data & 0b1000_0000 != 0;
+                let periods = match self.region {
+                    Region::Pal => &PERIODS_PAL,
+                    Region::Ntsc | Region::Dendy => &PERIODS_NTSC,
+                };
+                self.timer_period = periods[(data & 0b1111) as usize];
+            }
+            3 => {
+                self.length.load(data);
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.shift & 1 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new()
+    }
+}
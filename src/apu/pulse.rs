@@ -0,0 +1,141 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+
+/// Output of each duty cycle over the 8 sequencer steps.
+pub const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// # APU sweep https://www.nesdev.org/wiki/APU_Sweep
+///
+/// Moves the pulse period up or down by `period >> shift` every `period + 1`
+/// half frames. Pulse 1 negates with the ones' complement, so it subtracts
+/// one more than pulse 2 does.
+#[derive(Default)]
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+}
+
+/// # APU pulse channel https://www.nesdev.org/wiki/APU_Pulse
+///
+/// | Register     | Bits        | Function                               |
+/// |--------------|-------------|----------------------------------------|
+/// | $4000/$4004  | `DDLC VVVV` | duty, length halt / envelope loop, volume |
+/// | $4001/$4005  | `EPPP NSSS` | sweep enable, period, negate, shift    |
+/// | $4002/$4006  | `TTTT TTTT` | timer low                              |
+/// | $4003/$4007  | `LLLL LTTT` | length counter load, timer high        |
+pub struct Pulse {
+    // Pulse 1 negates its sweep with the ones' complement.
+    ones_complement: bool,
+    duty: u8,
+    duty_step: u8,
+    pub timer_period: u16,
+    timer: u16,
+    sweep: Sweep,
+    envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Pulse {
+    pub fn new(channel: u8) -> Self {
+        Pulse {
+            ones_complement: channel == 1,
+            duty: 0,
+            duty_step: 0,
+            timer_period: 0,
+            timer: 0,
+            sweep: Sweep::default(),
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.duty = data >> 6;
+                self.length.halt = data & 0b0010_0000 != 0;
+                self.envelope.write_control(data);
+            }
+            1 => {
+                self.sweep.enabled = data & 0b1000_0000 != 0;
+                self.sweep.period = (data >> 4) & 0b111;
+                self.sweep.negate = data & 0b0000_1000 != 0;
+                self.sweep.shift = data & 0b111;
+                self.sweep.reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0b111) << 8);
+                self.length.load(data);
+                self.duty_step = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    /// Clocked every other CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.duty_step = (self.duty_step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+
+        let sweep = &self.sweep;
+        if sweep.divider == 0 && sweep.enabled && sweep.shift > 0 && !self.muted() {
+            self.timer_period = self.target_period();
+        }
+        let sweep = &mut self.sweep;
+        if sweep.divider == 0 || sweep.reload {
+            sweep.divider = sweep.period;
+            sweep.reload = false;
+        } else {
+            sweep.divider -= 1;
+        }
+    }
+
+    fn target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+        if self.sweep.negate {
+            self.timer_period
+                .saturating_sub(change + self.ones_complement as u16)
+        } else {
+            self.timer_period + change
+        }
+    }
+
+    /// The sweep unit mutes the channel when the period is too short, or
+    /// would overflow, even while the sweep itself is disabled.
+    fn muted(&self) -> bool {
+        self.timer_period < 8 || self.target_period() > 0x7FF
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active()
+            || self.muted()
+            || DUTY_TABLE[self.duty as usize][self.duty_step as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use crate::apu::filter::FilterChain;
+use std::f64::consts::PI;
+
+/// Kernel taps on either side of a step.
+const HALF_WIDTH: usize = 16;
+/// Sub-sample positions the kernel is tabulated for.
+const PHASES: usize = 64;
+/// Cutoff of the kernel, as a fraction of the output sample rate.
+const CUTOFF: f64 = 0.45;
+
+/// # Band-limited step synthesis http://www.slack.net/~ant/bl-synth/
+///
+/// Brings the mixer output from the CPU clock down to the output sample
+/// rate. The APU output is a sum of steps, so instead of filtering 1.79
+/// million samples a second, every change of level is drawn into the output
+/// as a band-limited step: a windowed-sinc impulse at its exact sub-sample
+/// position, added to a buffer of differences that is integrated on the way
+/// out. Nothing above `CUTOFF` of the output rate gets through, so
+/// ultrasonic tones do not alias into audible ones, and steps do not click.
+///
+/// Finished samples then go through the console's own filters.
+pub struct Resampler {
+    kernel: Vec<[f32; 2 * HALF_WIDTH]>,
+    clock_rate: f64,
+    sample_rate: f64,
+    // Correction of the output rate, see `set_rate_adjustment`.
+    adjustment: f64,
+    // Output samples per input clock.
+    ratio: f64,
+    // Position of the next clock in the output, relative to `diffs[0]`.
+    time: f64,
+    diffs: Vec<f32>,
+    integrator: f32,
+    level: f32,
+    filters: FilterChain,
+}
+
+impl Resampler {
+    pub fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        let kernel = (0..=PHASES)
+            .map(|phase| {
+                let offset = phase as f64 / PHASES as f64;
+                let mut taps = [0.0; 2 * HALF_WIDTH];
+                let values: Vec<f64> = (0..2 * HALF_WIDTH)
+                    .map(|k| {
+                        let x = k as f64 - (HALF_WIDTH - 1) as f64 - offset;
+                        windowed_sinc(x)
+                    })
+                    .collect();
+                // Every step has to add up to exactly its height.
+                let sum: f64 = values.iter().sum();
+                for (tap, value) in taps.iter_mut().zip(values) {
+                    *tap = (value / sum) as f32;
+                }
+                taps
+            })
+            .collect();
+        let sample_rate = sample_rate as f64;
+        Resampler {
+            kernel,
+            clock_rate,
+            sample_rate,
+            adjustment: 1.0,
+            ratio: sample_rate / clock_rate,
+            time: HALF_WIDTH as f64,
+            diffs: vec![0.0; 4 * HALF_WIDTH],
+            integrator: 0.0,
+            level: 0.0,
+            filters: FilterChain::new(sample_rate as f32),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate as u32
+    }
+
+    pub fn set_clock_rate(&mut self, clock_rate: f64) {
+        self.clock_rate = clock_rate;
+        self.update_ratio();
+    }
+
+    /// Produces `adjustment` times as many samples as the sample rate calls
+    /// for. The frontend nudges this around 1.0 to keep its audio buffer
+    /// from running dry or overflowing when the emulation runs a little fast
+    /// or slow against the sound card.
+    pub fn set_rate_adjustment(&mut self, adjustment: f64) {
+        self.adjustment = adjustment;
+        self.update_ratio();
+    }
+
+    fn update_ratio(&mut self) {
+        self.ratio = self.sample_rate * self.adjustment / self.clock_rate;
+    }
+
+    /// Takes the level of the mixer output for one clock.
+    pub fn clock(&mut self, level: f32) {
+        if level != self.level {
+            self.add_step(level - self.level);
+            self.level = level;
+        }
+        self.time += self.ratio;
+    }
+
+    fn add_step(&mut self, delta: f32) {
+        let start = self.time.floor();
+        let phase = ((self.time - start) * PHASES as f64).round() as usize;
+        let start = start as usize - (HALF_WIDTH - 1);
+        if self.diffs.len() < start + 2 * HALF_WIDTH {
+            self.diffs.resize(start + 4 * HALF_WIDTH, 0.0);
+        }
+        for (diff, tap) in self.diffs[start..].iter_mut().zip(self.kernel[phase].iter()) {
+            *diff += delta * tap;
+        }
+    }
+
+    /// Moves the samples no future step can change anymore into `out`.
+    pub fn read_samples(&mut self, out: &mut Vec<f32>) {
+        let ready = (self.time.floor() as usize).saturating_sub(HALF_WIDTH - 1);
+        if self.diffs.len() < ready + 2 * HALF_WIDTH {
+            self.diffs.resize(ready + 2 * HALF_WIDTH, 0.0);
+        }
+        for &diff in &self.diffs[..ready] {
+            self.integrator += diff;
+            out.push(self.filters.process(self.integrator));
+        }
+        self.diffs.drain(..ready);
+        self.time -= ready as f64;
+    }
+}
+
+/// Blackman-windowed sinc at `x` output samples from its center.
+fn windowed_sinc(x: f64) -> f64 {
+    let width = HALF_WIDTH as f64;
+    if x.abs() >= width {
+        return 0.0;
+    }
+    let sinc = if x == 0.0 {
+        1.0
+    } else {
+        (PI * 2.0 * CUTOFF * x).sin() / (PI * 2.0 * CUTOFF * x)
+    };
+    let window = 0.42 + 0.5 * (PI * x / width).cos() + 0.08 * (2.0 * PI * x / width).cos();
+    sinc * window
+}
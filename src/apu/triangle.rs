@@ -0,0 +1,82 @@
+use crate::apu::length_counter::LengthCounter;
+
+#[rustfmt::skip]
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10,  9,  8,  7,  6,  5,  4,  3,  2,  1,  0,
+     0,  1,  2,  3,  4,  5,  6,  7,  8,  9, 10, 11, 12, 13, 14, 15,
+];
+
+/// # APU triangle channel https://www.nesdev.org/wiki/APU_Triangle
+///
+/// | Register | Bits        | Function                                   |
+/// |----------|-------------|--------------------------------------------|
+/// | $4008    | `CRRR RRRR` | length halt / linear control, linear reload |
+/// | $400A    | `TTTT TTTT` | timer low                                  |
+/// | $400B    | `LLLL LTTT` | length counter load, timer high            |
+///
+/// The timer runs at the CPU clock, twice the rate of the other channels,
+/// and the sequencer only steps while both the linear counter and the
+/// length counter are non-zero.
+#[derive(Default)]
+pub struct Triangle {
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    timer_period: u16,
+    timer: u16,
+    step: u8,
+    pub length: LengthCounter,
+}
+
+impl Triangle {
+    pub fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.control = data & 0b1000_0000 != 0;
+                self.length.halt = self.control;
+                self.linear_reload_value = data & 0b0111_1111;
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0b111) << 8);
+                self.length.load(data);
+                self.linear_reload = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Clocked every CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length.active() {
+                self.step = (self.step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length.clock();
+    }
+
+    /// Silencing the triangle only stops the sequencer, so the output holds
+    /// whatever step it stopped on.
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.step as usize]
+    }
+}
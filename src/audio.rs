@@ -0,0 +1,82 @@
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+use std::collections::VecDeque;
+
+const SAMPLE_RATE: i32 = 48000;
+/// Samples SDL asks for per callback.
+const DEVICE_SAMPLES: u16 = 1024;
+/// Buffer fill the rate control steers towards: three callbacks, 64 ms.
+const TARGET_FILL: usize = 3 * DEVICE_SAMPLES as usize;
+/// Past this the oldest samples are dropped rather than building up delay.
+const MAX_FILL: usize = 4 * TARGET_FILL;
+/// Largest correction of the resampling ratio. Half a percent moves the
+/// pitch by less than a tenth of a semitone.
+pub const MAX_RATE_DELTA: f64 = 0.005;
+
+/// Samples waiting for the sound card. SDL calls `callback` from its own
+/// thread, under the device lock.
+struct RingBuffer {
+    samples: VecDeque<f32>,
+    last: f32,
+}
+
+impl AudioCallback for RingBuffer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            // On an underrun hold the last level instead of dropping to 0,
+            // which would click.
+            if let Some(next) = self.samples.pop_front() {
+                self.last = next;
+            }
+            *sample = self.last;
+        }
+    }
+}
+
+/// Plays the resampled APU output through SDL.
+///
+/// The emulator is paced by vsync or by its own frame timer, neither of
+/// which runs at exactly the rate the sound card drains samples. Instead of letting the
+/// buffer run dry or pile up, `rate_adjustment` tells the resampler to make
+/// slightly more samples while the buffer is below its target and slightly
+/// fewer while it is above.
+pub struct AudioOutput {
+    device: AudioDevice<RingBuffer>,
+}
+
+impl AudioOutput {
+    pub fn new(audio: &AudioSubsystem) -> Result<Self, String> {
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: Some(DEVICE_SAMPLES),
+        };
+        let device = audio.open_playback(None, &desired, |_| RingBuffer {
+            samples: VecDeque::with_capacity(MAX_FILL),
+            last: 0.0,
+        })?;
+        device.resume();
+        Ok(AudioOutput { device })
+    }
+
+    /// The rate SDL actually opened the device with.
+    pub fn sample_rate(&self) -> u32 {
+        self.device.spec().freq as u32
+    }
+
+    pub fn push(&mut self, samples: &[f32]) {
+        let mut buffer = self.device.lock();
+        buffer.samples.extend(samples);
+        let excess = buffer.samples.len().saturating_sub(MAX_FILL);
+        buffer.samples.drain(..excess);
+    }
+
+    /// The factor to pass to `Bus::adjust_audio_rate` for the current fill.
+    pub fn rate_adjustment(&mut self) -> f64 {
+        let fill = self.device.lock().samples.len() as f64;
+        let error = (TARGET_FILL as f64 - fill) / TARGET_FILL as f64;
+        1.0 + MAX_RATE_DELTA * error.clamp(-1.0, 1.0)
+    }
+}
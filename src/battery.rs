@@ -0,0 +1,83 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::mapper::Mapper;
+
+/// How often PRG RAM is compared against the file and written out if changed.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Keeps the PRG RAM of a battery-backed cartridge in a `.sav` file next to
+/// the ROM. Writes go to a temporary file that is renamed over the save, so a
+/// crash mid-write leaves the previous save intact.
+pub struct BatterySave {
+    path: PathBuf,
+    saved: Vec<u8>,
+    last_flush: Instant,
+}
+
+impl BatterySave {
+    pub fn new(rom_path: &Path) -> Self {
+        BatterySave {
+            path: rom_path.with_extension("sav"),
+            saved: Vec::new(),
+            last_flush: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Fills the cartridge's PRG RAM from the save file, if there is one.
+    /// Without one the RAM as it is counts as saved, so nothing is written
+    /// until the game changes it.
+    pub fn load(&mut self, mapper: &mut dyn Mapper) -> io::Result<()> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.saved = mapper.prg_ram().to_vec();
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        let ram = mapper.prg_ram_mut();
+        let len = ram.len().min(data.len());
+        ram[..len].copy_from_slice(&data[..len]);
+        self.saved = ram.to_vec();
+        Ok(())
+    }
+
+    /// Writes PRG RAM out if it changed since the last flush.
+    pub fn flush(&mut self, mapper: &dyn Mapper) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        let ram = mapper.prg_ram();
+        if ram.is_empty() || ram == &self.saved[..] {
+            return Ok(());
+        }
+
+        let tmp_path = self.path.with_extension("sav.tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(ram)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        self.saved = ram.to_vec();
+        Ok(())
+    }
+
+    /// Called from the frame loop; flushes every `FLUSH_INTERVAL`.
+    pub fn tick(&mut self, mapper: &dyn Mapper) -> io::Result<()> {
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush(mapper)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "battery_tests.rs"]
+mod battery_tests;
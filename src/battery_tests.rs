@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod test {
+    use crate::battery::*;
+    use crate::cartridge::Rom;
+    use crate::mapper;
+
+    fn mmc3() -> Box<dyn mapper::Mapper> {
+        mapper::from_rom(Rom {
+            battery: true,
+            ..Rom::test_rom(4, vec![0; 0x8000], vec![0; 0x2000])
+        })
+        .unwrap()
+    }
+
+    fn rom_path(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("emu_battery_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("game.nes")
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let path = rom_path("round_trip");
+        let mut cartridge = mmc3();
+        cartridge.write_prg(0x6000, 0x12);
+        cartridge.write_prg(0x7FFF, 0x34);
+
+        let mut save = BatterySave::new(&path);
+        save.flush(cartridge.as_ref()).unwrap();
+        assert_eq!(save.path(), path.with_extension("sav"));
+        assert!(!path.with_extension("sav.tmp").exists());
+
+        let mut reloaded = mmc3();
+        BatterySave::new(&path).load(reloaded.as_mut()).unwrap();
+        assert_eq!(reloaded.read_prg(0x6000), 0x12);
+        assert_eq!(reloaded.read_prg(0x7FFF), 0x34);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_missing_save_is_not_an_error() {
+        let path = rom_path("missing");
+        let mut cartridge = mmc3();
+        assert!(BatterySave::new(&path).load(cartridge.as_mut()).is_ok());
+        assert_eq!(cartridge.read_prg(0x6000), 0);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_unchanged_ram_is_not_written() {
+        let path = rom_path("unchanged");
+        let mut cartridge = mmc3();
+        let mut save = BatterySave::new(&path);
+        save.load(cartridge.as_mut()).unwrap();
+        save.tick(cartridge.as_ref()).unwrap();
+        save.flush(cartridge.as_ref()).unwrap();
+        assert!(!save.path().exists());
+
+        cartridge.write_prg(0x6000, 0x12);
+        save.flush(cartridge.as_ref()).unwrap();
+        assert!(save.path().exists());
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
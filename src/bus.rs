@@ -0,0 +1,334 @@
+use crate::apu::resampler::Resampler;
+use crate::apu::Apu;
+use crate::cpu::Mem;
+use crate::joypad::Joypad;
+use crate::mapper::{Mapper, SharedMapper};
+use crate::ppu::NesPPU;
+use crate::region::Region;
+use std::cell::{RefCell, RefMut};
+use std::rc::Rc;
+
+//  _______________ $10000  _______________
+// | PRG-ROM       |       |               |
+// | Upper Bank    |       |               |
+// |_ _ _ _ _ _ _ _| $C000 | PRG-ROM       |
+// | PRG-ROM       |       |               |
+// | Lower Bank    |       |               |
+// |_______________| $8000 |_______________|
+// | SRAM          |       | SRAM          |
+// |_______________| $6000 |_______________|
+// | Expansion ROM |       | Expansion ROM |
+// |_______________| $4020 |_______________|
+// | I/O Registers |       |               |
+// |_ _ _ _ _ _ _ _| $4000 |               |
+// | Mirrors       |       | I/O Registers |
+// | $2000-$2007   |       |               |
+// |_ _ _ _ _ _ _ _| $2008 |               |
+// | I/O Registers |       |               |
+// |_______________| $2000 |_______________|
+// | Mirrors       |       |               |
+// | $0000-$07FF   |       |               |
+// |_ _ _ _ _ _ _ _| $0800 |               |
+// | RAM           |       | RAM           |
+// |_ _ _ _ _ _ _ _| $0200 |               |
+// | Stack         |       |               |
+// |_ _ _ _ _ _ _ _| $0100 |               |
+// | Zero Page     |       |               |
+// |_______________| $0000 |_______________|
+
+const RAM: u16 = 0x0000;
+const RAM_MIRRORS_END: u16 = 0x1FFF;
+const PPU_REGISTERS: u16 = 0x2000;
+const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const CARTRIDGE_SPACE: u16 = 0x4020;
+/// The controller ports only drive the low bits; the rest is open bus, which
+/// still holds the high byte of the address.
+const JOYPAD_OPEN_BUS: u8 = 0x40;
+
+/// The tracks `read_channel_audio` produces, in order.
+pub const AUDIO_CHANNELS: [&str; 6] = ["pulse 1", "pulse 2", "triangle", "noise", "DMC", "expansion"];
+
+pub struct Bus {
+    cpu_vram: [u8; 2048],
+    cartridge: Option<SharedMapper>,
+    pub ppu: NesPPU,
+    pub apu: Apu,
+    pub joypad1: Joypad,
+    pub joypad2: Joypad,
+    // Mixer output at the sample rate, once audio is enabled.
+    audio: Option<Resampler>,
+    // One per entry of `AUDIO_CHANNELS`, when enabled.
+    channel_audio: Vec<Resampler>,
+    cycles: usize,
+    region: Region,
+    // Left-over fraction of a PPU dot on PAL, in CPU cycles.
+    dot_remainder: u16,
+    // Page written to $4014, copied into OAM once the instruction is done.
+    oam_dma: Option<u8>,
+    oam_dma_running: bool,
+    // Without a cartridge the upper address space is plain memory, which is
+    // what Easy6502-style programs such as snake expect.
+    open_memory: Vec<u8>,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Bus {
+            cpu_vram: [0; 2048],
+            cartridge: None,
+            ppu: NesPPU::new_empty_rom(),
+            apu: Apu::new(),
+            joypad1: Joypad::new(),
+            joypad2: Joypad::new(),
+            audio: None,
+            channel_audio: Vec::new(),
+            cycles: 0,
+            region: Region::Ntsc,
+            dot_remainder: 0,
+            oam_dma: None,
+            oam_dma_running: false,
+            open_memory: vec![0; 0x10000 - CARTRIDGE_SPACE as usize],
+        }
+    }
+
+    pub fn with_cartridge(cartridge: Box<dyn Mapper>) -> Self {
+        let cartridge: SharedMapper = Rc::new(RefCell::new(cartridge));
+        Bus {
+            cpu_vram: [0; 2048],
+            ppu: NesPPU::new(Some(cartridge.clone())),
+            apu: Apu::new(),
+            joypad1: Joypad::new(),
+            joypad2: Joypad::new(),
+            audio: None,
+            channel_audio: Vec::new(),
+            cycles: 0,
+            region: Region::Ntsc,
+            dot_remainder: 0,
+            oam_dma: None,
+            oam_dma_running: false,
+            cartridge: Some(cartridge),
+            open_memory: Vec::new(),
+        }
+    }
+}
+
+impl Bus {
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.region = region;
+        self.apu.set_region(region);
+        let clock_rate = region.cpu_clock_hz();
+        for audio in self.audio.iter_mut().chain(self.channel_audio.iter_mut()) {
+            audio.set_clock_rate(clock_rate);
+        }
+    }
+
+    /// Starts resampling the APU and expansion audio to `sample_rate`.
+    pub fn enable_audio(&mut self, sample_rate: u32) {
+        self.audio = Some(Resampler::new(self.region.cpu_clock_hz(), sample_rate));
+    }
+
+    /// Also resamples every channel on its own, for `read_channel_audio`.
+    /// Call together with `enable_audio`, so the tracks line up with the mix.
+    pub fn enable_channel_audio(&mut self, sample_rate: u32) {
+        let clock_rate = self.region.cpu_clock_hz();
+        self.channel_audio = AUDIO_CHANNELS
+            .iter()
+            .map(|_| Resampler::new(clock_rate, sample_rate))
+            .collect();
+    }
+
+    /// See `Resampler::set_rate_adjustment`.
+    pub fn adjust_audio_rate(&mut self, adjustment: f64) {
+        for audio in self.audio.iter_mut().chain(self.channel_audio.iter_mut()) {
+            audio.set_rate_adjustment(adjustment);
+        }
+    }
+
+    /// Moves the audio samples produced so far into `out`.
+    pub fn read_audio(&mut self, out: &mut Vec<f32>) {
+        if let Some(audio) = self.audio.as_mut() {
+            audio.read_samples(out);
+        }
+    }
+
+    /// Moves the per-channel samples produced so far into `out`, one frame
+    /// of `AUDIO_CHANNELS.len()` samples at a time.
+    pub fn read_channel_audio(&mut self, out: &mut Vec<f32>) {
+        let tracks: Vec<Vec<f32>> = self
+            .channel_audio
+            .iter_mut()
+            .map(|audio| {
+                let mut track = Vec::new();
+                audio.read_samples(&mut track);
+                track
+            })
+            .collect();
+        let frames = tracks.iter().map(|track| track.len()).min().unwrap_or(0);
+        for frame in 0..frames {
+            out.extend(tracks.iter().map(|track| track[frame]));
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn tick(&mut self, cycles: u8) {
+        self.cycles += cycles as usize;
+        let (dots, per_cycles) = self.region.ppu_dots();
+        // One CPU cycle at a time, so boards that watch the PPU's fetches
+        // from their CPU clock see them interleaved as on hardware.
+        for _ in 0..cycles {
+            let total = dots + self.dot_remainder;
+            self.dot_remainder = total % per_cycles;
+            self.ppu.tick((total / per_cycles) as u8);
+            self.apu.tick();
+            let mut cartridge = self.cartridge.as_ref().map(|cartridge| cartridge.borrow_mut());
+            if let Some(cartridge) = cartridge.as_mut() {
+                cartridge.cpu_clock();
+            }
+            if self.audio.is_none() && self.channel_audio.is_empty() {
+                continue;
+            }
+            let expansion = cartridge.as_ref().map_or(0.0, |cartridge| cartridge.audio_output());
+            drop(cartridge);
+            if let Some(audio) = self.audio.as_mut() {
+                audio.clock(self.apu.output() + expansion);
+            }
+            if !self.channel_audio.is_empty() {
+                let levels = self.apu.channel_outputs();
+                let levels = levels.iter().chain(std::iter::once(&expansion));
+                for (audio, &level) in self.channel_audio.iter_mut().zip(levels) {
+                    audio.clock(level);
+                }
+            }
+        }
+        self.run_dmc_dma();
+    }
+
+    /// # DMC DMA https://www.nesdev.org/wiki/DMA#DMC_DMA
+    ///
+    /// Fetches the next DMC sample byte as soon as the channel's buffer runs
+    /// empty. The CPU is halted for 4 cycles: halt, dummy, alignment and the
+    /// read itself. During OAM DMA the CPU is already halted and the read
+    /// slots in between the OAM transfers, taking 2.
+    fn run_dmc_dma(&mut self) {
+        if let Some(addr) = self.apu.dmc_dma_request() {
+            self.tick(if self.oam_dma_running { 1 } else { 3 });
+            let data = self.mem_read(addr);
+            self.tick(1);
+            self.apu.dmc_dma_complete(data);
+        }
+    }
+
+    /// # OAM DMA https://www.nesdev.org/wiki/PPU_registers#OAMDMA
+    ///
+    /// Copies the page written to $4014 into OAM while the CPU is halted:
+    /// one cycle to halt, one more to line up with a read cycle if needed,
+    /// then 256 read/write pairs, 513 or 514 cycles in total, plus 2 for each
+    /// DMC sample fetch that lands in between.
+    pub fn run_oam_dma(&mut self) {
+        let page = match self.oam_dma.take() {
+            Some(page) => page,
+            None => return,
+        };
+        self.oam_dma_running = true;
+        self.tick(if self.cycles % 2 == 1 { 2 } else { 1 });
+        for offset in 0..=0xff {
+            let data = self.mem_read((page as u16) << 8 | offset);
+            self.tick(1);
+            self.ppu.write_to_oam_data(data);
+            self.tick(1);
+        }
+        self.oam_dma_running = false;
+    }
+
+    /// CPU cycles since power-on.
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn cartridge(&self) -> Option<RefMut<'_, Box<dyn Mapper>>> {
+        self.cartridge.as_ref().map(|cartridge| cartridge.borrow_mut())
+    }
+
+    pub fn poll_nmi_status(&mut self) -> bool {
+        self.ppu.poll_nmi_interrupt()
+    }
+
+    pub fn poll_irq(&self) -> bool {
+        self.apu.irq() || self.cartridge.as_ref().is_some_and(|cartridge| cartridge.borrow().irq())
+    }
+}
+
+impl Mem for Bus {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            RAM..=RAM_MIRRORS_END => {
+                let mirror_down_addr = addr & 0b0000_0111_1111_1111;
+                self.cpu_vram[mirror_down_addr as usize]
+            }
+            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => self.ppu.open_bus(),
+            0x2002 => self.ppu.read_status(),
+            0x2004 => self.ppu.read_oam_data(),
+            0x2007 => self.ppu.read_data(),
+            0x2008..=PPU_REGISTERS_MIRRORS_END => {
+                let mirror_down_addr = addr & 0b0010_0000_0000_0111;
+                self.mem_read(mirror_down_addr)
+            }
+            0x4015 => self.apu.read_status(),
+            0x4016 => JOYPAD_OPEN_BUS | self.joypad1.read(),
+            0x4017 => JOYPAD_OPEN_BUS | self.joypad2.read(),
+            CARTRIDGE_SPACE..=0xFFFF => match &self.cartridge {
+                Some(cartridge) => cartridge.borrow_mut().read_prg(addr),
+                None => self.open_memory[(addr - CARTRIDGE_SPACE) as usize],
+            },
+            // Nothing answers here, so the bus still holds the high byte of
+            // the address, the same open bus the controller ports leave.
+            _ => (addr >> 8) as u8,
+        }
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            RAM..=RAM_MIRRORS_END => {
+                let mirror_down_addr = addr & 0b0000_0111_1111_1111;
+                self.cpu_vram[mirror_down_addr as usize] = data;
+            }
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
+                if let Some(cartridge) = &self.cartridge {
+                    cartridge.borrow_mut().ppu_register_write(addr, data);
+                }
+                match addr & 0b0010_0000_0000_0111 {
+                    0x2000 => self.ppu.write_to_ctl(data),
+                    0x2001 => self.ppu.write_to_mask(data),
+                    0x2002 => {} // read-only
+                    0x2003 => self.ppu.write_to_oam_addr(data),
+                    0x2004 => self.ppu.write_to_oam_data(data),
+                    0x2005 => self.ppu.write_to_scroll(data),
+                    0x2006 => self.ppu.write_to_ppu_addr(data),
+                    _ => self.ppu.write_to_data(data),
+                }
+            }
+            0x4000..=0x4013 | 0x4015 | 0x4017 => self.apu.write_register(addr, data),
+            0x4014 => self.oam_dma = Some(data),
+            // One strobe line goes to both ports.
+            0x4016 => {
+                self.joypad1.write(data);
+                self.joypad2.write(data);
+            }
+            CARTRIDGE_SPACE..=0xFFFF => match &self.cartridge {
+                Some(cartridge) => cartridge.borrow_mut().write_prg(addr, data),
+                None => self.open_memory[(addr - CARTRIDGE_SPACE) as usize] = data,
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
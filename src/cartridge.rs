@@ -0,0 +1,147 @@
+use crate::region::Region;
+
+const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+pub const PRG_ROM_PAGE_SIZE: usize = 16384;
+pub const CHR_ROM_PAGE_SIZE: usize = 8192;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Mirroring {
+    Vertical,
+    Horizontal,
+    FourScreen,
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+/// # iNES / NES 2.0 cartridge image https://www.nesdev.org/wiki/NES_2.0
+///
+/// Byte 6 and 7 of the header hold the mapper number, mirroring and battery
+/// flags. When bits 2-3 of byte 7 are `0b10` the header is NES 2.0 and bytes
+/// 8-12 additionally carry the submapper, RAM sizes and timing region.
+pub struct Rom {
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub screen_mirroring: Mirroring,
+    pub battery: bool,
+    pub prg_ram_size: usize,
+    pub chr_ram_size: usize,
+    pub region: Region,
+}
+
+impl Rom {
+    pub fn new(raw: &[u8]) -> Result<Rom, String> {
+        if raw.len() < HEADER_SIZE || raw[0..4] != NES_TAG {
+            return Err("File is not in iNES file format".to_string());
+        }
+
+        let nes2 = raw[7] & 0b0000_1100 == 0b0000_1000;
+
+        let mut mapper = ((raw[7] & 0b1111_0000) | (raw[6] >> 4)) as u16;
+        let mut submapper = 0;
+        if nes2 {
+            mapper |= ((raw[8] & 0b0000_1111) as u16) << 8;
+            submapper = raw[8] >> 4;
+        }
+
+        let four_screen = raw[6] & 0b1000 != 0;
+        let vertical_mirroring = raw[6] & 0b1 != 0;
+        let screen_mirroring = match (four_screen, vertical_mirroring) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+        let battery = raw[6] & 0b10 != 0;
+
+        let mut prg_rom_pages = raw[4] as usize;
+        let mut chr_rom_pages = raw[5] as usize;
+        if nes2 {
+            prg_rom_pages |= ((raw[9] & 0b0000_1111) as usize) << 8;
+            chr_rom_pages |= ((raw[9] >> 4) as usize) << 8;
+        }
+        if prg_rom_pages == 0 {
+            return Err("The image has no PRG ROM".to_string());
+        }
+        let prg_rom_size = prg_rom_pages * PRG_ROM_PAGE_SIZE;
+        let chr_rom_size = chr_rom_pages * CHR_ROM_PAGE_SIZE;
+
+        let (prg_ram_size, chr_ram_size) = if nes2 {
+            (
+                shift_count_size(raw[10] & 0x0f) + shift_count_size(raw[10] >> 4),
+                shift_count_size(raw[11] & 0x0f) + shift_count_size(raw[11] >> 4),
+            )
+        } else {
+            let chr_ram_size = if chr_rom_size == 0 { CHR_ROM_PAGE_SIZE } else { 0 };
+            (0x2000, chr_ram_size)
+        };
+
+        // Multi-region images run as NTSC. iNES 1.0 only has a rarely set PAL
+        // flag in byte 9.
+        let region = if nes2 {
+            match raw[12] & 0b11 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc,
+            }
+        } else if raw[9] & 1 != 0 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        };
+
+        let skip_trainer = raw[6] & 0b100 != 0;
+        let prg_rom_start = HEADER_SIZE + if skip_trainer { TRAINER_SIZE } else { 0 };
+        let chr_rom_start = prg_rom_start + prg_rom_size;
+
+        if raw.len() < chr_rom_start + chr_rom_size {
+            return Err("File is shorter than its header claims".to_string());
+        }
+
+        Ok(Rom {
+            prg_rom: raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
+            chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
+            mapper,
+            submapper,
+            screen_mirroring,
+            battery,
+            prg_ram_size,
+            chr_ram_size,
+            region,
+        })
+    }
+}
+
+#[cfg(test)]
+impl Rom {
+    /// A cartridge for tests: vertical mirroring, 8 KB of PRG RAM, and 8 KB
+    /// of CHR RAM when `chr_rom` is empty.
+    pub fn test_rom(mapper: u16, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Rom {
+        Rom {
+            chr_ram_size: if chr_rom.is_empty() { 0x2000 } else { 0 },
+            prg_rom,
+            chr_rom,
+            mapper,
+            submapper: 0,
+            screen_mirroring: Mirroring::Vertical,
+            battery: false,
+            prg_ram_size: 0x2000,
+            region: Region::Ntsc,
+        }
+    }
+}
+
+/// NES 2.0 stores RAM sizes as a shift count: 64 << n bytes, 0 meaning none.
+fn shift_count_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+#[cfg(test)]
+#[path = "cartridge_tests.rs"]
+mod cartridge_tests;
@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod test {
+    use crate::cartridge::*;
+    use crate::region::Region;
+
+    struct TestRom {
+        header: Vec<u8>,
+        trainer: Option<Vec<u8>>,
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+    }
+
+    fn create_rom(rom: TestRom) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend(&rom.header);
+        if let Some(trainer) = rom.trainer {
+            result.extend(trainer);
+        }
+        result.extend(&rom.prg_rom);
+        result.extend(&rom.chr_rom);
+        result
+    }
+
+    #[test]
+    fn test_ines_header() {
+        let raw = create_rom(TestRom {
+            header: vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31, 00, 00, 00, 00, 00, 00, 00, 00, 00],
+            trainer: None,
+            prg_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        let rom = Rom::new(&raw).unwrap();
+
+        assert_eq!(rom.chr_rom, vec![2; CHR_ROM_PAGE_SIZE]);
+        assert_eq!(rom.prg_rom, vec![1; 2 * PRG_ROM_PAGE_SIZE]);
+        assert_eq!(rom.mapper, 3);
+        assert_eq!(rom.screen_mirroring, Mirroring::Vertical);
+        assert_eq!(rom.chr_ram_size, 0);
+    }
+
+    #[test]
+    fn test_with_trainer() {
+        let raw = create_rom(TestRom {
+            header: vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x31 | 0b100, 00, 00, 00, 00, 00, 00, 00, 00, 00],
+            trainer: Some(vec![0; 512]),
+            prg_rom: vec![1; 2 * PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        let rom = Rom::new(&raw).unwrap();
+
+        assert_eq!(rom.chr_rom, vec![2; CHR_ROM_PAGE_SIZE]);
+        assert_eq!(rom.prg_rom, vec![1; 2 * PRG_ROM_PAGE_SIZE]);
+    }
+
+    #[test]
+    fn test_nes2_header() {
+        let raw = create_rom(TestRom {
+            header: vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x72, 0x08, 0x20, 00, 0x07, 0x07, 00, 00, 00, 00],
+            trainer: None,
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![],
+        });
+
+        let rom = Rom::new(&raw).unwrap();
+
+        assert_eq!(rom.mapper, 7);
+        assert_eq!(rom.submapper, 2);
+        assert!(rom.battery);
+        assert_eq!(rom.prg_ram_size, 0x2000);
+        assert_eq!(rom.chr_ram_size, 0x2000);
+        assert_eq!(rom.screen_mirroring, Mirroring::Horizontal);
+        assert_eq!(rom.region, Region::Ntsc);
+    }
+
+    #[test]
+    fn test_region() {
+        let mut header = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x00, 0x08, 00, 00, 00, 00, 0x03, 00, 00, 00];
+        let rom = |header: &Vec<u8>| {
+            Rom::new(&create_rom(TestRom {
+                header: header.clone(),
+                trainer: None,
+                prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+                chr_rom: vec![],
+            }))
+            .unwrap()
+        };
+        assert_eq!(rom(&header).region, Region::Dendy);
+        header[12] = 0x01;
+        assert_eq!(rom(&header).region, Region::Pal);
+        header[12] = 0x02;
+        assert_eq!(rom(&header).region, Region::Ntsc);
+
+        // iNES 1.0 PAL flag.
+        header[7] = 0;
+        header[9] = 1;
+        assert_eq!(rom(&header).region, Region::Pal);
+    }
+
+    #[test]
+    fn test_not_ines() {
+        let raw = create_rom(TestRom {
+            header: vec![0x4E, 0x45, 0x53, 0x00, 0x01, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00],
+            trainer: None,
+            prg_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        assert!(Rom::new(&raw).is_err());
+    }
+
+    #[test]
+    fn test_no_prg_rom() {
+        let raw = create_rom(TestRom {
+            header: vec![0x4E, 0x45, 0x53, 0x1A, 0x00, 0x01, 0x00, 00, 00, 00, 00, 00, 00, 00, 00, 00],
+            trainer: None,
+            prg_rom: vec![],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        assert!(Rom::new(&raw).is_err());
+    }
+}
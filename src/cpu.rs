@@ -1,6 +1,7 @@
 #[allow(unused_imports)]
 use std::{collections::{btree_map::Values, HashMap}, hash::Hash};
 use crate::opcodes;
+use crate::bus::Bus;
 
 bitflags! {
     /// # Status Register (P) http://wiki.nesdev.com/w/index.php/Status_flags
@@ -27,6 +28,36 @@ bitflags! {
     }
 }
 
+mod interrupt {
+    #[derive(PartialEq, Eq)]
+    pub enum InterruptType {
+        Nmi,
+        Irq,
+    }
+
+    #[derive(PartialEq, Eq)]
+    pub(super) struct Interrupt {
+        pub(super) itype: InterruptType,
+        pub(super) vector_addr: u16,
+        pub(super) b_flag_mask: u8,
+        pub(super) cpu_cycles: u8,
+    }
+
+    pub(super) const NMI: Interrupt = Interrupt {
+        itype: InterruptType::Nmi,
+        vector_addr: 0xfffa,
+        b_flag_mask: 0b00100000,
+        cpu_cycles: 7,
+    };
+
+    pub(super) const IRQ: Interrupt = Interrupt {
+        itype: InterruptType::Irq,
+        vector_addr: 0xfffe,
+        b_flag_mask: 0b00100000,
+        cpu_cycles: 7,
+    };
+}
+
 #[allow(dead_code)]
 const STACK: u16 = 0x0100;
 const STACK_RESET: u8 = 0xfd;
@@ -38,7 +69,10 @@ pub struct CPU {
     pub status: CpuFlags,
     pub stack_pointer:u8,
     pub program_counter: u16,
-    memory: [u8; 0xFFFF]
+    pub bus: Bus,
+    // Set while running a read instruction that spends an extra cycle when
+    // indexing carries into the high byte; cleared once that cycle is spent.
+    page_cross_penalty: bool,
  }
 
  #[derive(Debug)]
@@ -58,11 +92,11 @@ pub struct CPU {
  }
   
  pub trait Mem {
-    fn mem_read(&self, addr: u16) -> u8;
+    fn mem_read(&mut self, addr: u16) -> u8;
 
     fn mem_write(&mut self, addr: u16, data: u8);
 
-    fn mem_read_u16(&self, pos: u16) -> u16 {
+    fn mem_read_u16(&mut self, pos: u16) -> u16 {
         let lo = self.mem_read(pos) as u16;
         let hi = self.mem_read(pos + 1) as u16;
         (hi << 8) | (lo as u16)
@@ -77,17 +111,21 @@ pub struct CPU {
 }
 
 impl Mem for CPU {
-    fn mem_read(&self, addr: u16) -> u8 {
-        self.memory[addr as usize]
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        self.bus.mem_read(addr)
     }
 
     fn mem_write(&mut self, addr: u16, data: u8) {
-        self.memory[addr as usize] = data;
+        self.bus.mem_write(addr, data);
     }
 }
 
 impl CPU {
 pub fn new() -> Self {
+    CPU::with_bus(Bus::new())
+}
+
+pub fn with_bus(bus: Bus) -> Self {
     CPU {
         register_a: 0,
         register_x: 0,
@@ -95,7 +133,8 @@ pub fn new() -> Self {
         stack_pointer: STACK_RESET,
         program_counter: 0,
         status: CpuFlags::from_bits_truncate(0b100100),
-        memory: [0; 0xFFFF],
+        bus,
+        page_cross_penalty: false,
     }
 }
 
@@ -182,12 +221,18 @@ fn asl(&mut self, mode: &AddressingMode) -> u8 {
 
 fn branch(&mut self, condition: bool) {
     if condition {
+        self.bus.tick(1);
+
         let jump: i8 = self.mem_read(self.program_counter) as i8;
         let jump_addr = self
             .program_counter
             .wrapping_add(1)
             .wrapping_add(jump as u16);
 
+        if self.program_counter.wrapping_add(1) & 0xFF00 != jump_addr & 0xFF00 {
+            self.bus.tick(1);
+        }
+
         self.program_counter = jump_addr;
     }
 }
@@ -495,7 +540,9 @@ pub fn reset(&mut self) {
 // }
 
 pub fn load(&mut self, program: Vec<u8>) {
-    self.memory[0x0600..(0x0600 + program.len())].copy_from_slice(&program[..]);
+    for (i, byte) in program.iter().enumerate() {
+        self.mem_write(0x0600 + i as u16, *byte);
+    }
     self.mem_write_u16(0xFFFC, 0x0600);
 }
 
@@ -529,6 +576,20 @@ fn stack_pop_u16(&mut self) -> u16 {
     hi << 8 | lo
 }
 
+fn interrupt(&mut self, interrupt: interrupt::Interrupt) {
+    self.stack_push_u16(self.program_counter);
+    let mut flag = self.status;
+    flag.remove(CpuFlags::BREAK);
+    flag.remove(CpuFlags::BREAK2);
+    flag.bits |= interrupt.b_flag_mask;
+
+    self.stack_push(flag.bits);
+    self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+
+    self.bus.tick(interrupt.cpu_cycles);
+    self.program_counter = self.mem_read_u16(interrupt.vector_addr);
+}
+
 pub fn run(&mut self) {
     self.run_with_callback(|_| {});
 }
@@ -537,12 +598,23 @@ pub fn run_with_callback<F>(&mut self, mut callback: F) where F: FnMut(&mut CPU)
     let ref opcodes: HashMap<u8, &'static opcodes::OpCode> = *opcodes::OPCODES_MAP;
 
     loop {
+        if self.bus.poll_nmi_status() {
+            self.interrupt(interrupt::NMI);
+        } else if self.bus.poll_irq() && !self.status.contains(CpuFlags::INTERRUPT_DISABLE) {
+            self.interrupt(interrupt::IRQ);
+        }
+
         let code = self.mem_read(self.program_counter);
         self.program_counter += 1;
         let program_counter_state = self.program_counter;
 
         let opcode = opcodes.get(&code).expect(&format!("OpCode {:x} is not recognized", code));
 
+        // Register accesses happen on an instruction's last cycle, so run
+        // everything before it first.
+        self.bus.tick(opcode.cycles - 1);
+        self.page_cross_penalty = matches!(opcode.command, "ADC" | "AND" | "CMP" | "EOR" | "LDA" | "LDX" | "LDY" | "ORA" | "SBC");
+
         match code {
             // ADC opcodes
             0x69 | 0x65 | 0x75 | 0x6d | 0x7d | 0x79 | 0x61 | 0x71 => {
@@ -793,6 +865,10 @@ pub fn run_with_callback<F>(&mut self, mut callback: F) where F: FnMut(&mut CPU)
             _=> println!("Unexpected Value! This shouldnt happen!"),
         }
 
+        self.page_cross_penalty = false;
+        self.bus.tick(1);
+        self.bus.run_oam_dma();
+
         if program_counter_state == self.program_counter {
             self.program_counter += (opcode.bytes - 1) as u16;
         }
@@ -801,6 +877,15 @@ pub fn run_with_callback<F>(&mut self, mut callback: F) where F: FnMut(&mut CPU)
     }
 }
 
+/// The extra cycle of a page cross is a read from the wrong page, so it
+/// has to pass before the real access.
+fn cross_page(&mut self, base: u16, addr: u16) {
+    if self.page_cross_penalty && base & 0xFF00 != addr & 0xFF00 {
+        self.page_cross_penalty = false;
+        self.bus.tick(1);
+    }
+}
+
 fn get_operand_address(&mut self, mode: &AddressingMode) -> u16 {
 
     match mode {
@@ -824,11 +909,13 @@ fn get_operand_address(&mut self, mode: &AddressingMode) -> u16 {
         AddressingMode::Absolute_X => {
             let base = self.mem_read_u16(self.program_counter);
             let addr = base.wrapping_add(self.register_x as u16);
+            self.cross_page(base, addr);
             addr
         }
         AddressingMode::Absolute_Y => {
             let base = self.mem_read_u16(self.program_counter);
             let addr = base.wrapping_add(self.register_y as u16);
+            self.cross_page(base, addr);
             addr
         }
 
@@ -847,6 +934,7 @@ fn get_operand_address(&mut self, mode: &AddressingMode) -> u16 {
             let hi = self.mem_read((base as u8).wrapping_add(1) as u16);
             let deref_base = (hi as u16) << 8 | (lo as u16);
             let deref = deref_base.wrapping_add(self.register_y as u16);
+            self.cross_page(deref_base, deref);
             deref
         }
         
@@ -468,5 +468,105 @@ mod test {
        assert_eq!(cpu.mem_read(0x10), 0x55);
    }
 
+   fn cycles_for(program: Vec<u8>) -> usize {
+       let mut cpu = CPU::new();
+       cpu.load_and_run(program);
+       cpu.bus.cycles()
+   }
 
-}
\ No newline at end of file
+   #[test]
+   fn test_page_cross_adds_a_cycle() {
+       let same_page = cycles_for(vec![0xa2, 0x01, 0xbd, 0x00, 0x10, 0x00]);
+       let crossed = cycles_for(vec![0xa2, 0x01, 0xbd, 0xff, 0x10, 0x00]);
+       assert_eq!(crossed, same_page + 1);
+
+       // Stores always take the extra cycle, so the table already counts it.
+       let store_same_page = cycles_for(vec![0xa2, 0x01, 0x9d, 0x00, 0x02, 0x00]);
+       let store_crossed = cycles_for(vec![0xa2, 0x01, 0x9d, 0xff, 0x02, 0x00]);
+       assert_eq!(store_crossed, store_same_page);
+   }
+
+   #[test]
+   fn test_page_cross_cycle_comes_before_the_read() {
+       let mut cpu = CPU::new();
+       cpu.mem_write_u16(0x10, 0x20ff);
+       cpu.program_counter = 0x10;
+       cpu.register_x = 1;
+       cpu.page_cross_penalty = true;
+       let before = cpu.bus.cycles();
+       assert_eq!(cpu.get_operand_address(&AddressingMode::Absolute_X), 0x2100);
+       assert_eq!(cpu.bus.cycles(), before + 1);
+   }
+
+   #[test]
+   fn test_branch_cycles() {
+       let not_taken = cycles_for(vec![0xa2, 0x01, 0xf0, 0x00, 0x00]);
+       let taken = cycles_for(vec![0xa2, 0x00, 0xf0, 0x00, 0x00]);
+       let taken_new_page = cycles_for(vec![0xa2, 0x00, 0xf0, 0x80, 0x00]);
+       assert_eq!(taken, not_taken + 1);
+       assert_eq!(taken_new_page, not_taken + 2);
+   }
+
+   #[test]
+   fn test_oam_dma() {
+       let mut cpu = CPU::new();
+       for i in 0..=0xff {
+           cpu.mem_write(0x0200 + i, i as u8);
+       }
+       cpu.load_and_run(vec![0xa9, 0x02, 0x8d, 0x14, 0x40, 0x00]);
+       for (i, &data) in cpu.bus.ppu.oam_data.iter().enumerate() {
+           // Attribute bytes lose their unused bits on the way in.
+           let expected = if i % 4 == 2 { i as u8 & 0b1110_0011 } else { i as u8 };
+           assert_eq!(data, expected);
+       }
+   }
+
+   #[test]
+   fn test_oam_dma_stall() {
+       // Same program with a write to $0014 instead of $4014.
+       let stall = |program: Vec<u8>| {
+           let mut without_dma = program.clone();
+           without_dma[3] = 0x00;
+           cycles_for(program) - cycles_for(without_dma)
+       };
+       let mut stalls = vec![
+           stall(vec![0xa9, 0x02, 0x8d, 0x14, 0x40, 0x00]),
+           stall(vec![0xa5, 0x02, 0x8d, 0x14, 0x40, 0x00]),
+       ];
+       stalls.sort();
+       assert_eq!(stalls, vec![513, 514]);
+   }
+
+   #[test]
+   fn test_dmc_dma_stall() {
+       // Enabling the DMC fetches the first byte of its 1-byte sample.
+       let with_dmc = cycles_for(vec![0xa9, 0x10, 0x8d, 0x15, 0x40, 0x00]);
+       let without_dmc = cycles_for(vec![0xa9, 0x10, 0x8d, 0x15, 0x00, 0x00]);
+       assert_eq!(with_dmc - without_dmc, 4);
+   }
+
+   #[test]
+   fn test_dmc_dma_during_oam_dma() {
+       let mut cpu = CPU::new();
+       let bus = &mut cpu.bus;
+       // Fastest rate, 17-byte sample: a byte every 8 * 54 cycles.
+       bus.mem_write(0x4010, 0x0f);
+       bus.mem_write(0x4013, 0x01);
+       bus.mem_write(0x4015, 0x10);
+       // Run up to the second fetch, so the third lands inside the OAM DMA.
+       let mut fetches = 0;
+       while fetches < 2 {
+           let before = bus.cycles();
+           bus.tick(1);
+           if bus.cycles() - before > 1 {
+               fetches += 1;
+           }
+       }
+
+       let before = bus.cycles();
+       bus.mem_write(0x4014, 0x02);
+       bus.run_oam_dma();
+       let stall = bus.cycles() - before;
+       assert!(stall == 515 || stall == 516, "stall of {} cycles", stall);
+   }
+}
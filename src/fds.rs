@@ -0,0 +1,122 @@
+const FDS_TAG: [u8; 4] = [0x46, 0x44, 0x53, 0x1A];
+const HEADER_SIZE: usize = 16;
+const DISK_INFO: &[u8] = b"*NINTENDO-HVC*";
+
+/// Bytes of block data on one side of a `.fds` image.
+pub const SIDE_SIZE: usize = 65500;
+/// Bytes on one side as the drive sees it, gaps and CRCs included. At one
+/// byte every 150 CPU cycles the head takes about seven seconds to cross it.
+pub const RAW_SIDE_SIZE: usize = 0x14000;
+
+/// The gap before the first block is 28300 bits, the ones between blocks
+/// 976; a gap ends with a single set bit, read as $80.
+const LEADING_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+pub const GAP_END: u8 = 0x80;
+
+/// Whether `raw` looks like an FDS disk image rather than a ROM.
+pub fn is_fds(raw: &[u8]) -> bool {
+    raw.starts_with(&FDS_TAG) || (raw.len() >= SIDE_SIZE && raw.len().is_multiple_of(SIDE_SIZE) && raw[1..15] == *DISK_INFO)
+}
+
+/// One byte through the drive's CRC-16 (polynomial $8408, bits LSB first).
+/// Starting from 0 and feeding it the gap end mark, the block and two zero
+/// bytes leaves the block's CRC.
+pub fn update_crc(mut crc: u16, byte: u8) -> u16 {
+    for bit in 0..8 {
+        let carry = crc & 1 != 0;
+        crc >>= 1;
+        if carry {
+            crc ^= 0x8408;
+        }
+        if byte & (1 << bit) != 0 {
+            crc ^= 0x8000;
+        }
+    }
+    crc
+}
+
+/// # FDS disk image https://www.nesdev.org/wiki/FDS_file_format
+///
+/// A `.fds` file holds the blocks of each disk side back to back, with an
+/// optional 16-byte header counting the sides:
+///
+///  1 disk info (56 bytes)   2 file count (2)
+///  3 file header (16)       4 file data (1 + size from the file header)
+///
+/// The drive sees more than that: a gap before each block, and a CRC after
+/// it. `FdsDisk` keeps every side in that raw form, `RAW_SIDE_SIZE` bytes
+/// each, so writes from the BIOS land exactly where they would on a disk.
+/// The default is no disk at all, for the sound chip on its own.
+#[derive(Default)]
+pub struct FdsDisk {
+    pub data: Vec<u8>,
+}
+
+impl FdsDisk {
+    pub fn new(raw: &[u8]) -> Result<FdsDisk, String> {
+        let (sides, body) = if raw.starts_with(&FDS_TAG) {
+            if raw.len() < HEADER_SIZE {
+                return Err("File is shorter than its header claims".to_string());
+            }
+            (raw[4] as usize, &raw[HEADER_SIZE..])
+        } else {
+            (raw.len() / SIDE_SIZE, raw)
+        };
+        if sides == 0 || body.len() < sides * SIDE_SIZE {
+            return Err("File is shorter than its header claims".to_string());
+        }
+
+        let mut data = Vec::with_capacity(sides * RAW_SIDE_SIZE);
+        for (number, side) in body.chunks(SIDE_SIZE).take(sides).enumerate() {
+            if side[0] != 1 || side[1..15] != *DISK_INFO {
+                return Err(format!("Side {} is not an FDS disk side", number + 1));
+            }
+            data.extend(with_gaps(side).map_err(|e| format!("Side {}: {}", number + 1, e))?);
+        }
+        Ok(FdsDisk { data })
+    }
+
+    pub fn sides(&self) -> usize {
+        self.data.len() / RAW_SIDE_SIZE
+    }
+}
+
+/// Lays out one side's blocks the way they are on the disk surface.
+fn with_gaps(side: &[u8]) -> Result<Vec<u8>, String> {
+    let mut raw = vec![0; LEADING_GAP];
+    let mut position = 0;
+    let mut file_size = 0;
+    while position < side.len() {
+        let length = match side[position] {
+            1 => 56,
+            2 => 2,
+            3 => 16,
+            4 => 1 + file_size,
+            // The rest of the side is unused.
+            _ => break,
+        };
+        let block = side
+            .get(position..position + length)
+            .ok_or("the last block is cut short")?;
+        if block[0] == 3 {
+            file_size = u16::from_le_bytes([block[13], block[14]]) as usize;
+        }
+
+        let crc = [GAP_END].iter().chain(block).chain(&[0, 0]).fold(0, |crc, &byte| update_crc(crc, byte));
+        raw.push(GAP_END);
+        raw.extend_from_slice(block);
+        raw.extend_from_slice(&crc.to_le_bytes());
+        raw.extend(std::iter::repeat_n(0, BLOCK_GAP));
+        position += length;
+    }
+    if raw.len() > RAW_SIDE_SIZE {
+        return Err("too many blocks to fit on a disk".to_string());
+    }
+    raw.resize(RAW_SIDE_SIZE, 0);
+    Ok(raw)
+}
+
+#[cfg(test)]
+#[path = "fds_tests.rs"]
+mod fds_tests;
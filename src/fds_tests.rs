@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod test {
+    use crate::fds::*;
+
+    /// One side with a single 4-byte file.
+    fn side() -> Vec<u8> {
+        let mut side = vec![0; SIDE_SIZE];
+        let mut blocks = vec![0x01];
+        blocks.extend_from_slice(b"*NINTENDO-HVC*");
+        blocks.resize(56, 0);
+        blocks.extend_from_slice(&[0x02, 0x01]);
+        blocks.extend_from_slice(&[0x03, 0x00, 0x00]);
+        blocks.extend_from_slice(b"FILE    ");
+        blocks.extend_from_slice(&0x6000u16.to_le_bytes());
+        blocks.extend_from_slice(&4u16.to_le_bytes());
+        blocks.push(0x00);
+        blocks.extend_from_slice(&[0x04, 0xDE, 0xAD, 0xBE, 0xEF]);
+        side[..blocks.len()].copy_from_slice(&blocks);
+        side
+    }
+
+    fn headered(sides: &[Vec<u8>]) -> Vec<u8> {
+        let mut raw = b"FDS\x1A".to_vec();
+        raw.push(sides.len() as u8);
+        raw.resize(16, 0);
+        for side in sides {
+            raw.extend_from_slice(side);
+        }
+        raw
+    }
+
+    #[test]
+    fn test_is_fds() {
+        assert!(is_fds(&side()));
+        assert!(is_fds(&headered(&[side()])));
+        assert!(!is_fds(b"NES\x1A\x01\x01\x00\x00"));
+        assert!(!is_fds(&vec![0; SIDE_SIZE]));
+    }
+
+    #[test]
+    fn test_sides_get_gaps_and_crcs() {
+        let disk = FdsDisk::new(&headered(&[side(), side()])).unwrap();
+        assert_eq!(disk.sides(), 2);
+        assert_eq!(disk.data.len(), 2 * RAW_SIDE_SIZE);
+
+        let side = &disk.data[..RAW_SIDE_SIZE];
+        let first = side.iter().position(|&byte| byte != 0).unwrap();
+        assert_eq!(first, 28300 / 8);
+        assert_eq!(side[first], GAP_END);
+        assert_eq!(side[first + 1], 0x01);
+
+        // Running the CRC over a block and its stored CRC leaves 0.
+        let block1 = &side[first..first + 1 + 56 + 2];
+        assert_eq!(block1.iter().fold(0, |crc, &byte| update_crc(crc, byte)), 0);
+
+        // File count block after a gap.
+        let next = first + 1 + 56 + 2 + 976 / 8;
+        assert_eq!(&side[next..next + 3], &[GAP_END, 0x02, 0x01]);
+
+        // The file data block is as long as the file header says.
+        let data = side.windows(5).position(|bytes| bytes == [0x04, 0xDE, 0xAD, 0xBE, 0xEF]).unwrap();
+        assert_eq!(side[data - 1], GAP_END);
+        let block4 = &side[data - 1..data + 5 + 2];
+        assert_eq!(block4.iter().fold(0, |crc, &byte| update_crc(crc, byte)), 0);
+        assert!(side[data + 7..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_headerless_image() {
+        let mut raw = side();
+        raw.extend(side());
+        assert_eq!(FdsDisk::new(&raw).unwrap().sides(), 2);
+    }
+
+    #[test]
+    fn test_bad_images() {
+        let mut raw = headered(&[side()]);
+        raw[4] = 2;
+        assert!(FdsDisk::new(&raw).is_err());
+
+        let mut bad_side = side();
+        bad_side[1] = b'?';
+        assert!(FdsDisk::new(&headered(&[bad_side])).is_err());
+
+        // A file header promising more data than the side holds.
+        let mut cut_short = side();
+        let size = cut_short.windows(8).position(|bytes| bytes == b"FILE    ").unwrap() + 10;
+        cut_short[size..size + 2].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        assert!(FdsDisk::new(&headered(&[cut_short])).is_err());
+    }
+}
@@ -0,0 +1,96 @@
+use crate::joypad::JoypadButton;
+use sdl2::keyboard::Keycode;
+use std::collections::HashMap;
+
+const BUTTON_NAMES: [(&str, JoypadButton); 8] = [
+    ("a", JoypadButton::A),
+    ("b", JoypadButton::B),
+    ("select", JoypadButton::SELECT),
+    ("start", JoypadButton::START),
+    ("up", JoypadButton::UP),
+    ("down", JoypadButton::DOWN),
+    ("left", JoypadButton::LEFT),
+    ("right", JoypadButton::RIGHT),
+];
+
+/// Which controller button each key presses.
+///
+/// A bindings file given with `--keys` replaces the defaults. Each line
+/// binds one key as `<port> <button> <key>`, with port 1 or 2, a button out
+/// of a, b, select, start, up, down, left and right, and the key under its
+/// SDL name, e.g. `1 start Return` or `2 a Left Shift`. `#` starts a comment.
+/// A bound key no longer works as a hotkey: binding P, N, F or Escape takes
+/// them away from the palette, NTSC filter, disk and quit keys.
+pub struct KeyBindings {
+    keys: HashMap<Keycode, (usize, JoypadButton)>,
+}
+
+impl KeyBindings {
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        KeyBindings::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        parse_with(text, Keycode::from_name)
+    }
+
+    /// The controller port, 0 or 1, and button that `key` is bound to.
+    pub fn lookup(&self, key: Keycode) -> Option<(usize, JoypadButton)> {
+        self.keys.get(&key).copied()
+    }
+}
+
+/// Controller 1 on the arrow keys, X and Z for A and B, Right Shift and
+/// Return for Select and Start. Controller 2 has no keys.
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let keys = [
+            (Keycode::X, JoypadButton::A),
+            (Keycode::Z, JoypadButton::B),
+            (Keycode::RShift, JoypadButton::SELECT),
+            (Keycode::Return, JoypadButton::START),
+            (Keycode::Up, JoypadButton::UP),
+            (Keycode::Down, JoypadButton::DOWN),
+            (Keycode::Left, JoypadButton::LEFT),
+            (Keycode::Right, JoypadButton::RIGHT),
+        ];
+        KeyBindings {
+            keys: keys.iter().map(|&(key, button)| (key, (0, button))).collect(),
+        }
+    }
+}
+
+/// `parse` with the key name lookup passed in.
+fn parse_with(text: &str, key_from_name: impl Fn(&str) -> Option<Keycode>) -> Result<KeyBindings, String> {
+    let mut keys = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("Line {}: {}", number + 1, message);
+
+        let mut fields = line.splitn(3, char::is_whitespace);
+        let port = match fields.next() {
+            Some("1") => 0,
+            Some("2") => 1,
+            _ => return Err(error("the port must be 1 or 2")),
+        };
+        let button = fields
+            .next()
+            .and_then(|name| BUTTON_NAMES.iter().find(|(button, _)| button.eq_ignore_ascii_case(name)))
+            .map(|&(_, button)| button)
+            .ok_or_else(|| error("unknown button"))?;
+        let key = fields
+            .next()
+            .and_then(|name| key_from_name(name.trim()))
+            .ok_or_else(|| error("unknown key"))?;
+        keys.insert(key, (port, button));
+    }
+    Ok(KeyBindings { keys })
+}
+
+#[cfg(test)]
+#[path = "input_tests.rs"]
+mod input_tests;
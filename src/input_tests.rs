@@ -0,0 +1,45 @@
+#[cfg(test)]
+mod test {
+    use crate::input::*;
+    use crate::joypad::JoypadButton;
+    use sdl2::keyboard::Keycode;
+
+    // SDL's own lookup calls into the library; these names are enough here.
+    fn key_from_name(name: &str) -> Option<Keycode> {
+        match name {
+            "Return" => Some(Keycode::Return),
+            "Left Shift" => Some(Keycode::LShift),
+            "K" => Some(Keycode::K),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_default_bindings() {
+        let bindings = KeyBindings::default();
+        assert_eq!(bindings.lookup(Keycode::X), Some((0, JoypadButton::A)));
+        assert_eq!(bindings.lookup(Keycode::Return), Some((0, JoypadButton::START)));
+        assert_eq!(bindings.lookup(Keycode::Left), Some((0, JoypadButton::LEFT)));
+        assert_eq!(bindings.lookup(Keycode::P), None);
+    }
+
+    #[test]
+    fn test_parse_bindings() {
+        let text = "# player one\n1 start Return\n\n2 A Left Shift  # fire\n1 b K\n";
+        let bindings = parse_with(text, key_from_name).unwrap();
+        assert_eq!(bindings.lookup(Keycode::Return), Some((0, JoypadButton::START)));
+        assert_eq!(bindings.lookup(Keycode::LShift), Some((1, JoypadButton::A)));
+        assert_eq!(bindings.lookup(Keycode::K), Some((0, JoypadButton::B)));
+        // A file replaces the defaults.
+        assert_eq!(bindings.lookup(Keycode::X), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |text| parse_with(text, key_from_name).err().unwrap();
+        assert_eq!(error("3 a K"), "Line 1: the port must be 1 or 2");
+        assert_eq!(error("1 a K\n1 turbo K"), "Line 2: unknown button");
+        assert_eq!(error("1 a Nope"), "Line 1: unknown key");
+        assert_eq!(error("1 a"), "Line 1: unknown key");
+    }
+}
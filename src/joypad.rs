@@ -0,0 +1,83 @@
+bitflags! {
+    /// # Standard controller buttons https://www.nesdev.org/wiki/Standard_controller
+    ///
+    /// In the order the controller shifts them out, A first.
+    pub struct JoypadButton: u8 {
+        const A      = 0b0000_0001;
+        const B      = 0b0000_0010;
+        const SELECT = 0b0000_0100;
+        const START  = 0b0000_1000;
+        const UP     = 0b0001_0000;
+        const DOWN   = 0b0010_0000;
+        const LEFT   = 0b0100_0000;
+        const RIGHT  = 0b1000_0000;
+    }
+}
+
+/// # Standard controller https://www.nesdev.org/wiki/Standard_controller
+///
+/// An 8-bit shift register. While the strobe bit written to $4016 is high it
+/// keeps reloading the buttons, so reads return A; once it goes low every
+/// read shifts out the next button. After all eight an official controller
+/// reads back 1.
+pub struct Joypad {
+    strobe: bool,
+    shift: u8,
+    pressed: u8,
+    buttons: JoypadButton,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            strobe: false,
+            shift: 0,
+            pressed: 0,
+            buttons: JoypadButton::empty(),
+        }
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 != 0;
+        if self.strobe {
+            self.latch();
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            self.latch();
+        }
+        if self.shift >= 8 {
+            return 1;
+        }
+        let bit = (self.pressed >> self.shift) & 1;
+        if !self.strobe {
+            self.shift += 1;
+        }
+        bit
+    }
+
+    fn latch(&mut self) {
+        self.shift = 0;
+        self.pressed = self.buttons.bits();
+    }
+
+    pub fn set_button(&mut self, button: JoypadButton, pressed: bool) {
+        self.buttons.set(button, pressed);
+    }
+
+    pub fn buttons(&self) -> JoypadButton {
+        self.buttons
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[path = "joypad_tests.rs"]
+mod joypad_tests;
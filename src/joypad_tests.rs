@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod test {
+    use crate::bus::Bus;
+    use crate::cpu::Mem;
+    use crate::joypad::*;
+
+    #[test]
+    fn test_serial_reads() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(JoypadButton::A, true);
+        joypad.set_button(JoypadButton::START, true);
+        joypad.set_button(JoypadButton::RIGHT, true);
+        joypad.write(1);
+        joypad.write(0);
+        let bits: Vec<u8> = (0..8).map(|_| joypad.read()).collect();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 1]);
+        // Past the eighth button an official controller reads 1.
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 1);
+    }
+
+    #[test]
+    fn test_strobe_high_reads_a() {
+        let mut joypad = Joypad::new();
+        joypad.write(1);
+        assert_eq!(joypad.read(), 0);
+        joypad.set_button(JoypadButton::A, true);
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 1);
+    }
+
+    #[test]
+    fn test_buttons_latch_on_strobe() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(JoypadButton::B, true);
+        joypad.write(1);
+        joypad.write(0);
+        // Released after the latch: the read still sees it.
+        joypad.set_button(JoypadButton::B, false);
+        assert_eq!(joypad.read(), 0);
+        assert_eq!(joypad.read(), 1);
+        assert!(joypad.buttons().is_empty());
+    }
+
+    #[test]
+    fn test_ports_on_the_bus() {
+        let mut bus = Bus::new();
+        bus.joypad1.set_button(JoypadButton::A, true);
+        bus.joypad2.set_button(JoypadButton::B, true);
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+        assert_eq!(bus.mem_read(0x4016), 0x41);
+        assert_eq!(bus.mem_read(0x4016), 0x40);
+        assert_eq!(bus.mem_read(0x4017), 0x40);
+        assert_eq!(bus.mem_read(0x4017), 0x41);
+        // $4017 writes still go to the APU frame counter, not the ports.
+        bus.mem_write(0x4017, 1);
+        assert_eq!(bus.mem_read(0x4017), 0x40);
+        // Unmapped registers next to the ports read the same open bus.
+        assert_eq!(bus.mem_read(0x4018), 0x40);
+    }
+}
@@ -1,17 +1,47 @@
+pub mod apu;
+pub mod audio;
+pub mod battery;
+pub mod bus;
+pub mod cartridge;
 pub mod cpu;
+pub mod fds;
+pub mod input;
+pub mod joypad;
+pub mod mapper;
+pub mod nsf;
 pub mod opcodes;
+pub mod ppu;
+pub mod region;
+pub mod render;
+pub mod wav;
 
+use audio::AudioOutput;
+use battery::BatterySave;
+use bus::Bus;
+use cartridge::Rom;
 use cpu::CPU;
 use cpu::Mem;
+use fds::FdsDisk;
+use input::KeyBindings;
+use joypad::JoypadButton;
+use mapper::fds::Fds;
+use nsf::player::{fade_volume, NsfPlayer};
+use nsf::Nsf;
+use render::frame::Frame;
+use render::ntsc::{NtscFilter, NtscSettings};
+use render::palette::Palette;
+use wav::WavRecorder;
+use region::Region;
 
 use rand::Rng;
 
 use sdl2::event::Event;
 use sdl2::EventPump;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
 use sdl2::pixels::PixelFormatEnum;
 
+use std::time::{Duration, Instant};
+
 
 
 #[macro_use(lazy_static)]
@@ -21,76 +51,193 @@ extern crate lazy_static;
 extern crate bitflags;
 
 
-fn handle_user_input(cpu: &mut CPU, event_pump: &mut EventPump) {
+/// Snake has no controller port: it reads the last key pressed from $FF, as
+/// ASCII w, s, a or d.
+const SNAKE_KEYS: [(JoypadButton, u8); 4] = [
+    (JoypadButton::UP, 0x77),
+    (JoypadButton::DOWN, 0x73),
+    (JoypadButton::LEFT, 0x61),
+    (JoypadButton::RIGHT, 0x64),
+];
+
+fn handle_user_input(
+    cpu: &mut CPU,
+    event_pump: &mut EventPump,
+    bindings: &KeyBindings,
+    battery: &mut Option<BatterySave>,
+    palettes: &mut [Palette],
+    ntsc: &mut bool,
+) {
     for event in event_pump.poll_iter() {
+        // Keys bound to a controller win over the hotkeys below.
+        let binding = match event {
+            Event::KeyDown { keycode: Some(key), .. } | Event::KeyUp { keycode: Some(key), .. } => bindings.lookup(key),
+            _ => None,
+        };
+        if let Some((port, button)) = binding {
+            let pressed = matches!(event, Event::KeyDown { .. });
+            let joypad = if port == 0 { &mut cpu.bus.joypad1 } else { &mut cpu.bus.joypad2 };
+            joypad.set_button(button, pressed);
+            continue;
+        }
+
         match event {
             Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                flush_save(cpu, battery);
                 std::process::exit(0)
             },
-            Event::KeyDown { keycode: Some(Keycode::W), .. } => {
-                cpu.mem_write(0xff, 0x77);
-            },
-            Event::KeyDown { keycode: Some(Keycode::S), .. } => {
-                cpu.mem_write(0xff, 0x73);
+            // Cycle through the built-in palette and the ones given with
+            // --palette.
+            Event::KeyDown { keycode: Some(Keycode::P), .. } => {
+                if let Some(next) = palettes.first_mut() {
+                    std::mem::swap(&mut cpu.bus.ppu.palette, next);
+                    palettes.rotate_left(1);
+                }
             },
-            Event::KeyDown { keycode: Some(Keycode::A), .. } => {
-                cpu.mem_write(0xff, 0x61);
+            Event::KeyDown { keycode: Some(Keycode::N), .. } => *ntsc = !*ntsc,
+            // Flip the disk, or put in the next one.
+            Event::KeyDown { keycode: Some(Keycode::F), .. } => {
+                if let Some(side) = cpu.bus.cartridge().and_then(|mut cartridge| cartridge.next_disk_side()) {
+                    println!("Inserting disk {} side {}", side / 2 + 1, if side % 2 == 0 { 'A' } else { 'B' });
+                }
             },
-            Event::KeyDown { keycode: Some(Keycode::D), .. } => {
-                cpu.mem_write(0xff, 0x64);
-            }
             _ => {/* do nothing */}
         }
     }
  }
 
- fn color(byte: u8) -> Color {
-    match byte {
-        0 => sdl2::pixels::Color::BLACK,
-        1 => sdl2::pixels::Color::WHITE,
-        2 | 9 => sdl2::pixels::Color::GREY,
-        3 | 10 => sdl2::pixels::Color::RED,
-        4 | 11 => sdl2::pixels::Color::GREEN,
-        5 | 12 => sdl2::pixels::Color::BLUE,
-        6 | 13 => sdl2::pixels::Color::MAGENTA,
-        7 | 14 => sdl2::pixels::Color::YELLOW,
-        _ => sdl2::pixels::Color::CYAN,
+fn flush_save(cpu: &CPU, battery: &mut Option<BatterySave>) {
+    if let (Some(save), Some(cartridge)) = (battery.as_mut(), cpu.bus.cartridge()) {
+        if let Err(e) = save.flush(cartridge.as_ref()) {
+            eprintln!("Unable to write {}: {}", save.path().display(), e);
+        }
     }
- }
+}
 
- fn read_screen_state(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
-    let mut frame_idx = 0;
-    let mut update = false;
-    for i in 0x0200..0x600 {
-        let color_idx = cpu.mem_read(i as u16);
-        let (b1, b2, b3) = color(color_idx).rgb();
-        if frame[frame_idx] != b1 || frame[frame_idx + 1] != b2 || frame[frame_idx + 2] != b3 {
-            frame[frame_idx] = b1;
-            frame[frame_idx + 1] = b2;
-            frame[frame_idx + 2] = b3;
-            update = true;
-        }
-        frame_idx += 3;
+/// Gives Snake its random number in $FE and the last direction pressed in
+/// $FF. Cartridges own that RAM, so they are left alone.
+fn feed_snake(cpu: &mut CPU, random: u8) {
+    if cpu.bus.cartridge().is_some() {
+        return;
     }
-    update
- }
+    cpu.mem_write(0xfe, random);
+    let buttons = cpu.bus.joypad1.buttons();
+    for &(button, key) in SNAKE_KEYS.iter() {
+        if buttons.contains(button) {
+            cpu.mem_write(0xff, key);
+        }
+    }
+}
 
-fn main() {
+/// Tracks without a length in the file play for this long.
+const DEFAULT_TRACK_LENGTH: Duration = Duration::from_secs(150);
 
-   let sdl_context = sdl2::init().unwrap();
-   let video_subsystem = sdl_context.video().unwrap();
-   let window = video_subsystem
-       .window("Snake game", (32.0 * 10.0) as u32, (32.0 * 10.0) as u32)
-       .position_centered()
-       .build().unwrap();
+fn format_time(time: Duration) -> String {
+    format!("{}:{:02}", time.as_secs() / 60, time.as_secs() % 60)
+}
 
-   let mut canvas = window.into_canvas().present_vsync().build().unwrap();
-   let mut event_pump = sdl_context.event_pump().unwrap();
-   canvas.set_scale(10.0, 10.0).unwrap();
+fn print_tracks(nsf: &Nsf) {
+    println!("{} - {} ({})", nsf.title, nsf.artist, nsf.copyright);
+    for track in 0..nsf.tracks {
+        let title = nsf.track_title(track).unwrap_or("");
+        let length = nsf.track_duration(track).map(format_time).unwrap_or_default();
+        println!("{:3}. {:40} {}", track + 1, title, length);
+    }
+}
 
-   let creator = canvas.texture_creator();
-   let mut texture = creator
-       .create_texture_target(PixelFormatEnum::RGB24, 32, 32).unwrap();
+/// Player mode for NSF files. The window shows what is playing in its title;
+/// Left and Right change tracks, and tracks with a known length move on to
+/// the next one once they have faded out.
+fn play_nsf(mut player: NsfPlayer, mut track: u8, audio_enabled: bool) {
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let window = video_subsystem
+        .window("NSF", (Frame::WIDTH * 2) as u32, 64)
+        .position_centered()
+        .build().unwrap();
+    let mut canvas = window.into_canvas().build().unwrap();
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    let mut audio = None;
+    if audio_enabled {
+        match sdl_context.audio().and_then(|subsystem| AudioOutput::new(&subsystem)) {
+            Ok(output) => {
+                player.cpu.bus.enable_audio(output.sample_rate());
+                audio = Some(output);
+            }
+            Err(e) => eprintln!("Audio disabled: {}", e),
+        }
+    }
+
+    let region = player.region();
+    let frame_cycles = (region.cpu_clock_hz() / region.frame_rate()) as usize;
+    let frame_time = Duration::from_secs_f64(1.0 / region.frame_rate());
+    let mut next_frame = Instant::now();
+    let tracks = player.nsf.tracks.max(1) as usize;
+    let mut samples = Vec::new();
+    let mut status = String::new();
+    player.start_track(track);
+
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return,
+                Event::KeyDown { keycode: Some(Keycode::Right), .. } => {
+                    track = ((track as usize + 1) % tracks) as u8;
+                    player.start_track(track);
+                }
+                Event::KeyDown { keycode: Some(Keycode::Left), .. } => {
+                    track = ((track as usize + tracks - 1) % tracks) as u8;
+                    player.start_track(track);
+                }
+                _ => {}
+            }
+        }
+
+        let length = player.nsf.track_duration(track);
+        let fade = player.nsf.track_fade(track).unwrap_or(Duration::ZERO);
+        if length.is_some_and(|length| player.elapsed() >= length + fade) {
+            track = ((track as usize + 1) % tracks) as u8;
+            player.start_track(track);
+        }
+
+        player.run(frame_cycles);
+        if let Some(output) = audio.as_mut() {
+            player.cpu.bus.read_audio(&mut samples);
+            if let Some(length) = length {
+                let volume = fade_volume(player.elapsed(), length, fade);
+                samples.iter_mut().for_each(|sample| *sample *= volume);
+            }
+            output.push(&samples);
+            samples.clear();
+            player.cpu.bus.adjust_audio_rate(output.rate_adjustment());
+        }
+
+        let mut now_playing = format!("{} - {}/{}", player.nsf.title, track + 1, tracks);
+        if let Some(title) = player.nsf.track_title(track) {
+            now_playing += &format!(" {}", title);
+        }
+        now_playing += &format!("  {}", format_time(player.elapsed()));
+        if let Some(length) = length {
+            now_playing += &format!(" / {}", format_time(length));
+        }
+        if now_playing != status {
+            canvas.window_mut().set_title(&now_playing).unwrap();
+            status = now_playing;
+        }
+        canvas.present();
+
+        next_frame += frame_time;
+        let now = Instant::now();
+        if next_frame > now {
+            std::thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
+    }
+}
+
+fn main() {
 
     let game_code = vec![
     0x20, 0x06, 0x06, 0x20, 0x38, 0x06, 0x20, 0x0d, 0x06, 0x20, 0x2a, 0x06, 0x60, 0xa9, 0x02, 0x85,
@@ -115,23 +262,254 @@ fn main() {
     0xea, 0xca, 0xd0, 0xfb, 0x60
     ];
 
-    let mut cpu = CPU::new();
-    cpu.load(game_code);
+    let mut rom_path = None;
+    let mut palettes = Vec::new();
+    let mut region_override = None;
+    let mut audio_enabled = true;
+    let mut record_path = None;
+    let mut record_channels = false;
+    let mut track = None;
+    let mut render_path = None;
+    let mut render_length = None;
+    let mut fds_bios = None;
+    let mut bindings = KeyBindings::default();
+    let mut ntsc_settings = NtscSettings::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--palette" => {
+                let path = args.next().expect("--palette needs a .pal file");
+                palettes.push(Palette::load(std::path::Path::new(&path)).unwrap());
+            }
+            "--region" => {
+                let name = args.next().expect("--region needs ntsc, pal or dendy");
+                region_override = Some(Region::from_name(&name).expect("--region needs ntsc, pal or dendy"));
+            }
+            "--ntsc-sharpness" | "--ntsc-saturation" | "--ntsc-hue" => {
+                let value = args.next().and_then(|value| value.parse::<f32>().ok());
+                let value = value.unwrap_or_else(|| panic!("{} needs a number", arg));
+                match arg.as_str() {
+                    "--ntsc-sharpness" => ntsc_settings.sharpness = value,
+                    "--ntsc-saturation" => ntsc_settings.saturation = value,
+                    _ => ntsc_settings.hue = value,
+                }
+            }
+            "--no-audio" => audio_enabled = false,
+            "--record" => record_path = Some(args.next().expect("--record needs a .wav file")),
+            "--record-channels" => record_channels = true,
+            "--track" => {
+                let number = args.next().and_then(|number| number.parse::<u8>().ok());
+                track = Some(number.expect("--track needs a track number"));
+            }
+            "--render" => render_path = Some(args.next().expect("--render needs a .wav file")),
+            "--length" => {
+                let seconds = args.next().and_then(|seconds| seconds.parse::<f64>().ok());
+                render_length = Some(Duration::from_secs_f64(seconds.expect("--length needs seconds")));
+            }
+            "--keys" => {
+                let path = args.next().expect("--keys needs a bindings file");
+                bindings = KeyBindings::load(std::path::Path::new(&path)).unwrap();
+            }
+            "--fds-bios" => fds_bios = Some(args.next().expect("--fds-bios needs the disksys ROM")),
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    let raw = rom_path.as_ref().map(|path| std::fs::read(path).expect("Unable to read ROM file"));
+    if let Some(raw) = raw.as_ref().filter(|raw| nsf::is_nsf(raw)) {
+        let nsf = Nsf::new(raw).unwrap();
+        let region = region_override.unwrap_or(nsf.region);
+        let track = match track {
+            Some(number) if (1..=nsf.tracks).contains(&number) => number - 1,
+            Some(_) => panic!("--track needs a track number from 1 to {}", nsf.tracks),
+            None => nsf.starting_track,
+        };
+        print_tracks(&nsf);
+        let mut player = NsfPlayer::new(nsf, region);
+        match render_path {
+            // Headless: no window and no sound device.
+            Some(path) => {
+                let length = render_length
+                    .or(player.nsf.track_duration(track))
+                    .unwrap_or(DEFAULT_TRACK_LENGTH);
+                let fade = player.nsf.track_fade(track).unwrap_or(Duration::ZERO);
+                let mut recorder = WavRecorder::create(std::path::Path::new(&path), 48000, 1)
+                    .expect("Unable to create the recording");
+                player.render(track, length, fade, &mut recorder).expect("Unable to write the recording");
+            }
+            None => play_nsf(player, track, audio_enabled),
+        }
+        return;
+    }
+
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let window = video_subsystem
+        .window("NES", (Frame::WIDTH * 3) as u32, (Frame::HEIGHT * 3) as u32)
+        .position_centered()
+        .build().unwrap();
+
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    // N switches the filter on and off; its settings come from --ntsc-*.
+    let mut ntsc_filter = NtscFilter::new(ntsc_settings);
+    let mut ntsc = false;
+
+    let mut battery = None;
+    let mut region = Region::Ntsc;
+    let mut cpu = match (rom_path, raw) {
+        (Some(path), Some(raw)) => {
+            let (mut cartridge, has_battery) = if fds::is_fds(&raw) {
+                let bios_path = fds_bios.expect("FDS images need the BIOS; pass it with --fds-bios");
+                let bios = std::fs::read(bios_path).expect("Unable to read the FDS BIOS");
+                let disk = FdsDisk::new(&raw).unwrap_or_else(|e| panic!("Unable to read the disk image: {}", e));
+                let fds = Fds::new(bios, disk).unwrap_or_else(|e| panic!("Unable to start the FDS: {}", e));
+                let cartridge: Box<dyn mapper::Mapper> = Box::new(fds);
+                // Writes to the disk go to the save file.
+                (cartridge, true)
+            } else {
+                let rom = Rom::new(&raw).unwrap();
+                let has_battery = rom.battery;
+                region = rom.region;
+                (mapper::from_rom(rom).unwrap(), has_battery)
+            };
+            if has_battery {
+                let mut save = BatterySave::new(std::path::Path::new(&path));
+                save.load(cartridge.as_mut()).expect("Unable to read save file");
+                battery = Some(save);
+            }
+            CPU::with_bus(Bus::with_cartridge(cartridge))
+        }
+        _ => {
+            let mut cpu = CPU::new();
+            cpu.load(game_code);
+            cpu
+        }
+    };
+    let region = region_override.unwrap_or(region);
+    cpu.bus.set_region(region);
     cpu.reset();
 
-    let mut screen_state = [0 as u8; 32 * 3 * 32];
+    // Vsync paces frames when the monitor is close enough to the console's
+    // rate for the audio rate control to absorb the gap: NTSC's 60.1 Hz on a
+    // 60 Hz monitor runs 0.2% slow. A PAL game on a 60 Hz monitor has to be
+    // paced by the frame timer instead.
+    let refresh_rate = window
+        .display_index()
+        .and_then(|display| video_subsystem.current_display_mode(display))
+        .map_or(0, |mode| mode.refresh_rate);
+    let vsync = (refresh_rate as f64 / region.frame_rate() - 1.0).abs() < audio::MAX_RATE_DELTA;
+    let mut canvas = if vsync {
+        window.into_canvas().present_vsync().build().unwrap()
+    } else {
+        window.into_canvas().build().unwrap()
+    };
+    canvas.set_scale(3.0, 3.0).unwrap();
+
+    let creator = canvas.texture_creator();
+    let mut texture = creator
+        .create_texture_target(PixelFormatEnum::RGB24, Frame::WIDTH as u32, Frame::HEIGHT as u32).unwrap();
+    let mut ntsc_texture = creator
+        .create_texture_target(PixelFormatEnum::RGB24, NtscFilter::WIDTH as u32, NtscFilter::HEIGHT as u32).unwrap();
+
+    // Without a sound device (or with --no-audio) the emulator runs silent.
+    let mut audio = None;
+    if audio_enabled {
+        match sdl_context.audio().and_then(|subsystem| AudioOutput::new(&subsystem)) {
+            Ok(output) => {
+                cpu.bus.enable_audio(output.sample_rate());
+                audio = Some(output);
+            }
+            Err(e) => eprintln!("Audio disabled: {}", e),
+        }
+    }
+
+    // The recording gets the mix in its first track and, with
+    // --record-channels, one track per entry of bus::AUDIO_CHANNELS after it.
+    let mut recorder = None;
+    if let Some(path) = record_path {
+        let sample_rate = audio.as_ref().map_or(48000, AudioOutput::sample_rate);
+        if audio.is_none() {
+            cpu.bus.enable_audio(sample_rate);
+        }
+        let mut channels = 1;
+        if record_channels {
+            cpu.bus.enable_channel_audio(sample_rate);
+            channels += bus::AUDIO_CHANNELS.len() as u16;
+        }
+        recorder = Some(WavRecorder::create(std::path::Path::new(&path), sample_rate, channels)
+            .expect("Unable to create the recording"));
+    }
+    let mut samples = Vec::new();
+    let mut channel_samples = Vec::new();
+    let mut frames = Vec::new();
+    // Start with the last palette given on the command line.
+    if let Some(palette) = palettes.pop() {
+        palettes.push(std::mem::replace(&mut cpu.bus.ppu.palette, palette));
+    }
+
     let mut rng = rand::thread_rng();
+    let frame_time = Duration::from_secs_f64(1.0 / region.frame_rate());
+    let mut next_frame = Instant::now();
+
+    cpu.run_with_callback(|cpu| {
+        feed_snake(cpu, rng.gen_range(1, 16));
 
-    cpu.run_with_callback(move |cpu| {
-        handle_user_input(cpu, &mut event_pump);
-        cpu.mem_write(0xfe, rng.gen_range(1, 16));
+        if cpu.bus.ppu.poll_frame() {
+            handle_user_input(cpu, &mut event_pump, &bindings, &mut battery, &mut palettes, &mut ntsc);
+            if let (Some(save), Some(cartridge)) = (battery.as_mut(), cpu.bus.cartridge()) {
+                if let Err(e) = save.tick(cartridge.as_ref()) {
+                    eprintln!("Unable to write {}: {}", save.path().display(), e);
+                }
+            }
 
-        if read_screen_state(cpu, &mut screen_state) {
-            texture.update(None, &screen_state, 32 * 3).unwrap();
-            canvas.copy(&texture, None, None).unwrap();
+            if ntsc {
+                ntsc_texture.update(None, ntsc_filter.apply(&cpu.bus.ppu.frame), NtscFilter::WIDTH * 3).unwrap();
+                canvas.copy(&ntsc_texture, None, None).unwrap();
+            } else {
+                texture.update(None, &cpu.bus.ppu.frame.data, Frame::WIDTH * 3).unwrap();
+                canvas.copy(&texture, None, None).unwrap();
+            }
             canvas.present();
-        }
 
-        ::std::thread::sleep(std::time::Duration::new(0, 70_000));
+            cpu.bus.read_audio(&mut samples);
+            if let Some(output) = audio.as_mut() {
+                output.push(&samples);
+                // Keep the pitch of a recording exact; the sound card gets
+                // the odd dropped or repeated sample instead.
+                if recorder.is_none() {
+                    cpu.bus.adjust_audio_rate(output.rate_adjustment());
+                }
+            }
+            if let Some(recorder) = recorder.as_mut() {
+                cpu.bus.read_channel_audio(&mut channel_samples);
+                let tracks = recorder.channels() as usize - 1;
+                for (frame, &mix) in samples.iter().enumerate() {
+                    frames.push(mix);
+                    frames.extend(channel_samples.iter().skip(frame * tracks).take(tracks));
+                }
+                if let Err(e) = recorder.write(&frames) {
+                    eprintln!("Unable to write {}: {}", recorder.path().display(), e);
+                }
+                channel_samples.clear();
+                frames.clear();
+            }
+            samples.clear();
+
+            if !vsync {
+                next_frame += frame_time;
+                let now = Instant::now();
+                if next_frame > now {
+                    std::thread::sleep(next_frame - now);
+                } else {
+                    next_frame = now;
+                }
+            }
+        }
     });
-}
\ No newline at end of file
+    // The game hit BRK.
+    flush_save(&cpu, &mut battery);
+}
+
+#[cfg(test)]
+#[path = "main_tests.rs"]
+mod main_tests;
@@ -0,0 +1,31 @@
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    #[test]
+    fn test_snake_ram_untouched_with_a_cartridge() {
+        // NOP, then BRK to end the run.
+        let mut prg_rom = vec![0; 0x8000];
+        prg_rom[0] = 0xea;
+        prg_rom[0x7ffc..0x7ffe].copy_from_slice(&[0x00, 0x80]);
+        let rom = Rom::test_rom(0, prg_rom, vec![]);
+        let mut cpu = CPU::with_bus(Bus::with_cartridge(mapper::from_rom(rom).unwrap()));
+        cpu.reset();
+        cpu.mem_write(0xfe, 0x42);
+        cpu.mem_write(0xff, 0x24);
+        cpu.bus.joypad1.set_button(JoypadButton::UP, true);
+
+        cpu.run_with_callback(|cpu| feed_snake(cpu, 7));
+        assert_eq!(cpu.mem_read(0xfe), 0x42);
+        assert_eq!(cpu.mem_read(0xff), 0x24);
+    }
+
+    #[test]
+    fn test_snake_gets_random_and_keys() {
+        let mut cpu = CPU::new();
+        cpu.bus.joypad1.set_button(JoypadButton::LEFT, true);
+        feed_snake(&mut cpu, 7);
+        assert_eq!(cpu.mem_read(0xfe), 7);
+        assert_eq!(cpu.mem_read(0xff), 0x61);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{bank_offset, Chr, Mapper};
+
+/// # AxROM (mapper 7) https://www.nesdev.org/wiki/AxROM
+///
+///  7  bit  0
+///  ---- ----
+///  xxxM xPPP
+///     |  |||
+///     |  +++- Select 32 KB PRG ROM bank for $8000-$FFFF
+///     +------ Select 1 KB VRAM page for all 4 nametables
+///
+/// Submapper 2 (AMROM) has bus conflicts, submapper 1 (ANROM) does not.
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    bus_conflicts: bool,
+    bank: usize,
+    mirroring: Mirroring,
+}
+
+impl Axrom {
+    pub fn new(rom: Rom) -> Self {
+        Axrom {
+            chr: Chr::new(&rom),
+            bus_conflicts: rom.submapper == 2,
+            prg_rom: rom.prg_rom,
+            bank: 0,
+            mirroring: Mirroring::SingleScreenLower,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => self.prg_rom[bank_offset(self.prg_rom.len(), self.bank, 0x8000, addr)],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if addr >= 0x8000 {
+            let data = if self.bus_conflicts { data & self.read_prg(addr) } else { data };
+            self.bank = (data & 0b0000_0111) as usize;
+            self.mirroring = if data & 0b0001_0000 != 0 {
+                Mirroring::SingleScreenUpper
+            } else {
+                Mirroring::SingleScreenLower
+            };
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
@@ -0,0 +1,55 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{bank_offset, Chr, Mapper};
+
+/// # CNROM (mapper 3) https://www.nesdev.org/wiki/CNROM
+///
+/// Fixed PRG ROM like NROM, with the whole 8 KB of CHR switched by writes to
+/// $8000-$FFFF. Submapper 2 boards AND the written value with the ROM byte.
+pub struct Cnrom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    chr_bank: usize,
+}
+
+impl Cnrom {
+    pub fn new(rom: Rom) -> Self {
+        Cnrom {
+            chr: Chr::new(&rom),
+            bus_conflicts: rom.submapper == 2,
+            prg_rom: rom.prg_rom,
+            mirroring: rom.screen_mirroring,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Cnrom {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => self.prg_rom[(addr - 0x8000) as usize % self.prg_rom.len()],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if addr >= 0x8000 {
+            let data = if self.bus_conflicts { data & self.read_prg(addr) } else { data };
+            self.chr_bank = data as usize;
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(bank_offset(self.chr.size(), self.chr_bank, 0x2000, addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        let offset = bank_offset(self.chr.size(), self.chr_bank, 0x2000, addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
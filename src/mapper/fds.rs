@@ -0,0 +1,363 @@
+use crate::cartridge::Mirroring;
+use crate::fds::{update_crc, FdsDisk, RAW_SIDE_SIZE};
+use crate::mapper::fds_audio::FdsAudio;
+use crate::mapper::Mapper;
+
+pub const BIOS_SIZE: usize = 0x2000;
+
+/// CPU cycles per byte under the head, at 96.4 kbit/s.
+const BYTE_CYCLES: u32 = 150;
+/// CPU cycles the drive takes to bring the head back to the start.
+const REWIND_CYCLES: u32 = 50_000;
+/// A disk being flipped stays out for about a second, long enough for the
+/// BIOS to notice it was ejected.
+const EJECT_CYCLES: u32 = 1_789_773;
+
+/// # Famicom Disk System https://www.nesdev.org/wiki/Family_Computer_Disk_System
+///
+/// The RAM adapter puts 32 KB of RAM at $6000-$DFFF, the 8 KB disksys BIOS
+/// at $E000-$FFFF and 8 KB of CHR RAM on the PPU bus. Its registers drive
+/// the disk one byte at a time, each transfer raising an IRQ:
+///
+///  $4020-$4021 timer IRQ reload   $4022 timer IRQ control
+///  $4023 disk/sound enable        $4024 byte to write
+///  $4025 drive control, mirroring $4026 expansion port out
+///  $4030 status, acknowledges     $4031 byte read
+///  $4032 drive status             $4033 expansion port in, battery
+///
+/// The disk in the drive stands in for battery RAM: `prg_ram()` is the raw
+/// disk, so whatever the game writes to it is saved next to the image.
+pub struct Fds {
+    bios: Vec<u8>,
+    ram: Vec<u8>,
+    chr_ram: [u8; 0x2000],
+    disk: FdsDisk,
+    side: Option<usize>,
+    next_side: usize,
+    eject_cycles: u32,
+
+    disk_enabled: bool,
+    sound_enabled: bool,
+    mirroring: Mirroring,
+
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_repeat: bool,
+    irq_enabled: bool,
+    timer_irq: bool,
+
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    previous_crc_control: bool,
+    disk_ready: bool,
+    transfer_irq_enabled: bool,
+    transferred: bool,
+    disk_irq: bool,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    position: usize,
+    delay: u32,
+    read_data: u8,
+    write_data: u8,
+    crc: u16,
+
+    audio: FdsAudio,
+}
+
+impl Fds {
+    /// Starts with the first side in the drive.
+    pub fn new(bios: Vec<u8>, disk: FdsDisk) -> Result<Self, String> {
+        if bios.len() != BIOS_SIZE {
+            return Err(format!("The FDS BIOS must be {} bytes, not {}", BIOS_SIZE, bios.len()));
+        }
+        Ok(Fds {
+            bios,
+            ram: vec![0; 0x8000],
+            chr_ram: [0; 0x2000],
+            side: if disk.sides() > 0 { Some(0) } else { None },
+            disk,
+            next_side: 0,
+            eject_cycles: 0,
+
+            disk_enabled: false,
+            sound_enabled: false,
+            mirroring: Mirroring::Vertical,
+
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_repeat: false,
+            irq_enabled: false,
+            timer_irq: false,
+
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            crc_control: false,
+            previous_crc_control: false,
+            disk_ready: false,
+            transfer_irq_enabled: false,
+            transferred: false,
+            disk_irq: false,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            position: 0,
+            delay: 0,
+            read_data: 0,
+            write_data: 0,
+            crc: 0,
+
+            audio: FdsAudio::new(),
+        })
+    }
+
+    /// Just the sound chip, with the sound registers enabled, for NSFs.
+    pub fn sound_only() -> Self {
+        let mut fds = Fds::new(vec![0; BIOS_SIZE], FdsDisk::default()).unwrap();
+        fds.sound_enabled = true;
+        fds
+    }
+
+    fn write_control(&mut self, data: u8) {
+        self.motor_on = data & 0x01 != 0;
+        self.reset_transfer = data & 0x02 != 0;
+        self.read_mode = data & 0x04 != 0;
+        self.mirroring = if data & 0x08 != 0 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        };
+        self.crc_control = data & 0x10 != 0;
+        self.disk_ready = data & 0x40 != 0;
+        self.transfer_irq_enabled = data & 0x80 != 0;
+        self.disk_irq = false;
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_counter == 0 {
+            self.timer_irq = true;
+            self.irq_counter = self.irq_reload;
+            self.irq_enabled = self.irq_repeat;
+        } else {
+            self.irq_counter -= 1;
+        }
+    }
+
+    /// Moves the head along the disk, one byte every `BYTE_CYCLES` once the
+    /// motor has spun up. Reads skip the gap until its end mark; writes put
+    /// down zeros while the BIOS is writing a gap, and the CRC when it asks
+    /// for one.
+    fn clock_drive(&mut self) {
+        let side = match self.side {
+            Some(side) if self.motor_on => side,
+            _ => {
+                self.end_of_head = true;
+                self.scanning = false;
+                return;
+            }
+        };
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.end_of_head = false;
+            self.delay = REWIND_CYCLES;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let offset = side * RAW_SIDE_SIZE + self.position;
+        let mut irq = self.transfer_irq_enabled;
+        if self.read_mode {
+            let data = self.disk.data[offset];
+            if !self.disk_ready {
+                self.gap_ended = false;
+            } else if data != 0 && !self.gap_ended {
+                // The gap end mark itself is not handed to the BIOS.
+                self.gap_ended = true;
+                irq = false;
+            }
+            if self.gap_ended {
+                self.transferred = true;
+                self.read_data = data;
+                self.disk_irq |= irq;
+            }
+        } else {
+            let mut data = self.write_data;
+            if !self.crc_control {
+                self.transferred = true;
+                self.disk_irq |= irq;
+            }
+            if !self.disk_ready {
+                data = 0;
+                self.crc = 0;
+            }
+            if !self.crc_control {
+                self.crc = update_crc(self.crc, data);
+            } else {
+                if !self.previous_crc_control {
+                    self.crc = update_crc(update_crc(self.crc, 0), 0);
+                }
+                data = self.crc as u8;
+                self.crc >>= 8;
+            }
+            self.disk.data[offset] = data;
+            self.gap_ended = false;
+        }
+        self.previous_crc_control = self.crc_control;
+
+        self.position += 1;
+        if self.position >= RAW_SIDE_SIZE {
+            self.motor_on = false;
+            self.disk_irq |= irq;
+        } else {
+            self.delay = BYTE_CYCLES - 1;
+        }
+    }
+}
+
+impl Mapper for Fds {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4030 => {
+                let mut status = 0;
+                if self.timer_irq {
+                    status |= 0x01;
+                }
+                if self.transferred {
+                    status |= 0x02;
+                }
+                if self.end_of_head {
+                    status |= 0x40;
+                }
+                self.timer_irq = false;
+                self.disk_irq = false;
+                self.transferred = false;
+                status
+            }
+            0x4031 => {
+                self.transferred = false;
+                self.disk_irq = false;
+                self.read_data
+            }
+            0x4032 => {
+                let mut status = 0x40;
+                if self.side.is_none() {
+                    // Not inserted, and so also write protected.
+                    status |= 0b101;
+                }
+                if self.side.is_none() || !self.scanning {
+                    status |= 0b010;
+                }
+                status
+            }
+            // Bit 7 reports a good battery.
+            0x4033 => 0x80,
+            0x4040..=0x4092 if self.sound_enabled => self.audio.read(addr),
+            0x6000..=0xDFFF => self.ram[(addr - 0x6000) as usize],
+            0xE000..=0xFFFF => self.bios[(addr - 0xE000) as usize],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4020..=0x4026 if !self.disk_enabled && addr != 0x4023 => {}
+            0x4020 => self.irq_reload = (self.irq_reload & 0xFF00) | data as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00FF) | (data as u16) << 8,
+            0x4022 => {
+                self.irq_repeat = data & 0x01 != 0;
+                self.irq_enabled = data & 0x02 != 0;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_enabled = data & 0x01 != 0;
+                self.sound_enabled = data & 0x02 != 0;
+                if !self.disk_enabled {
+                    self.irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 => {
+                self.write_data = data;
+                self.transferred = false;
+                self.disk_irq = false;
+            }
+            0x4025 => self.write_control(data),
+            0x4040..=0x4092 if self.sound_enabled => self.audio.write(addr, data),
+            0x6000..=0xDFFF => self.ram[(addr - 0x6000) as usize] = data,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr_ram[addr as usize]
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr_ram[addr as usize] = data;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.disk.data
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.disk.data
+    }
+
+    fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.eject_cycles > 0 {
+            self.eject_cycles -= 1;
+            if self.eject_cycles == 0 {
+                self.side = Some(self.next_side);
+            }
+        }
+        self.clock_timer();
+        self.clock_drive();
+        self.audio.clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn next_disk_side(&mut self) -> Option<usize> {
+        let sides = self.disk.sides();
+        if sides == 0 {
+            return None;
+        }
+        self.next_side = match self.side {
+            Some(side) => (side + 1) % sides,
+            None => (self.next_side + 1) % sides,
+        };
+        self.side = None;
+        self.eject_cycles = EJECT_CYCLES;
+        Some(self.next_side)
+    }
+}
@@ -0,0 +1,250 @@
+/// Output level of the largest sample at full volume, relative to the APU
+/// mix: peak to peak, the FDS at full volume is about 2.4 times a
+/// full-volume APU pulse.
+const FDS_LEVEL: f32 = 0.36 / 2016.0;
+
+/// The master volume bits of $4089 scale the output by 2/2, 2/3, 2/4, 2/5.
+const MASTER_VOLUMES: [f32; 4] = [1.0, 2.0 / 3.0, 0.5, 0.4];
+
+/// How far the modulator counter moves for each value in the mod table;
+/// value 4 resets the counter instead.
+const MOD_STEPS: [i16; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+
+/// The RAM adapter's output goes through an RC lowpass at roughly 2 kHz,
+/// here a one-pole filter running at the CPU rate.
+const LOWPASS: f32 = 0.007;
+
+/// The volume and the modulator gain each have one of these.
+#[derive(Default)]
+struct Envelope {
+    disabled: bool,
+    increase: bool,
+    speed: u8,
+    gain: u8,
+    timer: u32,
+}
+
+impl Envelope {
+    fn write(&mut self, data: u8, master_speed: u8) {
+        self.disabled = data & 0x80 != 0;
+        self.increase = data & 0x40 != 0;
+        self.speed = data & 0x3F;
+        if self.disabled {
+            self.gain = self.speed;
+        }
+        self.reload(master_speed);
+    }
+
+    /// Ticks every 8 * (speed + 1) * master speed CPU cycles.
+    fn reload(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.disabled {
+            return;
+        }
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.reload(master_speed);
+        if self.increase {
+            if self.gain < 32 {
+                self.gain += 1;
+            }
+        } else if self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
+
+/// # FDS audio https://www.nesdev.org/wiki/FDS_audio
+///
+/// One wavetable channel: 64 6-bit samples played at a 12-bit pitch that a
+/// second wavetable of 3-bit steps, the modulator, bends up and down. Both
+/// the volume and the modulation depth have their own envelope.
+///
+///  $4040-$407F wavetable, writable while $4089 bit 7 is set
+///  $4080 volume envelope       $4082-$4083 pitch, halts
+///  $4084 mod envelope          $4085 mod counter
+///  $4086-$4087 mod pitch, halt $4088 mod table, while halted
+///  $4089 write enable, master volume
+///  $408A envelope speed        $4090/$4092 read back the gains
+pub struct FdsAudio {
+    wave: [u8; 64],
+    wave_write: bool,
+    wave_halt: bool,
+    envelope_halt: bool,
+    frequency: u16,
+    wave_accumulator: u32,
+    master_volume: u8,
+    master_speed: u8,
+    volume: Envelope,
+    // Volume changes only take effect on the first sample of the wave.
+    output_volume: u8,
+
+    mod_table: [u8; 64],
+    mod_position: u8,
+    mod_frequency: u16,
+    mod_accumulator: u32,
+    mod_halt: bool,
+    mod_counter: i16,
+    mod_gain: Envelope,
+
+    level: f32,
+    output: f32,
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        FdsAudio {
+            wave: [0; 64],
+            wave_write: false,
+            wave_halt: true,
+            envelope_halt: false,
+            frequency: 0,
+            wave_accumulator: 0,
+            master_volume: 0,
+            master_speed: 0xE8,
+            volume: Envelope::default(),
+            output_volume: 0,
+
+            mod_table: [0; 64],
+            mod_position: 0,
+            mod_frequency: 0,
+            mod_accumulator: 0,
+            mod_halt: true,
+            mod_counter: 0,
+            mod_gain: Envelope::default(),
+
+            level: 0.0,
+            output: 0.0,
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x407F => self.wave[(addr - 0x4040) as usize],
+            0x4090 => self.volume.gain,
+            0x4092 => self.mod_gain.gain,
+            _ => 0,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x407F if self.wave_write => self.wave[(addr - 0x4040) as usize] = data & 0x3F,
+            0x4080 => self.volume.write(data, self.master_speed),
+            0x4082 => self.frequency = (self.frequency & 0x0F00) | data as u16,
+            0x4083 => {
+                self.frequency = (self.frequency & 0x00FF) | ((data & 0x0F) as u16) << 8;
+                self.envelope_halt = data & 0x40 != 0;
+                self.wave_halt = data & 0x80 != 0;
+                if self.wave_halt {
+                    self.wave_accumulator = 0;
+                }
+                if self.envelope_halt {
+                    self.volume.reload(self.master_speed);
+                    self.mod_gain.reload(self.master_speed);
+                }
+            }
+            0x4084 => self.mod_gain.write(data, self.master_speed),
+            0x4085 => self.mod_counter = wrap_counter((data & 0x7F) as i16),
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0x0F00) | data as u16,
+            0x4087 => {
+                self.mod_frequency = (self.mod_frequency & 0x00FF) | ((data & 0x0F) as u16) << 8;
+                self.mod_halt = data & 0x80 != 0;
+                if self.mod_halt {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // The table is 32 entries long; each is played twice.
+            0x4088 if self.mod_halt => {
+                let position = self.mod_position as usize & 0x3E;
+                self.mod_table[position] = data & 0b111;
+                self.mod_table[position + 1] = data & 0b111;
+                self.mod_position = (self.mod_position + 2) & 0x3F;
+            }
+            0x4089 => {
+                self.wave_write = data & 0x80 != 0;
+                self.master_volume = data & 0b11;
+            }
+            0x408A => self.master_speed = data,
+            _ => {}
+        }
+    }
+
+    /// The pitch after modulation, as the chip computes it.
+    fn modulated_frequency(&self) -> u32 {
+        let mut temp = self.mod_counter as i32 * self.mod_gain.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        let mut temp = self.frequency as i32 * temp;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        (self.frequency as i32 + temp).max(0) as u32
+    }
+
+    pub fn clock(&mut self) {
+        if !self.envelope_halt && !self.wave_halt && self.master_speed != 0 {
+            self.volume.clock(self.master_speed);
+            self.mod_gain.clock(self.master_speed);
+        }
+
+        if !self.mod_halt && self.mod_frequency != 0 {
+            self.mod_accumulator += self.mod_frequency as u32;
+            if self.mod_accumulator >= 0x10000 {
+                self.mod_accumulator -= 0x10000;
+                let step = self.mod_table[self.mod_position as usize];
+                self.mod_position = (self.mod_position + 1) & 0x3F;
+                self.mod_counter = if step == 4 {
+                    0
+                } else {
+                    wrap_counter(self.mod_counter + MOD_STEPS[step as usize])
+                };
+            }
+        }
+
+        // While the wavetable is writable the output holds its last level.
+        if !self.wave_write {
+            if !self.wave_halt && self.frequency != 0 {
+                self.wave_accumulator = (self.wave_accumulator + self.modulated_frequency()) & 0x3F_FFFF;
+            }
+            if self.wave_accumulator >> 16 == 0 {
+                self.output_volume = self.volume.gain;
+            }
+            let sample = self.wave[(self.wave_accumulator >> 16) as usize] as f32;
+            let level = sample * self.output_volume.min(32) as f32;
+            self.level = level * MASTER_VOLUMES[self.master_volume as usize] * FDS_LEVEL;
+        }
+        self.output += (self.level - self.output) * LOWPASS;
+    }
+
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The modulator counter is a 7-bit signed value that wraps around.
+fn wrap_counter(value: i16) -> i16 {
+    ((value & 0x7F) ^ 0x40) - 0x40
+}
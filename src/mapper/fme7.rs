@@ -0,0 +1,362 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{bank_offset, Chr, Mapper};
+
+/// The 5B divides M2 by 16 before clocking its tone, noise and envelope
+/// counters.
+const DIVIDER_CYCLES: u8 = 16;
+
+/// Output level of one channel at full volume, relative to the APU mix.
+const SUNSOFT_5B_LEVEL: f32 = 0.3;
+
+lazy_static! {
+    /// The 5B's DAC is logarithmic: 1.5 dB per step of the 5-bit level, with
+    /// level 0 silent.
+    static ref VOLUME_TABLE: [f32; 32] = {
+        let mut table = [0.0; 32];
+        for (level, volume) in table.iter_mut().enumerate().skip(1) {
+            *volume = 10f32.powf(-1.5 * (31 - level) as f32 / 20.0);
+        }
+        table
+    };
+}
+
+#[derive(Default)]
+struct ToneChannel {
+    period: u16,
+    counter: u16,
+    high: bool,
+    volume: u8,
+    use_envelope: bool,
+}
+
+impl ToneChannel {
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period.max(1) {
+            self.counter = 0;
+            self.high = !self.high;
+        }
+    }
+}
+
+/// # 5B envelope https://www.nesdev.org/wiki/Sunsoft_5B_audio#Envelope
+///
+/// A 32-step ramp whose shape register picks between one-shot, repeating,
+/// alternating and holding ramps, like the YM2149's.
+#[derive(Default)]
+struct Envelope {
+    period: u16,
+    counter: u16,
+    step: u8,
+    attack: bool,
+    alternate: bool,
+    hold: bool,
+    repeat: bool,
+    holding: bool,
+}
+
+impl Envelope {
+    fn write_shape(&mut self, data: u8) {
+        self.repeat = data & 0b1000 != 0;
+        self.attack = data & 0b0100 != 0;
+        self.alternate = data & 0b0010 != 0;
+        self.hold = data & 0b0001 != 0;
+        self.step = 0;
+        self.counter = 0;
+        self.holding = false;
+    }
+
+    fn clock(&mut self) {
+        self.counter += 1;
+        if self.counter < self.period.max(1) {
+            return;
+        }
+        self.counter = 0;
+        if self.holding {
+            return;
+        }
+        if self.step < 31 {
+            self.step += 1;
+            return;
+        }
+        if !self.repeat {
+            // Shapes $0-$7 always end silent.
+            self.holding = true;
+            self.attack = false;
+        } else if self.hold {
+            self.holding = true;
+            self.attack ^= self.alternate;
+        } else {
+            self.attack ^= self.alternate;
+            self.step = 0;
+        }
+    }
+
+    fn level(&self) -> u8 {
+        if self.attack {
+            self.step
+        } else {
+            31 - self.step
+        }
+    }
+}
+
+/// # Sunsoft 5B audio https://www.nesdev.org/wiki/Sunsoft_5B_audio
+///
+/// A YM2149F, the AY-3-8910's sibling: three square channels that can each
+/// mix in a shared noise generator, a shared envelope, and a logarithmic
+/// 4-bit volume per channel. Registers are picked through $C000 and written
+/// through $E000.
+///
+///  R0-R5 tone periods A-C (12 bits)   R6 noise period (5 bits)
+///  R7 tone/noise disables             R8-RA volume, bit 4 envelope
+///  RB-RC envelope period              RD envelope shape
+struct Sunsoft5b {
+    register: u8,
+    divider: u8,
+    tones: [ToneChannel; 3],
+    tone_disable: u8,
+    noise_disable: u8,
+    noise_period: u8,
+    noise_counter: u8,
+    noise_half: bool,
+    lfsr: u32,
+    envelope: Envelope,
+}
+
+impl Sunsoft5b {
+    fn new() -> Self {
+        Sunsoft5b {
+            register: 0,
+            divider: 0,
+            tones: Default::default(),
+            tone_disable: 0,
+            noise_disable: 0,
+            noise_period: 0,
+            noise_counter: 0,
+            noise_half: false,
+            lfsr: 1,
+            envelope: Envelope::default(),
+        }
+    }
+
+    fn write(&mut self, data: u8) {
+        match self.register {
+            0x0 | 0x2 | 0x4 => {
+                let tone = &mut self.tones[self.register as usize / 2];
+                tone.period = (tone.period & 0x0F00) | data as u16;
+            }
+            0x1 | 0x3 | 0x5 => {
+                let tone = &mut self.tones[self.register as usize / 2];
+                tone.period = (tone.period & 0x00FF) | ((data & 0x0F) as u16) << 8;
+            }
+            0x6 => self.noise_period = data & 0x1F,
+            0x7 => {
+                self.tone_disable = data & 0b111;
+                self.noise_disable = (data >> 3) & 0b111;
+            }
+            0x8..=0xA => {
+                let tone = &mut self.tones[self.register as usize - 8];
+                tone.volume = data & 0x0F;
+                tone.use_envelope = data & 0x10 != 0;
+            }
+            0xB => self.envelope.period = (self.envelope.period & 0xFF00) | data as u16,
+            0xC => self.envelope.period = (self.envelope.period & 0x00FF) | (data as u16) << 8,
+            0xD => self.envelope.write_shape(data),
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < DIVIDER_CYCLES {
+            return;
+        }
+        self.divider = 0;
+
+        for tone in self.tones.iter_mut() {
+            tone.clock();
+        }
+        self.envelope.clock();
+        // Noise runs at half the tone rate, from a 17-bit LFSR.
+        self.noise_half = !self.noise_half;
+        if self.noise_half {
+            self.noise_counter += 1;
+            if self.noise_counter >= self.noise_period.max(1) {
+                self.noise_counter = 0;
+                let feedback = (self.lfsr ^ (self.lfsr >> 3)) & 1;
+                self.lfsr = (self.lfsr >> 1) | feedback << 16;
+            }
+        }
+    }
+
+    fn output(&self) -> f32 {
+        let noise = self.lfsr & 1 != 0;
+        let mut sum = 0.0;
+        for (channel, tone) in self.tones.iter().enumerate() {
+            let tone_on = tone.high || self.tone_disable & (1 << channel) != 0;
+            let noise_on = noise || self.noise_disable & (1 << channel) != 0;
+            if tone_on && noise_on {
+                let level = if tone.use_envelope {
+                    self.envelope.level()
+                } else if tone.volume == 0 {
+                    0
+                } else {
+                    tone.volume * 2 + 1
+                };
+                sum += VOLUME_TABLE[level as usize];
+            }
+        }
+        sum * SUNSOFT_5B_LEVEL
+    }
+}
+
+/// # Sunsoft FME-7 / 5B (mapper 69) https://www.nesdev.org/wiki/Sunsoft_FME-7
+///
+/// $8000 selects one of 16 internal registers and $A000 writes it:
+///
+///  0-7 CHR 1 KB banks          8 $6000: bits 0-5 bank, 6 RAM, 7 RAM enable
+///  9-B 8 KB PRG at $8000-$DFFF C mirroring
+///  D IRQ control, acknowledge  E-F IRQ counter low/high
+///
+/// $E000-$FFFF is fixed to the last bank. The 5B is the FME-7 with the
+/// sound chip built in; its ports at $C000/$E000 are simply unused on
+/// plain FME-7 boards.
+pub struct Fme7 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Chr,
+
+    command: u8,
+    chr_banks: [u8; 8],
+    prg_banks: [u8; 4],
+    mirroring: Mirroring,
+
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+
+    audio: Sunsoft5b,
+}
+
+impl Fme7 {
+    pub fn new(rom: Rom) -> Self {
+        Fme7 {
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            mirroring: Mirroring::Vertical,
+
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+
+            audio: Sunsoft5b::new(),
+        }
+    }
+
+    fn write_parameter(&mut self, data: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[self.command as usize] = data,
+            0x8..=0xB => self.prg_banks[self.command as usize - 8] = data,
+            0xC => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                }
+            }
+            0xD => {
+                self.irq_enabled = data & 0x01 != 0;
+                self.irq_counter_enabled = data & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | data as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | (data as u16) << 8,
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => {
+                let control = self.prg_banks[0];
+                match (control & 0x40 != 0, control & 0x80 != 0) {
+                    (true, true) => self.prg_ram[(addr - 0x6000) as usize],
+                    (true, false) => 0,
+                    _ => self.prg_rom[bank_offset(self.prg_rom.len(), (control & 0x3F) as usize, 0x2000, addr)],
+                }
+            }
+            0x8000..=0xDFFF => {
+                let bank = self.prg_banks[1 + (addr as usize - 0x8000) / 0x2000] as usize & 0x3F;
+                self.prg_rom[bank_offset(self.prg_rom.len(), bank, 0x2000, addr)]
+            }
+            0xE000..=0xFFFF => self.prg_rom[self.prg_rom.len() - 0x2000 + (addr - 0xE000) as usize],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_banks[0] & 0xC0 == 0xC0 => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            0x8000..=0x9FFF => self.command = data & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(data),
+            0xC000..=0xDFFF => self.audio.register = data & 0x0F,
+            0xE000..=0xFFFF => self.audio.write(data),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize >> 10] as usize;
+        self.chr.read(bank_offset(self.chr.size(), bank, 0x400, addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        let bank = self.chr_banks[addr as usize >> 10] as usize;
+        let offset = bank_offset(self.chr.size(), bank, 0x400, addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    /// The 16-bit IRQ counter counts down every CPU cycle while enabled and
+    /// raises the IRQ when it wraps from $0000 to $FFFF.
+    fn cpu_clock(&mut self) {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
@@ -0,0 +1,94 @@
+use crate::cartridge::Mirroring;
+use crate::mapper::Mapper;
+
+/// # Four-screen mirroring https://www.nesdev.org/wiki/Mirroring#4-Screen
+///
+/// Boards with the four-screen bit set in the header carry 2 KB of extra
+/// VRAM, so all four nametables are distinct: $2000 and $2400 stay in the
+/// console's CIRAM, $2800 and $2C00 live on the cartridge. Any board can be
+/// wired like this, so it wraps the board's own mapper and passes everything
+/// else through. A board that maps its nametables itself still gets the
+/// first say over them.
+pub struct FourScreen {
+    mapper: Box<dyn Mapper>,
+    vram: [u8; 0x800],
+}
+
+impl FourScreen {
+    pub fn new(mapper: Box<dyn Mapper>) -> Self {
+        FourScreen { mapper, vram: [0; 0x800] }
+    }
+}
+
+impl Mapper for FourScreen {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        self.mapper.read_prg(addr)
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        self.mapper.write_prg(addr, data);
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.mapper.read_chr(addr)
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.mapper.write_chr(addr, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::FourScreen
+    }
+
+    fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
+    fn cpu_clock(&mut self) {
+        self.mapper.cpu_clock();
+    }
+
+    fn read_nametable(&mut self, addr: u16, ciram: &[u8]) -> Option<u8> {
+        if let Some(data) = self.mapper.read_nametable(addr, ciram) {
+            return Some(data);
+        }
+        let index = (addr & 0x0fff) as usize;
+        Some(match index {
+            0x000..=0x7ff => ciram[index],
+            _ => self.vram[index - 0x800],
+        })
+    }
+
+    fn write_nametable(&mut self, addr: u16, data: u8, ciram: &mut [u8]) -> bool {
+        if self.mapper.write_nametable(addr, data, ciram) {
+            return true;
+        }
+        let index = (addr & 0x0fff) as usize;
+        match index {
+            0x000..=0x7ff => ciram[index] = data,
+            _ => self.vram[index - 0x800] = data,
+        }
+        true
+    }
+
+    fn ppu_register_write(&mut self, addr: u16, data: u8) {
+        self.mapper.ppu_register_write(addr, data);
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        self.mapper.prg_ram()
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        self.mapper.prg_ram_mut()
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.mapper.audio_output()
+    }
+
+    fn next_disk_side(&mut self) -> Option<usize> {
+        self.mapper.next_disk_side()
+    }
+}
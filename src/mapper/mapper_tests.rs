@@ -0,0 +1,827 @@
+#[cfg(test)]
+mod test {
+    use crate::cartridge::{Mirroring, Rom};
+    use crate::mapper::*;
+    use crate::fds::{update_crc, FdsDisk, RAW_SIDE_SIZE};
+
+    fn test_rom(mapper: u16, submapper: u8, prg_banks: usize, chr_banks: usize) -> Rom {
+        // Every 16 KB PRG bank / 8 KB CHR bank is filled with its own index.
+        let mut prg_rom = Vec::new();
+        for bank in 0..prg_banks {
+            prg_rom.extend(vec![bank as u8; 0x4000]);
+        }
+        let mut chr_rom = Vec::new();
+        for bank in 0..chr_banks {
+            chr_rom.extend(vec![bank as u8; 0x2000]);
+        }
+        Rom {
+            submapper,
+            ..Rom::test_rom(mapper, prg_rom, chr_rom)
+        }
+    }
+
+    #[test]
+    fn test_nrom_mirrors_16k_prg() {
+        let mut mapper = from_rom(test_rom(0, 0, 1, 1)).unwrap();
+        assert_eq!(mapper.read_prg(0x8000), 0);
+        assert_eq!(mapper.read_prg(0xC000), 0);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_uxrom_switches_lower_bank() {
+        let mut mapper = from_rom(test_rom(2, 0, 8, 0)).unwrap();
+        assert_eq!(mapper.read_prg(0x8000), 0);
+        assert_eq!(mapper.read_prg(0xC000), 7);
+
+        mapper.write_prg(0x8000, 5);
+        assert_eq!(mapper.read_prg(0x8000), 5);
+        assert_eq!(mapper.read_prg(0xFFFF), 7);
+    }
+
+    #[test]
+    fn test_uxrom_bus_conflicts() {
+        let mut mapper = from_rom(test_rom(2, 2, 8, 0)).unwrap();
+        // The fixed bank is filled with 7, so 0b1110 & 0b0111 selects bank 6.
+        mapper.write_prg(0xC000, 0b1110);
+        assert_eq!(mapper.read_prg(0x8000), 6);
+    }
+
+    #[test]
+    fn test_uxrom_chr_ram() {
+        let mut mapper = from_rom(test_rom(2, 0, 2, 0)).unwrap();
+        mapper.write_chr(0x1234, 0x55);
+        assert_eq!(mapper.read_chr(0x1234), 0x55);
+    }
+
+    #[test]
+    fn test_cnrom_switches_chr() {
+        let mut mapper = from_rom(test_rom(3, 0, 2, 4)).unwrap();
+        mapper.write_prg(0x8000, 3);
+        assert_eq!(mapper.read_chr(0x0000), 3);
+        mapper.write_chr(0x0000, 0xff);
+        assert_eq!(mapper.read_chr(0x0000), 3);
+    }
+
+    #[test]
+    fn test_axrom_prg_and_single_screen() {
+        let mut mapper = from_rom(test_rom(7, 1, 8, 0)).unwrap();
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+
+        mapper.write_prg(0x8000, 0b0001_0011);
+        assert_eq!(mapper.read_prg(0x8000), 6);
+        assert_eq!(mapper.read_prg(0xC000), 7);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    fn mmc1_write(mapper: &mut Box<dyn Mapper>, addr: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.write_prg(addr, value >> bit);
+        }
+    }
+
+    #[test]
+    fn test_mmc1_shift_register_and_prg_modes() {
+        let mut mapper = from_rom(test_rom(1, 0, 8, 0)).unwrap();
+        // Power-on: $C000 fixed to the last bank.
+        assert_eq!(mapper.read_prg(0xC000), 7);
+
+        mmc1_write(&mut mapper, 0xE000, 3);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_prg(0xC000), 7);
+
+        // A write with bit 7 set drops the bits shifted in so far.
+        mapper.write_prg(0xE000, 1);
+        mapper.write_prg(0xE000, 0x80);
+        mmc1_write(&mut mapper, 0xE000, 5);
+        assert_eq!(mapper.read_prg(0x8000), 5);
+
+        // Fixed first bank at $8000, switchable at $C000.
+        mmc1_write(&mut mapper, 0x8000, 0b0_1000);
+        assert_eq!(mapper.read_prg(0x8000), 0);
+        assert_eq!(mapper.read_prg(0xC000), 5);
+
+        // 32 KB mode ignores the low bit of the bank number.
+        mmc1_write(&mut mapper, 0x8000, 0b0_0000);
+        assert_eq!(mapper.read_prg(0x8000), 4);
+        assert_eq!(mapper.read_prg(0xC000), 5);
+    }
+
+    #[test]
+    fn test_mmc1_chr_banks_mirroring_and_prg_ram() {
+        let mut rom = test_rom(1, 0, 2, 0);
+        rom.chr_rom = (0..32).flat_map(|bank| vec![bank as u8; 0x1000]).collect();
+        let mut mapper = from_rom(rom).unwrap();
+
+        mmc1_write(&mut mapper, 0xA000, 5);
+        mmc1_write(&mut mapper, 0xC000, 9);
+        assert_eq!(mapper.read_chr(0x0000), 4);
+        assert_eq!(mapper.read_chr(0x1000), 5);
+        mmc1_write(&mut mapper, 0x8000, 0b1_0010);
+        assert_eq!(mapper.read_chr(0x0000), 5);
+        assert_eq!(mapper.read_chr(0x1000), 9);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+
+        mmc1_write(&mut mapper, 0x8000, 0b1_0001);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+        mmc1_write(&mut mapper, 0x8000, 0b1_0011);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+
+        mapper.write_prg(0x6000, 0x42);
+        assert_eq!(mapper.read_prg(0x6000), 0x42);
+        mmc1_write(&mut mapper, 0xE000, 0b1_0000);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+    }
+
+    #[test]
+    fn test_mmc1_512k_prg() {
+        let mut mapper = from_rom(test_rom(1, 0, 32, 0)).unwrap();
+        assert_eq!(mapper.read_prg(0xC000), 15);
+        mmc1_write(&mut mapper, 0xA000, 0b1_0000);
+        assert_eq!(mapper.read_prg(0x8000), 16);
+        assert_eq!(mapper.read_prg(0xC000), 31);
+    }
+
+    #[test]
+    fn test_four_screen_vram() {
+        let mut rom = test_rom(0, 0, 1, 1);
+        rom.screen_mirroring = Mirroring::FourScreen;
+        let mut mapper = from_rom(rom).unwrap();
+        assert_eq!(mapper.mirroring(), Mirroring::FourScreen);
+
+        let mut ciram = [0; 0x800];
+        for (i, addr) in [0x2000, 0x2400, 0x2800, 0x2c00].into_iter().enumerate() {
+            assert!(mapper.write_nametable(addr, i as u8, &mut ciram));
+        }
+        assert_eq!(ciram[0x000], 0);
+        assert_eq!(ciram[0x400], 1);
+        assert_eq!(mapper.read_nametable(0x2800, &ciram), Some(2));
+        assert_eq!(mapper.read_nametable(0x2c00, &ciram), Some(3));
+    }
+
+    #[test]
+    fn test_four_screen_defers_to_the_board() {
+        let mut rom = test_rom(5, 0, 2, 1);
+        rom.screen_mirroring = Mirroring::FourScreen;
+        let mut mapper = from_rom(rom).unwrap();
+        // MMC5 fill mode on every nametable.
+        mapper.write_prg(0x5105, 0xff);
+        mapper.write_prg(0x5106, 0x42);
+
+        let mut ciram = [0; 0x800];
+        assert_eq!(mapper.read_nametable(0x2800, &ciram), Some(0x42));
+        assert!(mapper.write_nametable(0x2c00, 1, &mut ciram));
+        assert_eq!(mapper.read_nametable(0x2c00, &ciram), Some(0x42));
+    }
+
+    fn mmc3_rom(submapper: u8) -> Rom {
+        // 1 KB CHR banks filled with their own index make CHR mapping visible.
+        let mut rom = test_rom(4, submapper, 8, 0);
+        rom.chr_rom = (0..256).flat_map(|bank| vec![bank as u8; 0x400]).collect();
+        rom
+    }
+
+    fn mmc3_scanline(mapper: &mut Box<dyn Mapper>) {
+        mapper.read_chr(0x0000);
+        for _ in 0..4 {
+            mapper.cpu_clock();
+        }
+        mapper.read_chr(0x1000);
+    }
+
+    #[test]
+    fn test_mmc3_prg_modes() {
+        let mut mapper = from_rom(mmc3_rom(0)).unwrap();
+        mapper.write_prg(0x8000, 6);
+        mapper.write_prg(0x8001, 2);
+        mapper.write_prg(0x8000, 7);
+        mapper.write_prg(0x8001, 5);
+
+        // 16 KB test banks hold two 8 KB MMC3 banks each.
+        assert_eq!(mapper.read_prg(0x8000), 1);
+        assert_eq!(mapper.read_prg(0xA000), 2);
+        assert_eq!(mapper.read_prg(0xC000), 7);
+        assert_eq!(mapper.read_prg(0xE000), 7);
+
+        mapper.write_prg(0x8000, 0b0100_0000);
+        assert_eq!(mapper.read_prg(0x8000), 7);
+        assert_eq!(mapper.read_prg(0xC000), 1);
+    }
+
+    #[test]
+    fn test_mmc3_single_prg_bank() {
+        let mut mapper = from_rom(Rom::test_rom(4, vec![3; 0x2000], vec![])).unwrap();
+        assert_eq!(mapper.read_prg(0xC000), 3);
+        assert_eq!(mapper.read_prg(0xFFFF), 3);
+    }
+
+    #[test]
+    fn test_mmc3_chr_inversion() {
+        let mut mapper = from_rom(mmc3_rom(0)).unwrap();
+        mapper.write_prg(0x8000, 0);
+        mapper.write_prg(0x8001, 9);
+        mapper.write_prg(0x8000, 2);
+        mapper.write_prg(0x8001, 40);
+
+        assert_eq!(mapper.read_chr(0x0000), 8);
+        assert_eq!(mapper.read_chr(0x0400), 9);
+        assert_eq!(mapper.read_chr(0x1000), 40);
+
+        mapper.write_prg(0x8000, 0b1000_0000);
+        assert_eq!(mapper.read_chr(0x1000), 8);
+        assert_eq!(mapper.read_chr(0x0000), 40);
+    }
+
+    #[test]
+    fn test_mmc3_mirroring_and_prg_ram_protect() {
+        let mut mapper = from_rom(mmc3_rom(0)).unwrap();
+        mapper.write_prg(0xA000, 1);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+
+        mapper.write_prg(0x6000, 0x42);
+        assert_eq!(mapper.read_prg(0x6000), 0x42);
+
+        mapper.write_prg(0xA001, 0b1100_0000);
+        mapper.write_prg(0x6000, 0x24);
+        assert_eq!(mapper.read_prg(0x6000), 0x42);
+
+        mapper.write_prg(0xA001, 0);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+    }
+
+    #[test]
+    fn test_mmc3_scanline_irq() {
+        let mut mapper = from_rom(mmc3_rom(0)).unwrap();
+        mapper.write_prg(0xC000, 2);
+        mapper.write_prg(0xC001, 0);
+        mapper.write_prg(0xE001, 0);
+
+        mmc3_scanline(&mut mapper);
+        mmc3_scanline(&mut mapper);
+        assert!(!mapper.irq());
+        mmc3_scanline(&mut mapper);
+        assert!(mapper.irq());
+
+        mapper.write_prg(0xE000, 0);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn test_mmc3_a12_filter() {
+        let mut mapper = from_rom(mmc3_rom(0)).unwrap();
+        mapper.write_prg(0xC000, 0);
+        mapper.write_prg(0xE001, 0);
+
+        // A12 toggling without M2 cycles in between is ignored.
+        mapper.read_chr(0x0000);
+        mapper.read_chr(0x1000);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn test_mmc3_zero_latch_sharp_vs_nec() {
+        let mut sharp = from_rom(mmc3_rom(0)).unwrap();
+        let mut nec = from_rom(mmc3_rom(4)).unwrap();
+        for mapper in [&mut sharp, &mut nec] {
+            mapper.write_prg(0xC000, 0);
+            mapper.write_prg(0xE001, 0);
+            mmc3_scanline(mapper);
+            mapper.write_prg(0xE000, 0);
+            mapper.write_prg(0xE001, 0);
+            mmc3_scanline(mapper);
+        }
+        // A latch of 0 keeps firing on Sharp chips but only once on NEC ones.
+        assert!(sharp.irq());
+        assert!(!nec.irq());
+    }
+
+    fn mmc5_scanline(mapper: &mut Box<dyn Mapper>, ciram: &[u8]) {
+        for _ in 0..3 {
+            mapper.read_nametable(0x2000, ciram);
+        }
+        mapper.read_chr(0x0000);
+    }
+
+    #[test]
+    fn test_mmc5_prg_modes_and_ram() {
+        let mut mapper = from_rom(test_rom(5, 0, 8, 1)).unwrap();
+        // Power-on state: mode 3 with the last bank at $E000.
+        assert_eq!(mapper.read_prg(0xE000), 7);
+
+        mapper.write_prg(0x5100, 1);
+        mapper.write_prg(0x5115, 0x80 | 4);
+        assert_eq!(mapper.read_prg(0x8000), 2);
+        assert_eq!(mapper.read_prg(0xA000), 2);
+
+        mapper.write_prg(0x6000, 0x42);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+        mapper.write_prg(0x5102, 2);
+        mapper.write_prg(0x5103, 1);
+        mapper.write_prg(0x6000, 0x42);
+        assert_eq!(mapper.read_prg(0x6000), 0x42);
+
+        // $5115 without bit 7 maps PRG RAM into $8000-$BFFF.
+        mapper.write_prg(0x5113, 1);
+        mapper.write_prg(0x5115, 0);
+        mapper.write_prg(0x8000, 0x24);
+        assert_eq!(mapper.read_prg(0x8000), 0x24);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+    }
+
+    #[test]
+    fn test_mmc5_multiplier() {
+        let mut mapper = from_rom(test_rom(5, 0, 2, 1)).unwrap();
+        mapper.write_prg(0x5205, 200);
+        mapper.write_prg(0x5206, 100);
+        assert_eq!(mapper.read_prg(0x5205), (20000u16 & 0xff) as u8);
+        assert_eq!(mapper.read_prg(0x5206), (20000u16 >> 8) as u8);
+    }
+
+    #[test]
+    fn test_mmc5_nametable_mapping_and_fill() {
+        let mut mapper = from_rom(test_rom(5, 0, 2, 1)).unwrap();
+        let mut ciram = [0u8; 2048];
+        ciram[0x400 + 5] = 0x11;
+
+        // NT0 -> CIRAM B, NT1 -> ExRAM, NT2 -> fill, NT3 -> CIRAM A.
+        mapper.write_prg(0x5105, 0b00_11_10_01);
+        mapper.write_prg(0x5104, 2);
+        mapper.write_prg(0x5C05, 0x22);
+        mapper.write_prg(0x5104, 0);
+        mapper.write_prg(0x5106, 0x33);
+        mapper.write_prg(0x5107, 2);
+
+        assert_eq!(mapper.read_nametable(0x2005, &ciram), Some(0x11));
+        assert_eq!(mapper.read_nametable(0x2405, &ciram), Some(0x22));
+        assert_eq!(mapper.read_nametable(0x2805, &ciram), Some(0x33));
+        assert_eq!(mapper.read_nametable(0x2BC0, &ciram), Some(0xAA));
+
+        assert!(mapper.write_nametable(0x2C07, 0x44, &mut ciram));
+        assert_eq!(ciram[7], 0x44);
+    }
+
+    #[test]
+    fn test_mmc5_scanline_irq() {
+        let mut mapper = from_rom(test_rom(5, 0, 2, 1)).unwrap();
+        let ciram = [0u8; 2048];
+        mapper.write_prg(0x5203, 2);
+        mapper.write_prg(0x5204, 0x80);
+
+        mmc5_scanline(&mut mapper, &ciram);
+        assert_eq!(mapper.read_prg(0x5204), 0b0100_0000);
+        mmc5_scanline(&mut mapper, &ciram);
+        mmc5_scanline(&mut mapper, &ciram);
+        assert!(mapper.irq());
+        assert_eq!(mapper.read_prg(0x5204), 0b1100_0000);
+        assert!(!mapper.irq());
+
+        // No PPU reads for a few CPU cycles means the frame is over.
+        for _ in 0..3 {
+            mapper.cpu_clock();
+        }
+        assert_eq!(mapper.read_prg(0x5204), 0);
+    }
+
+    #[test]
+    fn test_mmc5_pulse_audio() {
+        let mut mapper = from_rom(test_rom(5, 0, 2, 1)).unwrap();
+        assert_eq!(mapper.audio_output(), 0.0);
+
+        mapper.write_prg(0x5015, 1);
+        mapper.write_prg(0x5000, 0b1011_1111);
+        mapper.write_prg(0x5002, 0x10);
+        mapper.write_prg(0x5003, 0x08);
+        assert_eq!(mapper.read_prg(0x5015), 1);
+
+        let mut heard = false;
+        for _ in 0..0x200 {
+            mapper.cpu_clock();
+            heard |= mapper.audio_output() > 0.0;
+        }
+        assert!(heard);
+    }
+
+    #[test]
+    fn test_vrc4_address_line_variants() {
+        // VRC4e (mapper 23 submapper 2) selects registers with A2/A3.
+        let mut mapper = from_rom(test_rom(23, 2, 8, 0)).unwrap();
+        mapper.write_prg(0x8000, 4);
+        mapper.write_prg(0x9008, 0b10);
+        assert_eq!(mapper.read_prg(0xC000), 2);
+        assert_eq!(mapper.read_prg(0x8000), 7);
+
+        // VRC4a (mapper 21 submapper 1) uses A1/A2 for the same registers.
+        let mut mapper = from_rom(test_rom(21, 1, 8, 0)).unwrap();
+        mapper.write_prg(0x9000, 3);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+        mapper.write_prg(0x9004, 0b10);
+        mapper.write_prg(0x8000, 4);
+        assert_eq!(mapper.read_prg(0xC000), 2);
+    }
+
+    #[test]
+    fn test_vrc4_chr_nibbles() {
+        let mut rom = test_rom(25, 1, 8, 0);
+        rom.chr_rom = (0..256).flat_map(|bank| vec![bank as u8; 0x400]).collect();
+        let mut mapper = from_rom(rom).unwrap();
+        // VRC4b: A1 is register bit 0, A0 is register bit 1.
+        mapper.write_prg(0xC000, 0x5);
+        mapper.write_prg(0xC002, 0x3);
+        assert_eq!(mapper.read_chr(0x0800), 0x35);
+    }
+
+    #[test]
+    fn test_vrc_irq_cycle_mode() {
+        let mut mapper = from_rom(test_rom(21, 0, 8, 0)).unwrap();
+        mapper.write_prg(0xF000, 0x0E);
+        mapper.write_prg(0xF002, 0x0F);
+        mapper.write_prg(0xF004, 0b110);
+
+        mapper.cpu_clock();
+        assert!(!mapper.irq());
+        mapper.cpu_clock();
+        assert!(mapper.irq());
+
+        mapper.write_prg(0xF006, 0);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn test_vrc_irq_scanline_mode() {
+        let mut mapper = from_rom(test_rom(24, 0, 8, 0)).unwrap();
+        mapper.write_prg(0xF000, 0xFE);
+        mapper.write_prg(0xF001, 0b010);
+
+        // Two scanlines of 113.667 CPU cycles each.
+        for _ in 0..227 {
+            mapper.cpu_clock();
+        }
+        assert!(!mapper.irq());
+        mapper.cpu_clock();
+        assert!(mapper.irq());
+    }
+
+    #[test]
+    fn test_vrc6_banking_and_audio() {
+        let mut mapper = from_rom(test_rom(26, 0, 8, 0)).unwrap();
+        mapper.write_prg(0x8000, 3);
+        mapper.write_prg(0xC000, 5);
+        assert_eq!(mapper.read_prg(0x8000), 3);
+        assert_eq!(mapper.read_prg(0xC000), 2);
+        assert_eq!(mapper.read_prg(0xE000), 7);
+
+        mapper.write_prg(0xB003, 0b0000_0100);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+
+        // Mapper 26 swaps A0/A1, so $9001 is the pulse enable register.
+        mapper.write_prg(0x9000, 0b1000_1111);
+        mapper.write_prg(0x9001, 0x80);
+        mapper.cpu_clock();
+        assert!((mapper.audio_output() - 15.0 * 0.01).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_vrc7_fm_key_on() {
+        let mut mapper = from_rom(test_rom(85, 0, 8, 0)).unwrap();
+        mapper.write_prg(0x8010, 5);
+        assert_eq!(mapper.read_prg(0xA000), 2);
+
+        mapper.write_prg(0x9010, 0x30);
+        mapper.write_prg(0x9030, 0x30);
+        mapper.write_prg(0x9010, 0x10);
+        mapper.write_prg(0x9030, 0xAC);
+        mapper.write_prg(0x9010, 0x20);
+        mapper.write_prg(0x9030, 0b0001_1000);
+
+        let mut peak: f32 = 0.0;
+        for _ in 0..36 * 500 {
+            mapper.cpu_clock();
+            peak = peak.max(mapper.audio_output().abs());
+        }
+        assert!(peak > 0.01);
+    }
+
+    #[test]
+    fn test_namco163_banking_and_nametables() {
+        let mut mapper = from_rom(test_rom(19, 0, 8, 4)).unwrap();
+        mapper.write_prg(0xE000, 4);
+        mapper.write_prg(0xE800, 7);
+        mapper.write_prg(0xF000, 9);
+        assert_eq!(mapper.read_prg(0x8000), 2);
+        assert_eq!(mapper.read_prg(0xA000), 3);
+        assert_eq!(mapper.read_prg(0xC000), 4);
+        assert_eq!(mapper.read_prg(0xE000), 7);
+
+        mapper.write_prg(0x8800, 17);
+        assert_eq!(mapper.read_chr(0x0400), 2);
+
+        // $E0-$FF pick console nametable RAM, lower values CHR ROM.
+        let mut ciram = [0u8; 0x800];
+        mapper.write_prg(0xC000, 0xE1);
+        mapper.write_prg(0xC800, 25);
+        assert!(mapper.write_nametable(0x2010, 0xAB, &mut ciram));
+        assert_eq!(ciram[0x410], 0xAB);
+        assert_eq!(mapper.read_nametable(0x2410, &ciram), Some(3));
+
+        // PRG RAM only takes writes once unlocked through $F800.
+        mapper.write_prg(0x6000, 0x55);
+        assert_eq!(mapper.read_prg(0x6000), 0);
+        mapper.write_prg(0xF800, 0x40);
+        mapper.write_prg(0x6000, 0x55);
+        assert_eq!(mapper.read_prg(0x6000), 0x55);
+    }
+
+    #[test]
+    fn test_namco163_irq() {
+        let mut mapper = from_rom(test_rom(19, 0, 8, 0)).unwrap();
+        mapper.write_prg(0x5000, 0xFD);
+        mapper.write_prg(0x5800, 0xFF);
+        mapper.cpu_clock();
+        assert!(!mapper.irq());
+        mapper.cpu_clock();
+        assert!(mapper.irq());
+        // The counter stops at $7FFF.
+        mapper.cpu_clock();
+        assert_eq!(mapper.read_prg(0x5000), 0xFF);
+        mapper.write_prg(0x5800, 0);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn test_namco163_wavetable_audio() {
+        let mut mapper = from_rom(test_rom(19, 0, 8, 0)).unwrap();
+        // A 4-sample wave at nibble 0: 15, 15, 0, 0.
+        mapper.write_prg(0xF800, 0x80);
+        mapper.write_prg(0x4800, 0xFF);
+        mapper.write_prg(0x4800, 0x00);
+        // Channel 7, the only one enabled: frequency $10000 steps one sample
+        // per update, wave length 4, volume 15.
+        mapper.write_prg(0xF800, 0x80 | 0x78);
+        for data in [0x00, 0x00, 0x00, 0x00, 0xFC | 0x01, 0x00, 0x00, 0x0F] {
+            mapper.write_prg(0x4800, data);
+        }
+
+        let mut levels = Vec::new();
+        for _ in 0..4 * 15 {
+            mapper.cpu_clock();
+            levels.push(mapper.audio_output());
+        }
+        let high = 7.0 * 15.0 * 0.00125;
+        let low = -8.0 * 15.0 * 0.00125;
+        let samples: Vec<f32> = (0..4).map(|slot| levels[slot * 15 + 14]).collect();
+        assert!((samples[0] - high).abs() < 1e-6);
+        assert!((samples[1] - low).abs() < 1e-6);
+        assert!((samples[2] - low).abs() < 1e-6);
+        assert!((samples[3] - high).abs() < 1e-6);
+
+        // Bit 6 of $E000 silences the chip.
+        mapper.write_prg(0xE000, 0x40);
+        assert_eq!(mapper.audio_output(), 0.0);
+    }
+
+    #[test]
+    fn test_fme7_banking() {
+        let mut mapper = from_rom(test_rom(69, 0, 8, 4)).unwrap();
+        mapper.write_prg(0x8000, 0x9);
+        mapper.write_prg(0xA000, 4);
+        mapper.write_prg(0x8000, 0xB);
+        mapper.write_prg(0xA000, 9);
+        assert_eq!(mapper.read_prg(0x8000), 2);
+        assert_eq!(mapper.read_prg(0xC000), 4);
+        assert_eq!(mapper.read_prg(0xE000), 7);
+
+        // $6000 maps ROM until register 8 selects and enables RAM.
+        mapper.write_prg(0x8000, 0x8);
+        mapper.write_prg(0xA000, 0x03);
+        assert_eq!(mapper.read_prg(0x6000), 1);
+        mapper.write_prg(0xA000, 0xC0);
+        mapper.write_prg(0x6000, 0x42);
+        assert_eq!(mapper.read_prg(0x6000), 0x42);
+
+        mapper.write_prg(0x8000, 0x2);
+        mapper.write_prg(0xA000, 9);
+        assert_eq!(mapper.read_chr(0x0800), 1);
+
+        mapper.write_prg(0x8000, 0xC);
+        mapper.write_prg(0xA000, 3);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn test_fme7_cycle_irq() {
+        let mut mapper = from_rom(test_rom(69, 0, 8, 0)).unwrap();
+        mapper.write_prg(0x8000, 0xE);
+        mapper.write_prg(0xA000, 2);
+        mapper.write_prg(0x8000, 0xF);
+        mapper.write_prg(0xA000, 0);
+        mapper.write_prg(0x8000, 0xD);
+        mapper.write_prg(0xA000, 0x81);
+        for _ in 0..3 {
+            assert!(!mapper.irq());
+            mapper.cpu_clock();
+        }
+        assert!(mapper.irq());
+        mapper.write_prg(0xA000, 0x81);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn test_sunsoft5b_square_and_envelope() {
+        let mut mapper = from_rom(test_rom(69, 0, 8, 0)).unwrap();
+        let mut write = |register: u8, data: u8| {
+            mapper.write_prg(0xC000, register);
+            mapper.write_prg(0xE000, data);
+        };
+        // Channel A: tone only, period 2, full volume.
+        write(0x0, 2);
+        write(0x7, 0b0011_1110);
+        write(0x8, 0x0F);
+
+        // The square toggles every 16 * 2 CPU cycles.
+        let mut levels = Vec::new();
+        for _ in 0..128 {
+            mapper.cpu_clock();
+            levels.push(mapper.audio_output());
+        }
+        let full = 0.3;
+        assert!(levels.iter().all(|&level| level == 0.0 || (level - full).abs() < 1e-6));
+        let edges = levels.windows(2).filter(|pair| pair[0] != pair[1]).count();
+        assert_eq!(edges, 4);
+
+        // A decaying one-shot envelope ends silent.
+        let mut write = |register: u8, data: u8| {
+            mapper.write_prg(0xC000, register);
+            mapper.write_prg(0xE000, data);
+        };
+        write(0x7, 0b0011_1111);
+        write(0x8, 0x10);
+        write(0xB, 1);
+        write(0xD, 0b0000);
+        mapper.cpu_clock();
+        assert!(mapper.audio_output() > 0.25);
+        for _ in 0..32 * 16 {
+            mapper.cpu_clock();
+        }
+        assert_eq!(mapper.audio_output(), 0.0);
+    }
+
+    /// A drive with `sides` sides, each holding only a disk info block.
+    fn fds(sides: usize) -> Box<dyn Mapper> {
+        let mut raw = Vec::new();
+        for _ in 0..sides {
+            let mut side = vec![0; 65500];
+            side[0] = 0x01;
+            side[1..15].copy_from_slice(b"*NINTENDO-HVC*");
+            raw.extend(side);
+        }
+        let disk = FdsDisk::new(&raw).unwrap();
+        Box::new(fds::Fds::new(vec![0xEA; fds::BIOS_SIZE], disk).unwrap())
+    }
+
+    /// Clocks until the drive raises its IRQ; returns the cycles it took.
+    fn fds_next_byte(mapper: &mut Box<dyn Mapper>) -> usize {
+        for cycles in 1..1_000_000 {
+            mapper.cpu_clock();
+            if mapper.irq() {
+                return cycles;
+            }
+        }
+        panic!("no byte transferred");
+    }
+
+    #[test]
+    fn test_fds_memory_map() {
+        let mut mapper = fds(1);
+        assert_eq!(mapper.read_prg(0xE000), 0xEA);
+        mapper.write_prg(0x6000, 1);
+        mapper.write_prg(0xDFFF, 2);
+        assert_eq!(mapper.read_prg(0x6000), 1);
+        assert_eq!(mapper.read_prg(0xDFFF), 2);
+        mapper.write_chr(0x1FFF, 3);
+        assert_eq!(mapper.read_chr(0x1FFF), 3);
+
+        mapper.write_prg(0x4023, 0x01);
+        mapper.write_prg(0x4025, 0x08);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        assert!(fds::Fds::new(vec![0; 0x1000], FdsDisk::default()).is_err());
+    }
+
+    #[test]
+    fn test_fds_reads_blocks() {
+        let mut mapper = fds(1);
+        mapper.write_prg(0x4023, 0x01);
+        // Motor on, read mode, IRQ per byte, past the gap.
+        mapper.write_prg(0x4025, 0xC5);
+        assert_eq!(mapper.read_prg(0x4032) & 0b11, 0b10);
+
+        fds_next_byte(&mut mapper);
+        assert_eq!(mapper.read_prg(0x4032) & 0b11, 0b00);
+        assert_eq!(mapper.read_prg(0x4031), 0x01);
+        assert!(!mapper.irq());
+        assert_eq!(fds_next_byte(&mut mapper), 150);
+        assert_eq!(mapper.read_prg(0x4030) & 0x02, 0x02);
+        assert_eq!(mapper.read_prg(0x4031), b'*');
+    }
+
+    #[test]
+    fn test_fds_writes_block_with_crc() {
+        let mut mapper = fds(1);
+        mapper.write_prg(0x4023, 0x01);
+        // Motor on, write mode, IRQ per byte: a gap byte first.
+        mapper.write_prg(0x4025, 0x81);
+        mapper.write_prg(0x4024, 0x00);
+        fds_next_byte(&mut mapper);
+        mapper.write_prg(0x4025, 0xC1);
+        for data in [0x80, 0x12, 0x34] {
+            mapper.write_prg(0x4024, data);
+            fds_next_byte(&mut mapper);
+        }
+        // CRC control: the drive writes the two CRC bytes on its own.
+        mapper.write_prg(0x4025, 0xD1);
+        for _ in 0..300 {
+            mapper.cpu_clock();
+        }
+
+        let crc = [0x80, 0x12, 0x34, 0, 0].iter().fold(0, |crc, &byte| update_crc(crc, byte));
+        let crc = crc.to_le_bytes();
+        assert_eq!(&mapper.prg_ram()[..6], &[0x00, 0x80, 0x12, 0x34, crc[0], crc[1]]);
+        assert_eq!(mapper.prg_ram().len(), RAW_SIDE_SIZE);
+    }
+
+    #[test]
+    fn test_fds_timer_irq() {
+        let mut mapper = fds(1);
+        mapper.write_prg(0x4020, 3);
+        mapper.write_prg(0x4022, 0x02);
+        // Ignored while the disk registers are off.
+        mapper.cpu_clock();
+        assert!(!mapper.irq());
+
+        mapper.write_prg(0x4023, 0x01);
+        mapper.write_prg(0x4020, 3);
+        mapper.write_prg(0x4021, 0);
+        mapper.write_prg(0x4022, 0x03);
+        for _ in 0..4 {
+            assert!(!mapper.irq());
+            mapper.cpu_clock();
+        }
+        assert!(mapper.irq());
+        assert_eq!(mapper.read_prg(0x4030) & 0x01, 0x01);
+        assert!(!mapper.irq());
+        // Repeat mode reloads the counter.
+        for _ in 0..4 {
+            mapper.cpu_clock();
+        }
+        assert!(mapper.irq());
+    }
+
+    #[test]
+    fn test_fds_side_switching() {
+        let mut mapper = fds(2);
+        assert_eq!(mapper.read_prg(0x4032) & 0x01, 0);
+        assert_eq!(mapper.next_disk_side(), Some(1));
+        assert_eq!(mapper.read_prg(0x4032) & 0b101, 0b101);
+        for _ in 0..1_789_773 {
+            mapper.cpu_clock();
+        }
+        assert_eq!(mapper.read_prg(0x4032) & 0x01, 0);
+        assert_eq!(mapper.next_disk_side(), Some(0));
+        assert!(from_rom(test_rom(0, 0, 1, 1)).unwrap().next_disk_side().is_none());
+    }
+
+    #[test]
+    fn test_fds_wavetable_audio() {
+        let mut mapper = fds(1);
+        mapper.write_prg(0x4089, 0x80);
+        mapper.write_prg(0x4040, 63);
+        assert_eq!(mapper.read_prg(0x4040), 0, "sound registers start disabled");
+
+        mapper.write_prg(0x4023, 0x02);
+        mapper.write_prg(0x4089, 0x80);
+        // Half a square wave.
+        for index in 0..32 {
+            mapper.write_prg(0x4040 + index, 63);
+        }
+        assert_eq!(mapper.read_prg(0x4040), 63);
+        mapper.write_prg(0x4089, 0x00);
+        mapper.write_prg(0x4080, 0x80 | 32);
+        assert_eq!(mapper.read_prg(0x4090), 32);
+        // One sample every 64 cycles: the high half lasts 2048 cycles.
+        mapper.write_prg(0x4082, 0x00);
+        mapper.write_prg(0x4083, 0x04);
+
+        for _ in 0..2000 {
+            mapper.cpu_clock();
+        }
+        assert!(mapper.audio_output() > 0.3);
+        for _ in 0..2000 {
+            mapper.cpu_clock();
+        }
+        assert!(mapper.audio_output() < 0.05);
+    }
+
+    #[test]
+    fn test_unsupported_mapper() {
+        assert!(from_rom(test_rom(255, 0, 1, 1)).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{bank_offset, Chr, Mapper};
+
+/// # MMC1 (mapper 1) https://www.nesdev.org/wiki/MMC1
+///
+/// Registers are loaded one bit at a time: five writes to $8000-$FFFF shift
+/// bit 0 in, and the fifth one stores the value in the register selected by
+/// address bits 13-14. Writing a value with bit 7 set resets the shift
+/// register and locks $C000-$FFFF to the last bank.
+///
+///  $8000 control       CPPMM   CHR 4 KB mode, PRG mode, mirroring
+///  $A000 CHR bank 0    $C000 CHR bank 1
+///  $E000 PRG bank      RPPPP   PRG RAM disable, 16 KB PRG bank
+///
+/// Boards with 512 KB of PRG ROM (SUROM) use bit 4 of the CHR bank
+/// registers to pick the 256 KB half.
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+
+    shift: u8,
+    control: u8,
+    chr_banks: [u8; 2],
+    prg_bank: u8,
+}
+
+impl Mmc1 {
+    pub fn new(rom: Rom) -> Self {
+        Mmc1 {
+            chr: Chr::new(&rom),
+            prg_ram: vec![0; rom.prg_ram_size.max(0x2000)],
+            prg_rom: rom.prg_rom,
+
+            shift: 0b1_0000,
+            control: 0b0_1100,
+            chr_banks: [0; 2],
+            prg_bank: 0,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0b1_0000 == 0
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let outer = if self.prg_rom.len() > 0x40000 {
+            (self.chr_banks[0] & 0b1_0000) as usize
+        } else {
+            0
+        };
+        let bank = (self.prg_bank & 0b1111) as usize;
+        let last = (self.prg_rom.len() / 0x4000 - 1).min(0b1111);
+        let bank = match ((self.control >> 2) & 0b11, addr) {
+            (0 | 1, _) => (bank & !1) | ((addr as usize >> 14) & 1),
+            (2, 0x8000..=0xBFFF) => 0,
+            (2, _) => bank,
+            (_, 0x8000..=0xBFFF) => bank,
+            (_, _) => last,
+        };
+        bank_offset(self.prg_rom.len(), outer | bank, 0x4000, addr)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        if self.control & 0b1_0000 == 0 {
+            bank_offset(self.chr.size(), (self.chr_banks[0] >> 1) as usize, 0x2000, addr)
+        } else {
+            let bank = self.chr_banks[(addr >> 12) as usize & 1];
+            bank_offset(self.chr.size(), bank as usize, 0x1000, addr)
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.control = data,
+            0xA000..=0xBFFF => self.chr_banks[0] = data,
+            0xC000..=0xDFFF => self.chr_banks[1] = data,
+            _ => self.prg_bank = data,
+        }
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_offset(addr)],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = data;
+            }
+            0x8000..=0xFFFF if data & 0b1000_0000 != 0 => {
+                self.shift = 0b1_0000;
+                self.control |= 0b0_1100;
+            }
+            0x8000..=0xFFFF => {
+                // The initial 1 bit reaching bit 0 marks the fifth write.
+                let full = self.shift & 1 != 0;
+                self.shift = (self.shift >> 1) | ((data & 1) << 4);
+                if full {
+                    self.write_register(addr, self.shift);
+                    self.shift = 0b1_0000;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        let offset = self.chr_offset(addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+}
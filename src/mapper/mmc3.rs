@@ -0,0 +1,217 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{bank_offset, Chr, Mapper};
+
+/// The two MMC3 revisions disagree on what a counter value of zero means.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum IrqBehavior {
+    /// MMC3B/MMC3C (Sharp): an IRQ fires every time the counter is zero after
+    /// a clock, including after reloading a latch of zero.
+    Sharp,
+    /// MMC3A (NEC): an IRQ only fires when the counter reaches zero from a
+    /// decrement or from a reload requested through $C001.
+    Nec,
+}
+
+/// A12 has to stay low for this many M2 cycles before a rise counts, which
+/// filters out the toggling caused by the 8 sprite fetches of a scanline.
+const A12_LOW_CYCLES: u8 = 3;
+
+/// # MMC3 (mapper 4) https://www.nesdev.org/wiki/MMC3
+///
+///  $8000 bank select   CPMx xRRR
+///                      |||    +++- Register R0-R7 updated by the next $8001 write
+///                      ||+-------- Nothing on MMC3, PRG RAM enable on MMC6
+///                      |+--------- PRG mode: $8000 swappable (0) or fixed to second-last bank (1)
+///                      +---------- CHR A12 inversion
+///  $8001 bank data
+///  $A000 mirroring     0: vertical, 1: horizontal
+///  $A001 PRG RAM       RWxx xxxx   chip enable, deny writes
+///  $C000 IRQ latch     $C001 IRQ reload
+///  $E000 IRQ disable   $E001 IRQ enable
+pub struct Mmc3 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    prg_ram: Vec<u8>,
+    mirroring: Mirroring,
+
+    bank_select: u8,
+    registers: [u8; 8],
+    prg_ram_enabled: bool,
+    prg_ram_write_protected: bool,
+
+    irq_behavior: IrqBehavior,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12_high: bool,
+    a12_low_cycles: u8,
+}
+
+impl Mmc3 {
+    pub fn new(rom: Rom) -> Self {
+        let irq_behavior = if rom.submapper == 4 {
+            IrqBehavior::Nec
+        } else {
+            IrqBehavior::Sharp
+        };
+        Mmc3::with_irq_behavior(rom, irq_behavior)
+    }
+
+    pub fn with_irq_behavior(rom: Rom, irq_behavior: IrqBehavior) -> Self {
+        Mmc3 {
+            chr: Chr::new(&rom),
+            prg_ram: vec![0; rom.prg_ram_size.max(0x2000)],
+            mirroring: rom.screen_mirroring,
+            prg_rom: rom.prg_rom,
+
+            bank_select: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            prg_ram_enabled: true,
+            prg_ram_write_protected: false,
+
+            irq_behavior,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12_high: false,
+            a12_low_cycles: 0,
+        }
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        let second_last = (self.prg_rom.len() / 0x2000).saturating_sub(2);
+        let prg_mode = self.bank_select & 0b0100_0000 != 0;
+        match (addr, prg_mode) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.registers[6] as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => self.registers[7] as usize,
+            _ => second_last + 1,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let addr = if self.bank_select & 0b1000_0000 != 0 {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+        let bank = match addr {
+            0x0000..=0x07FF => (self.registers[0] & 0xFE) as usize + (addr as usize >> 10 & 1),
+            0x0800..=0x0FFF => (self.registers[1] & 0xFE) as usize + (addr as usize >> 10 & 1),
+            _ => self.registers[2 + ((addr as usize - 0x1000) >> 10)] as usize,
+        };
+        bank_offset(self.chr.size(), bank, 0x0400, addr)
+    }
+
+    /// Watches PPU address line A12 and clocks the scanline counter on a
+    /// filtered rising edge.
+    fn watch_a12(&mut self, addr: u16) {
+        let a12_high = addr & 0x1000 != 0;
+        if a12_high && !self.a12_high && self.a12_low_cycles >= A12_LOW_CYCLES {
+            self.clock_irq_counter();
+        }
+        if a12_high {
+            self.a12_low_cycles = 0;
+        }
+        self.a12_high = a12_high;
+    }
+
+    fn clock_irq_counter(&mut self) {
+        let previous = self.irq_counter;
+        let reloaded = self.irq_reload;
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+        } else {
+            self.irq_counter -= 1;
+        }
+        self.irq_reload = false;
+
+        let fire = match self.irq_behavior {
+            IrqBehavior::Sharp => self.irq_counter == 0,
+            IrqBehavior::Nec => self.irq_counter == 0 && (previous > 0 || reloaded),
+        };
+        if fire && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => self.prg_ram[(addr - 0x6000) as usize % self.prg_ram.len()],
+            0x8000..=0xFFFF => self.prg_rom[bank_offset(self.prg_rom.len(), self.prg_bank(addr), 0x2000, addr)],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match (addr, addr & 1) {
+            (0x6000..=0x7FFF, _) if self.prg_ram_enabled && !self.prg_ram_write_protected => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr - 0x6000) as usize % len] = data;
+            }
+            (0x8000..=0x9FFF, 0) => self.bank_select = data,
+            (0x8000..=0x9FFF, _) => self.registers[(self.bank_select & 0b111) as usize] = data,
+            (0xA000..=0xBFFF, 0) => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            (0xA000..=0xBFFF, 1) => {
+                self.prg_ram_enabled = data & 0b1000_0000 != 0;
+                self.prg_ram_write_protected = data & 0b0100_0000 != 0;
+            }
+            (0xC000..=0xDFFF, 0) => self.irq_latch = data,
+            (0xC000..=0xDFFF, _) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xE000..=0xFFFF, 0) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (0xE000..=0xFFFF, _) => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.watch_a12(addr);
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.watch_a12(addr);
+        let offset = self.chr_offset(addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn cpu_clock(&mut self) {
+        if !self.a12_high {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+}
@@ -0,0 +1,541 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+use crate::apu::pulse::DUTY_TABLE;
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{bank_offset, Chr, Mapper};
+
+const PRG_RAM_SIZE: usize = 0x10000;
+
+/// The pulse envelopes and length counters run off a fixed 240 Hz divider
+/// instead of the APU frame counter.
+const FRAME_PERIOD: u16 = 7457;
+
+/// After the scanline-detecting nametable read, 32 background tiles of four
+/// reads each come first, then 8 sprites of four reads each.
+const BG_READS: u16 = 128;
+const SPRITE_READS: u16 = 32;
+
+/// One of the two MMC5 pulse channels: an APU pulse channel without sweep.
+#[derive(Default)]
+struct Pulse {
+    duty: u8,
+    duty_step: u8,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.duty = data >> 6;
+                self.length.halt = data & 0b0010_0000 != 0;
+                self.envelope.write_control(data);
+            }
+            2 => self.timer_period = (self.timer_period & 0x0700) | data as u16,
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((data as u16 & 0b111) << 8);
+                self.length.load(data);
+                self.duty_step = 0;
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.duty_step = (self.duty_step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_frame(&mut self) {
+        self.envelope.clock();
+        self.length.clock();
+    }
+
+    fn output(&self) -> u8 {
+        if !self.length.active() || DUTY_TABLE[self.duty as usize][self.duty_step as usize] == 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+/// # MMC5 (mapper 5) https://www.nesdev.org/wiki/MMC5
+///
+/// The MMC5 does not see the PPU's dot counter. It works out where the PPU is
+/// from the PPU bus alone: three reads of the same nametable address in a row
+/// only happen at the start of a scanline, and counting the reads after that
+/// tells background fetches apart from sprite fetches.
+pub struct Mmc5 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Chr,
+    exram: [u8; 0x400],
+
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    exram_mode: u8,
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    prg_banks: [u8; 5],
+    chr_banks_a: [u16; 8],
+    chr_banks_b: [u16; 4],
+    chr_upper: u8,
+    last_chr_set_b: bool,
+    large_sprites: bool,
+
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+
+    irq_compare: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    in_frame: bool,
+    scanline: u8,
+    last_nametable_addr: u16,
+    nametable_repeats: u8,
+    ppu_reads: u16,
+    idle_cycles: u8,
+    exram_tile: u8,
+    split_tile: Option<u16>,
+
+    multiplicand: u8,
+    multiplier: u8,
+
+    pulses: [Pulse; 2],
+    frame_divider: u16,
+    odd_cycle: bool,
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq: bool,
+    pcm: u8,
+}
+
+impl Mmc5 {
+    pub fn new(rom: Rom) -> Self {
+        Mmc5 {
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            prg_ram: vec![0; PRG_RAM_SIZE],
+            exram: [0; 0x400],
+
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0, 0, 0, 0xFF],
+            chr_banks_a: [0; 8],
+            chr_banks_b: [0; 4],
+            chr_upper: 0,
+            last_chr_set_b: false,
+            large_sprites: false,
+
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            in_frame: false,
+            scanline: 0,
+            last_nametable_addr: 0,
+            nametable_repeats: 0,
+            ppu_reads: 0,
+            idle_cycles: 0,
+            exram_tile: 0,
+            split_tile: None,
+
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+
+            pulses: [Pulse::default(), Pulse::default()],
+            frame_divider: 0,
+            odd_cycle: false,
+            pcm_read_mode: false,
+            pcm_irq_enabled: false,
+            pcm_irq: false,
+            pcm: 0,
+        }
+    }
+
+    /// Resolves a CPU address in $6000-$FFFF to (is_rom, 8 KB bank number).
+    fn prg_bank(&self, addr: u16) -> (bool, usize) {
+        let slot = ((addr - 0x6000) / 0x2000) as usize;
+        if slot == 0 {
+            return (false, (self.prg_banks[0] & 0b111) as usize);
+        }
+        let quarter = slot - 1;
+        let register = match (self.prg_mode, quarter) {
+            (0, _) => 4,
+            (1, 0..=1) => 2,
+            (1, _) => 4,
+            (2, 0..=1) => 2,
+            (2, 2) => 3,
+            (2, _) => 4,
+            (_, q) => q + 1,
+        };
+        let value = self.prg_banks[register];
+        let is_rom = register == 4 || value & 0x80 != 0;
+        let bank = (value & 0x7F) as usize;
+        let bank = match (self.prg_mode, register) {
+            (0, _) => (bank & !0b11) + quarter,
+            (1, _) | (2, 2) => (bank & !0b1) + (quarter & 1),
+            _ => bank,
+        };
+        if is_rom {
+            (true, bank)
+        } else {
+            (false, bank & 0b111)
+        }
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect[0] & 0b11 == 0b10 && self.prg_ram_protect[1] & 0b11 == 0b01
+    }
+
+    fn fetching_sprites(&self) -> bool {
+        self.in_frame && self.ppu_reads > BG_READS && self.ppu_reads <= BG_READS + SPRITE_READS
+    }
+
+    fn uses_chr_set_b(&self) -> bool {
+        if self.in_frame && self.large_sprites {
+            !self.fetching_sprites()
+        } else {
+            self.last_chr_set_b
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let (bank_size, register) = if self.uses_chr_set_b() {
+            let register = match self.chr_mode {
+                0 => 3,
+                1 => 3,
+                2 => 1 + 2 * ((addr as usize >> 11) & 1),
+                _ => (addr as usize >> 10) & 0b11,
+            };
+            (0x2000 >> self.chr_mode, self.chr_banks_b[register])
+        } else {
+            let register = match self.chr_mode {
+                0 => 7,
+                1 => 3 + 4 * (addr as usize >> 12),
+                2 => 1 + 2 * (addr as usize >> 11),
+                _ => addr as usize >> 10,
+            };
+            (0x2000 >> self.chr_mode, self.chr_banks_a[register])
+        };
+        bank_offset(self.chr.size(), register as usize, bank_size, addr)
+    }
+
+    fn read_register(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x5015 => {
+                self.pulses[0].length.active() as u8 | (self.pulses[1].length.active() as u8) << 1
+            }
+            0x5010 => {
+                let status = (self.pcm_irq as u8) << 7;
+                self.pcm_irq = false;
+                status
+            }
+            0x5204 => {
+                let status = (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6;
+                self.irq_pending = false;
+                status
+            }
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => self.exram[(addr - 0x5C00) as usize],
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5003 => self.pulses[0].write(addr - 0x5000, data),
+            0x5004..=0x5007 => self.pulses[1].write(addr - 0x5004, data),
+            0x5010 => {
+                self.pcm_read_mode = data & 1 != 0;
+                self.pcm_irq_enabled = data & 0x80 != 0;
+            }
+            0x5011 if !self.pcm_read_mode && data != 0 => self.pcm = data,
+            0x5015 => {
+                self.pulses[0].length.set_enabled(data & 1 != 0);
+                self.pulses[1].length.set_enabled(data & 2 != 0);
+            }
+            0x5100 => self.prg_mode = data & 0b11,
+            0x5101 => self.chr_mode = data & 0b11,
+            0x5102 => self.prg_ram_protect[0] = data,
+            0x5103 => self.prg_ram_protect[1] = data,
+            0x5104 => self.exram_mode = data & 0b11,
+            0x5105 => self.nametable_mapping = data,
+            0x5106 => self.fill_tile = data,
+            0x5107 => self.fill_attribute = data & 0b11,
+            0x5113..=0x5117 => self.prg_banks[(addr - 0x5113) as usize] = data,
+            0x5120..=0x5127 => {
+                self.chr_banks_a[(addr - 0x5120) as usize] = data as u16 | (self.chr_upper as u16) << 8;
+                self.last_chr_set_b = false;
+            }
+            0x5128..=0x512B => {
+                self.chr_banks_b[(addr - 0x5128) as usize] = data as u16 | (self.chr_upper as u16) << 8;
+                self.last_chr_set_b = true;
+            }
+            0x5130 => self.chr_upper = data & 0b11,
+            0x5200 => self.split_control = data,
+            0x5201 => self.split_scroll = data,
+            0x5202 => self.split_bank = data,
+            0x5203 => self.irq_compare = data,
+            0x5204 => self.irq_enabled = data & 0x80 != 0,
+            0x5205 => self.multiplicand = data,
+            0x5206 => self.multiplier = data,
+            0x5C00..=0x5FFF => match self.exram_mode {
+                0 | 1 => self.exram[(addr - 0x5C00) as usize] = if self.in_frame { data } else { 0 },
+                2 => self.exram[(addr - 0x5C00) as usize] = data,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Bookkeeping shared by every PPU read the MMC5 can see.
+    fn ppu_read(&mut self, nametable_addr: Option<u16>) {
+        self.idle_cycles = 0;
+        match nametable_addr {
+            Some(addr) if addr == self.last_nametable_addr => {
+                self.nametable_repeats += 1;
+                if self.nametable_repeats == 2 {
+                    self.detect_scanline();
+                    return;
+                }
+            }
+            Some(addr) => {
+                self.last_nametable_addr = addr;
+                self.nametable_repeats = 0;
+            }
+            None => {
+                self.last_nametable_addr = 0;
+                self.nametable_repeats = 0;
+            }
+        }
+        self.ppu_reads = self.ppu_reads.saturating_add(1);
+    }
+
+    fn detect_scanline(&mut self) {
+        if self.in_frame {
+            self.scanline = self.scanline.wrapping_add(1);
+            if self.scanline == self.irq_compare {
+                self.irq_pending = true;
+            }
+        } else {
+            self.in_frame = true;
+            self.scanline = 0;
+        }
+        self.ppu_reads = 1;
+    }
+
+    /// The tile column being fetched and the scanline it will be drawn on.
+    fn fetch_position(&self) -> (u16, u16) {
+        let group = (self.ppu_reads.max(1) - 1) / 4;
+        if self.ppu_reads <= BG_READS {
+            (group + 2, self.scanline as u16)
+        } else {
+            (group - (BG_READS + SPRITE_READS) / 4, self.scanline as u16 + 1)
+        }
+    }
+
+    fn in_split(&self, tile: u16) -> bool {
+        if self.split_control & 0x80 == 0 || self.exram_mode >= 2 {
+            return false;
+        }
+        let threshold = (self.split_control & 0b1_1111) as u16;
+        if self.split_control & 0x40 != 0 {
+            tile >= threshold
+        } else {
+            tile < threshold
+        }
+    }
+
+    fn split_y(&self, scanline: u16) -> u16 {
+        (self.split_scroll as u16 + scanline) % 240
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x5000..=0x5FFF => self.read_register(addr),
+            0x6000..=0xFFFF => {
+                let value = match self.prg_bank(addr) {
+                    (true, bank) => self.prg_rom[bank_offset(self.prg_rom.len(), bank, 0x2000, addr)],
+                    (false, bank) => self.prg_ram[bank_offset(PRG_RAM_SIZE, bank, 0x2000, addr)],
+                };
+                if self.pcm_read_mode && (0x8000..=0xBFFF).contains(&addr) {
+                    if value == 0 {
+                        self.pcm_irq = self.pcm_irq_enabled;
+                    } else {
+                        self.pcm = value;
+                    }
+                }
+                value
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5FFF => self.write_register(addr, data),
+            0x6000..=0xDFFF if self.prg_ram_writable() => {
+                if let (false, bank) = self.prg_bank(addr) {
+                    self.prg_ram[bank_offset(PRG_RAM_SIZE, bank, 0x2000, addr)] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.ppu_read(None);
+        if self.in_frame && !self.fetching_sprites() {
+            if self.split_tile.is_some() {
+                let fine_y = self.split_y(self.fetch_position().1) & 0b111;
+                let addr = (addr & !0b111) | fine_y;
+                return self.chr.read(bank_offset(self.chr.size(), self.split_bank as usize, 0x1000, addr));
+            }
+            if self.exram_mode == 1 {
+                let bank = (self.exram_tile & 0x3F) as usize | (self.chr_upper as usize) << 6;
+                return self.chr.read(bank_offset(self.chr.size(), bank, 0x1000, addr));
+            }
+        }
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        let offset = self.chr_offset(addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.nametable_mapping {
+            0x44 => Mirroring::Vertical,
+            0x50 => Mirroring::Horizontal,
+            0x55 => Mirroring::SingleScreenUpper,
+            _ => Mirroring::SingleScreenLower,
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn irq(&self) -> bool {
+        (self.irq_pending && self.irq_enabled) || self.pcm_irq
+    }
+
+    fn cpu_clock(&mut self) {
+        self.idle_cycles = self.idle_cycles.saturating_add(1);
+        if self.idle_cycles >= 3 {
+            self.in_frame = false;
+            self.last_nametable_addr = 0;
+        }
+
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            self.pulses[0].clock_timer();
+            self.pulses[1].clock_timer();
+        }
+        self.frame_divider += 1;
+        if self.frame_divider == FRAME_PERIOD {
+            self.frame_divider = 0;
+            self.pulses[0].clock_frame();
+            self.pulses[1].clock_frame();
+        }
+    }
+
+    fn read_nametable(&mut self, addr: u16, ciram: &[u8]) -> Option<u8> {
+        let offset = (addr & 0x3FF) as usize;
+        let is_attribute = offset >= 0x3C0;
+        self.ppu_read(if is_attribute { None } else { Some(addr) });
+
+        if self.in_frame && !self.fetching_sprites() {
+            let (tile, scanline) = self.fetch_position();
+            if !is_attribute {
+                self.split_tile = if self.in_split(tile) { Some(tile) } else { None };
+            }
+            if let Some(tile) = self.split_tile {
+                let row = self.split_y(scanline) / 8;
+                let column = tile % 32;
+                return Some(if is_attribute {
+                    let attribute = self.exram[0x3C0 + (row as usize / 4) * 8 + column as usize / 4];
+                    let shift = ((row & 0b10) << 1) | (column & 0b10);
+                    ((attribute >> shift) & 0b11) * 0x55
+                } else {
+                    self.exram[(row * 32 + column) as usize]
+                });
+            }
+            if self.exram_mode == 1 {
+                if is_attribute {
+                    return Some((self.exram_tile >> 6) * 0x55);
+                }
+                self.exram_tile = self.exram[offset];
+            }
+        }
+
+        let table = (addr as usize >> 10) & 0b11;
+        Some(match (self.nametable_mapping >> (table * 2)) & 0b11 {
+            0 => ciram[offset],
+            1 => ciram[0x400 + offset],
+            2 if self.exram_mode <= 1 => self.exram[offset],
+            2 => 0,
+            _ if is_attribute => self.fill_attribute * 0x55,
+            _ => self.fill_tile,
+        })
+    }
+
+    fn write_nametable(&mut self, addr: u16, data: u8, ciram: &mut [u8]) -> bool {
+        let offset = (addr & 0x3FF) as usize;
+        let table = (addr as usize >> 10) & 0b11;
+        match (self.nametable_mapping >> (table * 2)) & 0b11 {
+            0 => ciram[offset] = data,
+            1 => ciram[0x400 + offset] = data,
+            2 if self.exram_mode <= 1 => self.exram[offset] = data,
+            _ => {}
+        }
+        true
+    }
+
+    fn ppu_register_write(&mut self, addr: u16, data: u8) {
+        match addr & 0x2007 {
+            0x2000 => self.large_sprites = data & 0b0010_0000 != 0,
+            0x2001 if data & 0b0001_1000 == 0 => self.in_frame = false,
+            _ => {}
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        let pulses = (self.pulses[0].output() + self.pulses[1].output()) as f32;
+        let pulse_out = if pulses == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulses + 100.0)
+        };
+        pulse_out + self.pcm as f32 / 255.0 * 0.25
+    }
+}
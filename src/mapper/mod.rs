@@ -0,0 +1,164 @@
+pub mod axrom;
+pub mod cnrom;
+pub mod fds;
+pub mod fds_audio;
+pub mod fme7;
+pub mod four_screen;
+pub mod mmc1;
+pub mod mmc3;
+pub mod mmc5;
+pub mod namco163;
+pub mod nrom;
+pub mod nsf;
+pub mod uxrom;
+pub mod vrc4;
+pub mod vrc6;
+pub mod vrc7;
+pub mod vrc_irq;
+
+use crate::cartridge::{Mirroring, Rom};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The cartridge sits on both the CPU and the PPU bus, so the two share it.
+pub type SharedMapper = Rc<RefCell<Box<dyn Mapper>>>;
+
+/// # Cartridge board https://www.nesdev.org/wiki/Mapper
+///
+/// The bus hands every CPU access in $4020-$FFFF and every PPU access in
+/// $0000-$1FFF to the mapper. Reads take `&mut self` because some boards
+/// have registers that change state when read.
+pub trait Mapper {
+    fn read_prg(&mut self, addr: u16) -> u8;
+
+    fn write_prg(&mut self, addr: u16, data: u8);
+
+    fn read_chr(&mut self, addr: u16) -> u8;
+
+    fn write_chr(&mut self, addr: u16, data: u8);
+
+    fn mirroring(&self) -> Mirroring;
+
+    /// State of the cartridge's /IRQ output, polled by the CPU before every
+    /// instruction.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Called once per CPU cycle (M2) for boards that count cycles.
+    fn cpu_clock(&mut self) {}
+
+    /// Lets boards that wire up the nametables themselves answer a PPU read
+    /// in $2000-$2FFF. `ciram` is the console's 2 KB of nametable RAM.
+    /// `None` leaves the read to the console using `mirroring()`.
+    fn read_nametable(&mut self, _addr: u16, _ciram: &[u8]) -> Option<u8> {
+        None
+    }
+
+    /// Write counterpart of `read_nametable`; returns whether it was handled.
+    fn write_nametable(&mut self, _addr: u16, _data: u8, _ciram: &mut [u8]) -> bool {
+        false
+    }
+
+    /// CPU writes to the PPU registers ($2000-$3FFF) are visible to boards
+    /// that snoop the CPU bus.
+    fn ppu_register_write(&mut self, _addr: u16, _data: u8) {}
+
+    /// PRG RAM that a battery keeps alive between sessions; empty for boards
+    /// without any.
+    fn prg_ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    /// Current level of the board's expansion audio, in the same units as
+    /// the APU mixer output.
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    /// Boards with a disk drive eject the disk and put the next side in,
+    /// returning which one; `None` for cartridges.
+    fn next_disk_side(&mut self) -> Option<usize> {
+        None
+    }
+}
+
+pub fn from_rom(rom: Rom) -> Result<Box<dyn Mapper>, String> {
+    let four_screen = rom.screen_mirroring == Mirroring::FourScreen;
+    let mapper = board(rom)?;
+    Ok(if four_screen {
+        Box::new(four_screen::FourScreen::new(mapper))
+    } else {
+        mapper
+    })
+}
+
+fn board(rom: Rom) -> Result<Box<dyn Mapper>, String> {
+    match rom.mapper {
+        0 => Ok(Box::new(nrom::Nrom::new(rom))),
+        1 => Ok(Box::new(mmc1::Mmc1::new(rom))),
+        2 => Ok(Box::new(uxrom::Uxrom::new(rom))),
+        3 => Ok(Box::new(cnrom::Cnrom::new(rom))),
+        4 => Ok(Box::new(mmc3::Mmc3::new(rom))),
+        5 => Ok(Box::new(mmc5::Mmc5::new(rom))),
+        7 => Ok(Box::new(axrom::Axrom::new(rom))),
+        19 => Ok(Box::new(namco163::Namco163::new(rom))),
+        21 | 22 | 23 | 25 => Ok(Box::new(vrc4::Vrc4::new(rom))),
+        24 | 26 => Ok(Box::new(vrc6::Vrc6::new(rom))),
+        69 => Ok(Box::new(fme7::Fme7::new(rom))),
+        85 => Ok(Box::new(vrc7::Vrc7::new(rom))),
+        other => Err(format!("Mapper {} is not supported", other)),
+    }
+}
+
+/// Offset of `addr` inside bank number `bank` of `bank_size` bytes, wrapping
+/// around `len` so oversized bank numbers mirror like the real address lines.
+pub fn bank_offset(len: usize, bank: usize, bank_size: usize, addr: u16) -> usize {
+    let banks = (len / bank_size).max(1);
+    (bank % banks) * bank_size + (addr as usize % bank_size)
+}
+
+/// CHR ROM, or CHR RAM when the cartridge ships without CHR ROM.
+pub struct Chr {
+    data: Vec<u8>,
+    writable: bool,
+}
+
+impl Chr {
+    pub fn new(rom: &Rom) -> Self {
+        if rom.chr_rom.is_empty() {
+            Chr {
+                data: vec![0; rom.chr_ram_size.max(0x2000)],
+                writable: true,
+            }
+        } else {
+            Chr {
+                data: rom.chr_rom.clone(),
+                writable: false,
+            }
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn read(&self, offset: usize) -> u8 {
+        self.data[offset % self.data.len()]
+    }
+
+    pub fn write(&mut self, offset: usize, data: u8) {
+        if self.writable {
+            let len = self.data.len();
+            self.data[offset % len] = data;
+        }
+    }
+}
+
+#[cfg(test)]
+#[path = "mapper_tests.rs"]
+mod mapper_tests;
@@ -0,0 +1,276 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{bank_offset, Chr, Mapper};
+
+/// Each enabled channel gets one update slot of 15 CPU cycles in turn.
+const CHANNEL_CYCLES: u8 = 15;
+
+/// Output level of one sample step at full volume, relative to the APU mix.
+/// Boards differ in how hot they run the chip; this puts a full-volume
+/// channel about level with a full-volume APU pulse.
+const N163_LEVEL: f32 = 0.00125;
+
+/// # Namco 163 audio https://www.nesdev.org/wiki/Namco_163_audio
+///
+/// Up to eight wavetable channels that live entirely in 128 bytes of
+/// internal RAM: the channel registers sit at the top of it and the 4-bit
+/// waveforms anywhere below. The chip updates one channel every 15 CPU
+/// cycles and its DAC outputs only that channel until the next slot, so the
+/// channels are time-multiplexed rather than summed.
+struct N163Audio {
+    ram: [u8; 0x80],
+    address: u8,
+    auto_increment: bool,
+    disabled: bool,
+    cycles: u8,
+    channel: u8,
+    output: i16,
+}
+
+impl N163Audio {
+    fn new() -> Self {
+        N163Audio {
+            ram: [0; 0x80],
+            address: 0,
+            auto_increment: false,
+            disabled: false,
+            cycles: 0,
+            channel: 7,
+            output: 0,
+        }
+    }
+
+    fn write_address(&mut self, data: u8) {
+        self.address = data & 0x7F;
+        self.auto_increment = data & 0x80 != 0;
+    }
+
+    fn read_data(&mut self) -> u8 {
+        let data = self.ram[self.address as usize];
+        self.step_address();
+        data
+    }
+
+    fn write_data(&mut self, data: u8) {
+        self.ram[self.address as usize] = data;
+        self.step_address();
+    }
+
+    fn step_address(&mut self) {
+        if self.auto_increment {
+            self.address = (self.address + 1) & 0x7F;
+        }
+    }
+
+    /// Channels 7 down to `8 - count` are enabled, set by bits 4-6 of $7F.
+    fn first_channel(&self) -> u8 {
+        7 - ((self.ram[0x7F] >> 4) & 0b111)
+    }
+
+    fn clock(&mut self) {
+        if self.disabled {
+            return;
+        }
+        self.cycles += 1;
+        if self.cycles < CHANNEL_CYCLES {
+            return;
+        }
+        self.cycles = 0;
+        self.output = self.update_channel(self.channel);
+        self.channel = if self.channel <= self.first_channel() { 7 } else { self.channel - 1 };
+    }
+
+    /// Advances one channel's 24-bit phase and returns its new sample.
+    fn update_channel(&mut self, channel: u8) -> i16 {
+        let base = 0x40 + channel as usize * 8;
+        let registers = &self.ram[base..base + 8];
+        let frequency = registers[0] as u32 | (registers[2] as u32) << 8 | ((registers[4] & 0b11) as u32) << 16;
+        let length = (256 - (registers[4] & 0xFC) as u32) << 16;
+        let wave_address = registers[6] as u32;
+        let volume = (registers[7] & 0x0F) as i16;
+        let phase = registers[1] as u32 | (registers[3] as u32) << 8 | (registers[5] as u32) << 16;
+
+        let phase = (phase + frequency) % length;
+        self.ram[base + 1] = phase as u8;
+        self.ram[base + 3] = (phase >> 8) as u8;
+        self.ram[base + 5] = (phase >> 16) as u8;
+
+        let nibble = ((phase >> 16) + wave_address) & 0xFF;
+        let byte = self.ram[(nibble >> 1) as usize];
+        let sample = if nibble & 1 == 0 { byte & 0x0F } else { byte >> 4 };
+        (sample as i16 - 8) * volume
+    }
+
+    fn output(&self) -> f32 {
+        if self.disabled {
+            0.0
+        } else {
+            self.output as f32 * N163_LEVEL
+        }
+    }
+}
+
+/// # Namco 163 (mapper 19) https://www.nesdev.org/wiki/INES_Mapper_019
+///
+///  $4800 sound RAM data port       $F800 sound RAM address, PRG RAM protect
+///  $5000 IRQ counter low           $5800 IRQ counter high, bit 7 enable
+///  $8000-$B800 CHR banks 0-7       $C000-$D800 nametable banks
+///  $E000 8 KB PRG at $8000, bit 6 sound disable
+///  $E800 8 KB PRG at $A000         $F000 8 KB PRG at $C000
+///  $E000-$FFFF fixed to the last bank
+///
+/// Nametable banks $E0-$FF pick a page of the console's nametable RAM,
+/// anything lower a 1 KB page of CHR ROM. CHR banks $E0 and up can map the
+/// nametable RAM into the pattern tables as well; that is not supported and
+/// they address CHR like any other bank.
+pub struct Namco163 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Chr,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    nametable_banks: [u8; 4],
+    write_protect: u8,
+
+    irq_counter: u16,
+    irq_pending: bool,
+
+    audio: N163Audio,
+}
+
+impl Namco163 {
+    pub fn new(rom: Rom) -> Self {
+        Namco163 {
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametable_banks: [0xE0, 0xE1, 0xE0, 0xE1],
+            write_protect: 0,
+
+            irq_counter: 0,
+            irq_pending: false,
+
+            audio: N163Audio::new(),
+        }
+    }
+
+    /// PRG RAM takes writes only with $4x in $F800, and bits 0-3 then
+    /// protect its four 2 KB windows one by one.
+    fn ram_writable(&self, addr: u16) -> bool {
+        let window = (addr - 0x6000) >> 11;
+        self.write_protect & 0xF0 == 0x40 && self.write_protect & (1 << window) == 0
+    }
+
+    fn irq_enabled(&self) -> bool {
+        self.irq_counter & 0x8000 != 0
+    }
+}
+
+impl Mapper for Namco163 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => self.audio.read_data(),
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_counter >> 8) as u8,
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xDFFF => {
+                let bank = self.prg_banks[(addr as usize - 0x8000) / 0x2000] as usize;
+                self.prg_rom[bank_offset(self.prg_rom.len(), bank, 0x2000, addr)]
+            }
+            0xE000..=0xFFFF => self.prg_rom[self.prg_rom.len() - 0x2000 + (addr - 0xE000) as usize],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4800..=0x4FFF => self.audio.write_data(data),
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0xFF00) | data as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | (data as u16) << 8;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF if self.ram_writable(addr) => self.prg_ram[(addr - 0x6000) as usize] = data,
+            0x8000..=0xBFFF => self.chr_banks[(addr as usize - 0x8000) >> 11] = data,
+            0xC000..=0xDFFF => self.nametable_banks[(addr as usize - 0xC000) >> 11] = data,
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = data & 0x3F;
+                self.audio.disabled = data & 0x40 != 0;
+            }
+            0xE800..=0xEFFF => self.prg_banks[1] = data & 0x3F,
+            0xF000..=0xF7FF => self.prg_banks[2] = data & 0x3F,
+            0xF800..=0xFFFF => {
+                self.write_protect = data;
+                self.audio.write_address(data);
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize >> 10] as usize;
+        self.chr.read(bank_offset(self.chr.size(), bank, 0x400, addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        let bank = self.chr_banks[addr as usize >> 10] as usize;
+        let offset = bank_offset(self.chr.size(), bank, 0x400, addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Vertical
+    }
+
+    fn read_nametable(&mut self, addr: u16, ciram: &[u8]) -> Option<u8> {
+        let offset = (addr & 0x3FF) as usize;
+        let bank = self.nametable_banks[(addr as usize >> 10) & 0b11];
+        Some(if bank >= 0xE0 {
+            ciram[(bank as usize & 1) * 0x400 + offset]
+        } else {
+            self.chr.read(bank_offset(self.chr.size(), bank as usize, 0x400, addr))
+        })
+    }
+
+    fn write_nametable(&mut self, addr: u16, data: u8, ciram: &mut [u8]) -> bool {
+        let bank = self.nametable_banks[(addr as usize >> 10) & 0b11];
+        if bank >= 0xE0 {
+            ciram[(bank as usize & 1) * 0x400 + (addr & 0x3FF) as usize] = data;
+        }
+        true
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    /// The 15-bit IRQ counter counts up while enabled and stops at $7FFF,
+    /// where it raises the IRQ.
+    fn cpu_clock(&mut self) {
+        if self.irq_enabled() && self.irq_counter & 0x7FFF != 0x7FFF {
+            self.irq_counter += 1;
+            if self.irq_counter & 0x7FFF == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
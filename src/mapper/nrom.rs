@@ -0,0 +1,44 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{Chr, Mapper};
+
+/// # NROM (mapper 0) https://www.nesdev.org/wiki/NROM
+///
+/// 16 KB or 32 KB of fixed PRG ROM; a 16 KB image is mirrored into $C000.
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(rom: Rom) -> Self {
+        Nrom {
+            chr: Chr::new(&rom),
+            prg_rom: rom.prg_rom,
+            mirroring: rom.screen_mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => self.prg_rom[(addr - 0x8000) as usize % self.prg_rom.len()],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, _addr: u16, _data: u8) {}
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
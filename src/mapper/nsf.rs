@@ -0,0 +1,193 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::fds::Fds;
+use crate::mapper::{bank_offset, Mapper};
+use crate::nsf::{Nsf, EXPANSION_FDS, EXPANSION_MMC5, EXPANSION_N163, EXPANSION_SUNSOFT_5B, EXPANSION_VRC6, EXPANSION_VRC7};
+
+const BANK_SIZE: usize = 0x1000;
+
+/// One expansion chip, and the CPU addresses of its sound registers.
+struct Expansion {
+    chip: Box<dyn Mapper>,
+    registers: fn(u16) -> bool,
+}
+
+/// # NSF hardware https://www.nesdev.org/wiki/NSF#Bankswitching
+///
+/// The cartridge an NSF player provides: 8 KB of RAM at $6000, the tune's
+/// data at $8000-$FFFF in eight 4 KB banks switched through $5FF8-$5FFF, and
+/// whichever expansion sound chips the header asks for. The chips come from
+/// the boards that carry them, with only their sound registers wired up.
+/// Everything else in $4020-$5FFF reads as 0.
+///
+/// Tunes for the FDS run from RAM instead: all of $6000-$FFFF is RAM,
+/// writable up to $DFFF, and a bank switch through $5FF6-$5FFF copies the
+/// bank in.
+pub struct NsfMapper {
+    prg: Vec<u8>,
+    banks: [u8; 8],
+    bankswitched: bool,
+    ram: [u8; 0x2000],
+    fds_ram: Option<Vec<u8>>,
+    expansions: Vec<Expansion>,
+}
+
+impl NsfMapper {
+    pub fn new(nsf: &Nsf) -> Self {
+        // Without bank switching the data simply sits at the load address;
+        // with it, the load address only sets the offset into the first bank.
+        let (padding, banks) = match nsf.banks {
+            Some(banks) => ((nsf.load_address as usize) & (BANK_SIZE - 1), banks),
+            None => ((nsf.load_address as usize).saturating_sub(0x8000), [0, 1, 2, 3, 4, 5, 6, 7]),
+        };
+        let mut prg = vec![0; padding];
+        prg.extend_from_slice(&nsf.data);
+        let size = prg.len().div_ceil(BANK_SIZE).max(8) * BANK_SIZE;
+        prg.resize(size, 0);
+
+        let board = |mapper: u16| {
+            crate::mapper::from_rom(Rom {
+                prg_rom: vec![0; 0x8000],
+                chr_rom: Vec::new(),
+                mapper,
+                submapper: 0,
+                screen_mirroring: Mirroring::Horizontal,
+                battery: false,
+                prg_ram_size: 0x2000,
+                chr_ram_size: 0x2000,
+                region: nsf.region,
+            })
+            .unwrap()
+        };
+        let mut expansions = Vec::new();
+        if nsf.expansion & EXPANSION_VRC6 != 0 {
+            expansions.push(Expansion {
+                chip: board(24),
+                registers: |addr| matches!(addr, 0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002),
+            });
+        }
+        if nsf.expansion & EXPANSION_VRC7 != 0 {
+            expansions.push(Expansion {
+                chip: board(85),
+                registers: |addr| addr == 0x9010 || addr == 0x9030,
+            });
+        }
+        if nsf.expansion & EXPANSION_FDS != 0 {
+            expansions.push(Expansion {
+                chip: Box::new(Fds::sound_only()),
+                registers: |addr| matches!(addr, 0x4040..=0x4092),
+            });
+        }
+        if nsf.expansion & EXPANSION_MMC5 != 0 {
+            let mut chip = board(5);
+            // ExRAM as plain RAM.
+            chip.write_prg(0x5104, 0x02);
+            expansions.push(Expansion {
+                chip,
+                registers: |addr| matches!(addr, 0x5000..=0x5015 | 0x5205..=0x5206 | 0x5C00..=0x5FF5),
+            });
+        }
+        if nsf.expansion & EXPANSION_N163 != 0 {
+            expansions.push(Expansion {
+                chip: board(19),
+                registers: |addr| matches!(addr, 0x4800..=0x4FFF | 0xF800..=0xFFFF),
+            });
+        }
+        if nsf.expansion & EXPANSION_SUNSOFT_5B != 0 {
+            expansions.push(Expansion {
+                chip: board(69),
+                registers: |addr| matches!(addr, 0xC000..=0xFFFF),
+            });
+        }
+
+        let mut mapper = NsfMapper {
+            prg,
+            banks,
+            bankswitched: nsf.banks.is_some(),
+            ram: [0; 0x2000],
+            fds_ram: None,
+            expansions,
+        };
+        if nsf.expansion & EXPANSION_FDS != 0 {
+            mapper.fds_ram = Some(vec![0; 0xA000]);
+            for (slot, &bank) in banks.iter().enumerate() {
+                mapper.copy_fds_bank(2 + slot, bank);
+            }
+            // $6000 and $7000 start out with the banks of $E000 and $F000.
+            if nsf.banks.is_some() {
+                mapper.copy_fds_bank(0, banks[6]);
+                mapper.copy_fds_bank(1, banks[7]);
+            }
+        }
+        mapper
+    }
+
+    /// Copies PRG bank `bank` into 4 KB slot `slot` of the FDS RAM at $6000.
+    fn copy_fds_bank(&mut self, slot: usize, bank: u8) {
+        if let Some(ram) = self.fds_ram.as_mut() {
+            let start = bank_offset(self.prg.len(), bank as usize, BANK_SIZE, 0);
+            ram[slot * BANK_SIZE..(slot + 1) * BANK_SIZE].copy_from_slice(&self.prg[start..start + BANK_SIZE]);
+        }
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        if let Some(expansion) = self.expansions.iter_mut().find(|expansion| (expansion.registers)(addr)) {
+            if addr < 0x8000 {
+                return expansion.chip.read_prg(addr);
+            }
+        }
+        if let (Some(ram), 0x6000..=0xFFFF) = (&self.fds_ram, addr) {
+            return ram[(addr - 0x6000) as usize];
+        }
+        match addr {
+            0x6000..=0x7FFF => self.ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => {
+                let bank = self.banks[(addr as usize - 0x8000) / BANK_SIZE] as usize;
+                self.prg[bank_offset(self.prg.len(), bank, BANK_SIZE, addr)]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        for expansion in self.expansions.iter_mut() {
+            if (expansion.registers)(addr) {
+                expansion.chip.write_prg(addr, data);
+            }
+        }
+        if let Some(ram) = self.fds_ram.as_mut() {
+            match addr {
+                0x5FF6..=0x5FFF if self.bankswitched => self.copy_fds_bank((addr - 0x5FF6) as usize, data),
+                0x6000..=0xDFFF => ram[(addr - 0x6000) as usize] = data,
+                _ => {}
+            }
+            return;
+        }
+        match addr {
+            0x5FF8..=0x5FFF if self.bankswitched => self.banks[(addr - 0x5FF8) as usize] = data,
+            0x6000..=0x7FFF => self.ram[(addr - 0x6000) as usize] = data,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn write_chr(&mut self, _addr: u16, _data: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Horizontal
+    }
+
+    fn cpu_clock(&mut self) {
+        for expansion in self.expansions.iter_mut() {
+            expansion.chip.cpu_clock();
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.expansions.iter().map(|expansion| expansion.chip.audio_output()).sum()
+    }
+}
@@ -0,0 +1,62 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::{bank_offset, Chr, Mapper};
+
+/// # UxROM (mapper 2) https://www.nesdev.org/wiki/UxROM
+///
+/// $8000-$BFFF is a switchable 16 KB PRG bank, $C000-$FFFF is fixed to the
+/// last bank. Any write to $8000-$FFFF selects the bank. Submapper 2 boards
+/// have bus conflicts: the written value is ANDed with the ROM byte under it.
+pub struct Uxrom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    bank: usize,
+}
+
+impl Uxrom {
+    pub fn new(rom: Rom) -> Self {
+        Uxrom {
+            chr: Chr::new(&rom),
+            bus_conflicts: rom.submapper == 2,
+            prg_rom: rom.prg_rom,
+            mirroring: rom.screen_mirroring,
+            bank: 0,
+        }
+    }
+
+    fn last_bank(&self) -> usize {
+        self.prg_rom.len() / 0x4000 - 1
+    }
+}
+
+impl Mapper for Uxrom {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xBFFF => self.prg_rom[bank_offset(self.prg_rom.len(), self.bank, 0x4000, addr)],
+            0xC000..=0xFFFF => {
+                self.prg_rom[bank_offset(self.prg_rom.len(), self.last_bank(), 0x4000, addr)]
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if addr >= 0x8000 {
+            let data = if self.bus_conflicts { data & self.read_prg(addr) } else { data };
+            self.bank = data as usize;
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
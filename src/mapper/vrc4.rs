@@ -0,0 +1,167 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::vrc_irq::VrcIrq;
+use crate::mapper::{bank_offset, Chr, Mapper};
+
+/// # VRC2 / VRC4 (mappers 21, 22, 23, 25) https://www.nesdev.org/wiki/VRC2_and_VRC4
+///
+/// The boards differ mostly in which two CPU address lines select one of the
+/// four registers behind each $x000 page. The NES 2.0 submapper says which;
+/// with submapper 0 both candidate pairs are ORed together, which works for
+/// every known game.
+///
+///  $8000 PRG bank 0     $9000 mirroring      $9002 PRG swap mode (VRC4)
+///  $A000 PRG bank 1     $B000-$E003 CHR banks, low and high nibble pairs
+///  $F000/$F001 IRQ latch low/high   $F002 IRQ control   $F003 IRQ acknowledge
+pub struct Vrc4 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Chr,
+    is_vrc2: bool,
+    chr_shift: u8,
+    register_lines: [(u16, u16); 2],
+
+    prg_banks: [u8; 2],
+    prg_swap: bool,
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+}
+
+impl Vrc4 {
+    pub fn new(rom: Rom) -> Self {
+        // Pairs of (line for register bit 0, line for register bit 1).
+        let variants: &[(u16, u16)] = match (rom.mapper, rom.submapper) {
+            (21, 1) => &[(1 << 1, 1 << 2)],
+            (21, 2) => &[(1 << 6, 1 << 7)],
+            (21, _) => &[(1 << 1, 1 << 2), (1 << 6, 1 << 7)],
+            (22, _) => &[(1 << 1, 1 << 0)],
+            (23, 1) | (23, 3) => &[(1 << 0, 1 << 1)],
+            (23, 2) => &[(1 << 2, 1 << 3)],
+            (23, _) => &[(1 << 0, 1 << 1), (1 << 2, 1 << 3)],
+            (25, 1) | (25, 3) => &[(1 << 1, 1 << 0)],
+            (25, 2) => &[(1 << 3, 1 << 2)],
+            (_, _) => &[(1 << 1, 1 << 0), (1 << 3, 1 << 2)],
+        };
+        let register_lines = [variants[0], *variants.last().unwrap()];
+        let is_vrc2 = rom.mapper == 22 || rom.submapper == 3;
+
+        Vrc4 {
+            chr: Chr::new(&rom),
+            is_vrc2,
+            // VRC2a ignores the lowest CHR bank bit.
+            chr_shift: if rom.mapper == 22 { 1 } else { 0 },
+            register_lines,
+            mirroring: rom.screen_mirroring,
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+
+            prg_banks: [0, 0],
+            prg_swap: false,
+            chr_banks: [0; 8],
+            irq: VrcIrq::default(),
+        }
+    }
+
+    /// Folds the board-specific address lines into a register number 0-3.
+    fn register(&self, addr: u16) -> u16 {
+        let (bit0, bit1) = self.register_lines.iter().fold((false, false), |(b0, b1), (l0, l1)| {
+            (b0 || addr & l0 != 0, b1 || addr & l1 != 0)
+        });
+        bit0 as u16 | (bit1 as u16) << 1
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        let second_last = self.prg_rom.len() / 0x2000 - 2;
+        match (addr, self.prg_swap) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.prg_banks[0] as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => self.prg_banks[1] as usize,
+            _ => second_last + 1,
+        }
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => self.prg_rom[bank_offset(self.prg_rom.len(), self.prg_bank(addr), 0x2000, addr)],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if addr < 0x8000 {
+            if addr >= 0x6000 {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            return;
+        }
+
+        let register = self.register(addr);
+        match (addr & 0xF000, register) {
+            (0x8000, _) => self.prg_banks[0] = data & 0b1_1111,
+            (0x9000, 0) | (0x9000, 1) if self.is_vrc2 => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                };
+            }
+            (0x9000, 0) => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            (0x9000, 2) if !self.is_vrc2 => self.prg_swap = data & 0b10 != 0,
+            (0xA000, _) => self.prg_banks[1] = data & 0b1_1111,
+            (0xB000..=0xE000, _) => {
+                let bank = (((addr & 0xF000) - 0xB000) / 0x1000 * 2 + (register >> 1)) as usize;
+                self.chr_banks[bank] = if register & 1 == 0 {
+                    (self.chr_banks[bank] & 0x1F0) | (data & 0x0F) as u16
+                } else {
+                    (self.chr_banks[bank] & 0x00F) | ((data & 0x1F) as u16) << 4
+                };
+            }
+            (0xF000, 0) if !self.is_vrc2 => self.irq.write_latch_low(data),
+            (0xF000, 1) if !self.is_vrc2 => self.irq.write_latch_high(data),
+            (0xF000, 2) if !self.is_vrc2 => self.irq.write_control(data),
+            (0xF000, 3) if !self.is_vrc2 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let bank = (self.chr_banks[addr as usize >> 10] >> self.chr_shift) as usize;
+        self.chr.read(bank_offset(self.chr.size(), bank, 0x400, addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        let bank = (self.chr_banks[addr as usize >> 10] >> self.chr_shift) as usize;
+        let offset = bank_offset(self.chr.size(), bank, 0x400, addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.cpu_clock();
+    }
+}
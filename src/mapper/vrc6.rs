@@ -0,0 +1,259 @@
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::vrc_irq::VrcIrq;
+use crate::mapper::{bank_offset, Chr, Mapper};
+
+/// Scales the 6-bit VRC6 mix so a full-volume VRC6 pulse is about as loud
+/// as a full-volume APU pulse.
+const VRC6_LEVEL: f32 = 0.01;
+
+#[derive(Default)]
+struct VrcPulse {
+    volume: u8,
+    duty: u8,
+    ignore_duty: bool,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+}
+
+impl VrcPulse {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.ignore_duty = data & 0x80 != 0;
+                self.duty = (data >> 4) & 0b111;
+                self.volume = data & 0x0F;
+            }
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((data & 0x0F) as u16) << 8;
+                self.enabled = data & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = (self.step + 1) & 0x0F;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Default)]
+struct VrcSawtooth {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl VrcSawtooth {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => self.rate = data & 0b11_1111,
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((data & 0x0F) as u16) << 8;
+                self.enabled = data & 0x80 != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            // The accumulator grows on steps 2, 4, ... 12, six adds for seven
+            // output levels, and resets on step 14.
+            self.step += 1;
+            if self.step == 14 {
+                self.step = 0;
+                self.accumulator = 0;
+            } else if self.step & 1 == 0 {
+                self.accumulator = self.accumulator.wrapping_add(self.rate);
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+/// # VRC6 (mappers 24, 26) https://www.nesdev.org/wiki/VRC6
+///
+/// Mapper 26 swaps address lines A0 and A1 relative to mapper 24.
+///
+///  $8000 16 KB PRG at $8000    $C000 8 KB PRG at $C000    $E000 fixed to last
+///  $9000-$9002 pulse 1         $A000-$A002 pulse 2        $B000-$B002 sawtooth
+///  $9003 audio frequency control
+///  $B003 PPU banking style     $D000-$E003 CHR banks R0-R7
+///  $F000 IRQ latch             $F001 IRQ control          $F002 IRQ acknowledge
+pub struct Vrc6 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Chr,
+    swap_lines: bool,
+
+    prg_16k: u8,
+    prg_8k: u8,
+    chr_banks: [u8; 8],
+    banking_style: u8,
+    irq: VrcIrq,
+
+    halt: bool,
+    frequency_shift: u8,
+    pulses: [VrcPulse; 2],
+    sawtooth: VrcSawtooth,
+}
+
+impl Vrc6 {
+    pub fn new(rom: Rom) -> Self {
+        Vrc6 {
+            chr: Chr::new(&rom),
+            swap_lines: rom.mapper == 26,
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+
+            prg_16k: 0,
+            prg_8k: 0,
+            chr_banks: [0; 8],
+            banking_style: 0,
+            irq: VrcIrq::default(),
+
+            halt: false,
+            frequency_shift: 0,
+            pulses: [VrcPulse::default(), VrcPulse::default()],
+            sawtooth: VrcSawtooth::default(),
+        }
+    }
+
+    fn chr_bank(&self, addr: u16) -> usize {
+        let slot = addr as usize >> 10;
+        match self.banking_style & 0b11 {
+            0 => self.chr_banks[slot] as usize,
+            1 => ((self.chr_banks[slot >> 1] as usize) << 1) | (slot & 1),
+            _ if slot < 4 => self.chr_banks[slot] as usize,
+            _ => ((self.chr_banks[4 + ((slot - 4) >> 1)] as usize) << 1) | (slot & 1),
+        }
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.banking_style & 0x80 != 0 => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xBFFF => self.prg_rom[bank_offset(self.prg_rom.len(), self.prg_16k as usize, 0x4000, addr)],
+            0xC000..=0xDFFF => self.prg_rom[bank_offset(self.prg_rom.len(), self.prg_8k as usize, 0x2000, addr)],
+            0xE000..=0xFFFF => self.prg_rom[self.prg_rom.len() - 0x2000 + (addr - 0xE000) as usize],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        let register = if self.swap_lines {
+            (addr & 1) << 1 | (addr >> 1) & 1
+        } else {
+            addr & 0b11
+        };
+        match (addr & 0xF000, register) {
+            (0x6000..=0x7000, _) if self.banking_style & 0x80 != 0 => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            (0x8000, _) => self.prg_16k = data & 0x0F,
+            (0x9000, 3) => {
+                self.halt = data & 1 != 0;
+                self.frequency_shift = if data & 0b100 != 0 {
+                    8
+                } else if data & 0b10 != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            (0x9000, r) => self.pulses[0].write(r, data),
+            (0xA000, r) if r < 3 => self.pulses[1].write(r, data),
+            (0xB000, 3) => self.banking_style = data,
+            (0xB000, r) => self.sawtooth.write(r, data),
+            (0xC000, _) => self.prg_8k = data & 0x1F,
+            (0xD000, r) => self.chr_banks[r as usize] = data,
+            (0xE000, r) => self.chr_banks[4 + r as usize] = data,
+            (0xF000, 0) => self.irq.write_latch(data),
+            (0xF000, 1) => self.irq.write_control(data),
+            (0xF000, 2) => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        self.chr.read(bank_offset(self.chr.size(), self.chr_bank(addr), 0x400, addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        let offset = bank_offset(self.chr.size(), self.chr_bank(addr), 0x400, addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.banking_style >> 2) & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.cpu_clock();
+        if !self.halt {
+            self.pulses[0].clock(self.frequency_shift);
+            self.pulses[1].clock(self.frequency_shift);
+            self.sawtooth.clock(self.frequency_shift);
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        let sum = self.pulses[0].output() + self.pulses[1].output() + self.sawtooth.output();
+        sum as f32 * VRC6_LEVEL
+    }
+}
@@ -0,0 +1,482 @@
+use std::f32::consts::PI;
+
+use crate::cartridge::{Mirroring, Rom};
+use crate::mapper::vrc_irq::VrcIrq;
+use crate::mapper::{bank_offset, Chr, Mapper};
+
+/// The OPLL produces one sample every 36 CPU cycles (3.58 MHz / 72).
+const OPLL_CYCLES: u8 = 36;
+const OPLL_RATE: f32 = 1_789_773.0 / OPLL_CYCLES as f32;
+
+/// Output level of one channel at full volume, relative to the APU mix.
+const VRC7_LEVEL: f32 = 0.12;
+
+/// Built-in instruments 1-15; instrument 0 is the custom one in $00-$07.
+#[rustfmt::skip]
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+/// Frequency multiplier per MULT value; MULT 0 halves the frequency.
+const MULTIPLIERS: [f32; 16] = [
+    0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0,
+];
+
+/// Key scale attenuation in dB for the top four F-number bits at block 7.
+const KSL_TABLE: [f32; 16] = [
+    0.0, 9.0, 12.0, 13.875, 15.0, 16.125, 16.875, 17.625,
+    18.0, 18.75, 19.125, 19.5, 19.875, 20.25, 20.625, 21.0,
+];
+
+/// Attenuation at which an operator counts as silent.
+const MAX_ATTENUATION: f32 = 48.0;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Operator settings decoded from one half of an instrument patch.
+#[derive(Clone, Copy, Default)]
+struct OperatorPatch {
+    tremolo: bool,
+    vibrato: bool,
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: f32,
+    key_scale_level: u8,
+    rectified: bool,
+    attack: u8,
+    decay: u8,
+    sustain_level: u8,
+    release: u8,
+}
+
+impl OperatorPatch {
+    fn decode(patch: &[u8; 8], carrier: bool) -> Self {
+        let i = carrier as usize;
+        OperatorPatch {
+            tremolo: patch[i] & 0x80 != 0,
+            vibrato: patch[i] & 0x40 != 0,
+            sustained: patch[i] & 0x20 != 0,
+            key_scale_rate: patch[i] & 0x10 != 0,
+            multiplier: MULTIPLIERS[(patch[i] & 0x0F) as usize],
+            key_scale_level: patch[2 + i] >> 6,
+            rectified: patch[3] & if carrier { 0x10 } else { 0x08 } != 0,
+            attack: patch[4 + i] >> 4,
+            decay: patch[4 + i] & 0x0F,
+            sustain_level: patch[6 + i] >> 4,
+            release: patch[6 + i] & 0x0F,
+        }
+    }
+}
+
+struct Operator {
+    phase: f32,
+    state: EnvelopeState,
+    attenuation: f32,
+}
+
+impl Operator {
+    fn new() -> Self {
+        Operator {
+            phase: 0.0,
+            state: EnvelopeState::Release,
+            attenuation: MAX_ATTENUATION,
+        }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0.0;
+        self.state = EnvelopeState::Attack;
+    }
+
+    fn key_off(&mut self) {
+        self.state = EnvelopeState::Release;
+    }
+
+    /// dB per sample for a 4-bit rate. Each step of the effective rate
+    /// (rate * 4 + key scaling) doubles the speed every four steps.
+    fn rate_step(rate: u8, key_scale: u8) -> f32 {
+        if rate == 0 {
+            return 0.0;
+        }
+        let effective = (rate * 4 + key_scale).min(63) as f32;
+        let seconds = 19.2 * (-effective / 4.0).exp2();
+        MAX_ATTENUATION / (seconds * OPLL_RATE)
+    }
+
+    fn clock_envelope(&mut self, patch: &OperatorPatch, key_scale: u8, channel_sustain: bool) {
+        match self.state {
+            EnvelopeState::Attack => {
+                if patch.attack == 15 {
+                    self.attenuation = 0.0;
+                } else {
+                    let step = Operator::rate_step(patch.attack, key_scale) * 6.0;
+                    self.attenuation -= step * (1.0 + self.attenuation / 8.0);
+                }
+                if self.attenuation <= 0.0 {
+                    self.attenuation = 0.0;
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                self.attenuation += Operator::rate_step(patch.decay, key_scale);
+                let sustain_level = patch.sustain_level as f32 * 3.0;
+                if self.attenuation >= sustain_level {
+                    self.attenuation = sustain_level;
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain => {
+                if !patch.sustained {
+                    self.attenuation += Operator::rate_step(patch.release, key_scale);
+                }
+            }
+            EnvelopeState::Release => {
+                let rate = if channel_sustain {
+                    5
+                } else if patch.sustained {
+                    patch.release
+                } else {
+                    7
+                };
+                self.attenuation += Operator::rate_step(rate, key_scale);
+            }
+        }
+        self.attenuation = self.attenuation.min(MAX_ATTENUATION);
+    }
+
+    fn output(&mut self, increment: f32, modulation: f32, attenuation: f32, rectified: bool) -> f32 {
+        self.phase = (self.phase + increment).fract();
+        let wave = (2.0 * PI * (self.phase + modulation)).sin();
+        let wave = if rectified && wave < 0.0 { 0.0 } else { wave };
+        let total = self.attenuation + attenuation;
+        if total >= MAX_ATTENUATION {
+            0.0
+        } else {
+            wave * 10f32.powf(-total / 20.0)
+        }
+    }
+}
+
+struct FmChannel {
+    fnum: u16,
+    block: u8,
+    key: bool,
+    sustain: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+    feedback: [f32; 2],
+}
+
+impl FmChannel {
+    fn new() -> Self {
+        FmChannel {
+            fnum: 0,
+            block: 0,
+            key: false,
+            sustain: false,
+            instrument: 0,
+            volume: 0,
+            modulator: Operator::new(),
+            carrier: Operator::new(),
+            feedback: [0.0; 2],
+        }
+    }
+
+    fn key_scale(&self, patch: &OperatorPatch) -> u8 {
+        let scale = (self.block << 1) | (self.fnum >> 8) as u8;
+        if patch.key_scale_rate {
+            scale
+        } else {
+            scale >> 2
+        }
+    }
+
+    fn key_scale_attenuation(&self, patch: &OperatorPatch) -> f32 {
+        if patch.key_scale_level == 0 {
+            return 0.0;
+        }
+        let base = KSL_TABLE[(self.fnum >> 5) as usize] - 6.0 * (7 - self.block) as f32;
+        base.max(0.0) * [0.0, 0.5, 1.0, 2.0][patch.key_scale_level as usize]
+    }
+
+    fn sample(&mut self, patch: &[u8; 8], tremolo: f32, vibrato: f32) -> f32 {
+        let modulator_patch = OperatorPatch::decode(patch, false);
+        let carrier_patch = OperatorPatch::decode(patch, true);
+
+        let base = self.fnum as f32 * (self.block as f32).exp2() / (1 << 19) as f32;
+        let increment = |op: &OperatorPatch| {
+            base * op.multiplier * if op.vibrato { 1.0 + vibrato } else { 1.0 }
+        };
+        let extra = |op: &OperatorPatch, level: f32| {
+            level + self.key_scale_attenuation(op) + if op.tremolo { tremolo } else { 0.0 }
+        };
+
+        let modulator_level = extra(&modulator_patch, (patch[2] & 0x3F) as f32 * 0.75);
+        let carrier_level = extra(&carrier_patch, self.volume as f32 * 3.0);
+        let modulator_increment = increment(&modulator_patch);
+        let carrier_increment = increment(&carrier_patch);
+        let modulator_key_scale = self.key_scale(&modulator_patch);
+        let carrier_key_scale = self.key_scale(&carrier_patch);
+
+        self.modulator.clock_envelope(&modulator_patch, modulator_key_scale, self.sustain);
+        self.carrier.clock_envelope(&carrier_patch, carrier_key_scale, self.sustain);
+
+        let feedback = patch[3] & 0b111;
+        let feedback_phase = if feedback == 0 {
+            0.0
+        } else {
+            (self.feedback[0] + self.feedback[1]) / 2.0 * (feedback as f32).exp2() / 128.0
+        };
+        let modulation = self.modulator.output(
+            modulator_increment,
+            feedback_phase,
+            modulator_level,
+            modulator_patch.rectified,
+        );
+        self.feedback = [self.feedback[1], modulation];
+
+        self.carrier.output(carrier_increment, modulation * 2.0, carrier_level, carrier_patch.rectified)
+    }
+}
+
+/// # YM2413 (OPLL) subset found in the VRC7 https://www.nesdev.org/wiki/VRC7_audio
+///
+/// Six two-operator FM channels with the VRC7's own instrument ROM. Rhythm
+/// mode and the test register are not implemented since no VRC7 game uses
+/// them.
+struct Opll {
+    register_select: u8,
+    custom_patch: [u8; 8],
+    channels: Vec<FmChannel>,
+    divider: u8,
+    tremolo_phase: f32,
+    vibrato_phase: f32,
+    output: f32,
+}
+
+impl Opll {
+    fn new() -> Self {
+        Opll {
+            register_select: 0,
+            custom_patch: [0; 8],
+            channels: (0..6).map(|_| FmChannel::new()).collect(),
+            divider: 0,
+            tremolo_phase: 0.0,
+            vibrato_phase: 0.0,
+            output: 0.0,
+        }
+    }
+
+    fn write(&mut self, data: u8) {
+        let register = self.register_select;
+        match register {
+            0x00..=0x07 => self.custom_patch[register as usize] = data,
+            0x10..=0x15 => {
+                let channel = &mut self.channels[(register - 0x10) as usize];
+                channel.fnum = (channel.fnum & 0x100) | data as u16;
+            }
+            0x20..=0x25 => {
+                let channel = &mut self.channels[(register - 0x20) as usize];
+                channel.fnum = (channel.fnum & 0xFF) | ((data & 1) as u16) << 8;
+                channel.block = (data >> 1) & 0b111;
+                channel.sustain = data & 0x20 != 0;
+                let key = data & 0x10 != 0;
+                if key && !channel.key {
+                    channel.modulator.key_on();
+                    channel.carrier.key_on();
+                } else if !key && channel.key {
+                    channel.modulator.key_off();
+                    channel.carrier.key_off();
+                }
+                channel.key = key;
+            }
+            0x30..=0x35 => {
+                let channel = &mut self.channels[(register - 0x30) as usize];
+                channel.instrument = data >> 4;
+                channel.volume = data & 0x0F;
+            }
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        self.divider += 1;
+        if self.divider < OPLL_CYCLES {
+            return;
+        }
+        self.divider = 0;
+
+        // Tremolo is 3.7 Hz with a 4.8 dB depth, vibrato 6.4 Hz at about 14 cents.
+        self.tremolo_phase = (self.tremolo_phase + 3.7 / OPLL_RATE).fract();
+        self.vibrato_phase = (self.vibrato_phase + 6.4 / OPLL_RATE).fract();
+        let tremolo = 2.4 * (1.0 + (2.0 * PI * self.tremolo_phase).sin());
+        let vibrato = 0.008 * (2.0 * PI * self.vibrato_phase).sin();
+
+        let custom_patch = self.custom_patch;
+        self.output = self
+            .channels
+            .iter_mut()
+            .map(|channel| {
+                let patch = match channel.instrument {
+                    0 => &custom_patch,
+                    n => &PATCHES[n as usize - 1],
+                };
+                channel.sample(patch, tremolo, vibrato)
+            })
+            .sum();
+    }
+}
+
+/// # VRC7 (mapper 85) https://www.nesdev.org/wiki/VRC7
+///
+/// VRC7a (submapper 2) uses A4 to pick the second register of a page, VRC7b
+/// (submapper 1) uses A3; submapper 0 accepts either.
+///
+///  $8000 / $8010 / $9000   8 KB PRG banks for $8000, $A000, $C000
+///  $9010 audio register select, $9030 audio register data
+///  $A000-$D010             CHR banks R0-R7
+///  $E000 mirroring, audio reset, WRAM enable
+///  $E010 IRQ latch         $F000 IRQ control         $F010 IRQ acknowledge
+pub struct Vrc7 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Chr,
+    second_register_mask: u16,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    control: u8,
+    irq: VrcIrq,
+    opll: Opll,
+}
+
+impl Vrc7 {
+    pub fn new(rom: Rom) -> Self {
+        Vrc7 {
+            chr: Chr::new(&rom),
+            second_register_mask: match rom.submapper {
+                1 => 0x08,
+                2 => 0x10,
+                _ => 0x18,
+            },
+            prg_rom: rom.prg_rom,
+            prg_ram: [0; 0x2000],
+
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::default(),
+            opll: Opll::new(),
+        }
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        match addr {
+            0x8000..=0xDFFF => self.prg_banks[((addr - 0x8000) / 0x2000) as usize] as usize,
+            _ => self.prg_rom.len() / 0x2000 - 1,
+        }
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn read_prg(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.control & 0x80 != 0 => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => self.prg_rom[bank_offset(self.prg_rom.len(), self.prg_bank(addr), 0x2000, addr)],
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        let second = addr & self.second_register_mask != 0;
+        match (addr & 0xF000, second) {
+            (0x6000..=0x7000, _) if self.control & 0x80 != 0 => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+            }
+            (0x8000, false) => self.prg_banks[0] = data & 0x3F,
+            (0x8000, true) => self.prg_banks[1] = data & 0x3F,
+            (0x9000, _) if addr & 0x30 == 0x10 => self.opll.register_select = data,
+            (0x9000, _) if addr & 0x30 == 0x30 => self.opll.write(data),
+            (0x9000, false) => self.prg_banks[2] = data & 0x3F,
+            (0xA000..=0xD000, _) => {
+                let bank = ((addr & 0xF000) - 0xA000) / 0x1000 * 2 + second as u16;
+                self.chr_banks[bank as usize] = data;
+            }
+            (0xE000, false) => {
+                self.control = data;
+                if data & 0x40 != 0 {
+                    self.opll = Opll::new();
+                }
+            }
+            (0xE000, true) => self.irq.write_latch(data),
+            (0xF000, false) => self.irq.write_control(data),
+            (0xF000, true) => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, addr: u16) -> u8 {
+        let bank = self.chr_banks[addr as usize >> 10] as usize;
+        self.chr.read(bank_offset(self.chr.size(), bank, 0x400, addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        let bank = self.chr_banks[addr as usize >> 10] as usize;
+        let offset = bank_offset(self.chr.size(), bank, 0x400, addr);
+        self.chr.write(offset, data);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.cpu_clock();
+        if self.control & 0x40 == 0 {
+            self.opll.clock();
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.opll.output * VRC7_LEVEL
+    }
+}
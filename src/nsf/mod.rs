@@ -0,0 +1,273 @@
+pub mod player;
+
+use crate::region::Region;
+use std::time::Duration;
+
+const NSF_MAGIC: &[u8] = b"NESM\x1A";
+const NSFE_MAGIC: &[u8] = b"NSFE";
+const NSF_HEADER_SIZE: usize = 0x80;
+
+/// Expansion sound chips, as bits of the header's expansion byte.
+pub const EXPANSION_VRC6: u8 = 0b0000_0001;
+pub const EXPANSION_VRC7: u8 = 0b0000_0010;
+pub const EXPANSION_FDS: u8 = 0b0000_0100;
+pub const EXPANSION_MMC5: u8 = 0b0000_1000;
+pub const EXPANSION_N163: u8 = 0b0001_0000;
+pub const EXPANSION_SUNSOFT_5B: u8 = 0b0010_0000;
+
+/// Whether `raw` looks like an NSF or NSFe file rather than a ROM.
+pub fn is_nsf(raw: &[u8]) -> bool {
+    raw.starts_with(NSF_MAGIC) || raw.starts_with(NSFE_MAGIC)
+}
+
+/// # NSF https://www.nesdev.org/wiki/NSF
+///
+/// A game's music driver and data without the game: the player loads the
+/// data at `load_address`, calls `init_address` once with the track number
+/// in A, then `play_address` at `play_speed` microsecond intervals.
+///
+/// NSFe (https://www.nesdev.org/wiki/NSFe) carries the same information in
+/// chunks, plus track titles and durations; NSF2 files can append the same
+/// chunks after their data.
+pub struct Nsf {
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub tracks: u8,
+    /// 0-based.
+    pub starting_track: u8,
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    /// Microseconds between PLAY calls, per region.
+    pub play_speed_ntsc: u16,
+    pub play_speed_pal: u16,
+    pub region: Region,
+    /// Initial 4 KB banks of $8000-$FFFF, for tunes that bank switch.
+    pub banks: Option<[u8; 8]>,
+    pub expansion: u8,
+    pub data: Vec<u8>,
+    pub track_titles: Vec<Option<String>>,
+    pub track_durations: Vec<Option<Duration>>,
+    pub track_fades: Vec<Option<Duration>>,
+}
+
+impl Nsf {
+    pub fn new(raw: &[u8]) -> Result<Nsf, String> {
+        if raw.starts_with(NSF_MAGIC) {
+            Nsf::from_nsf(raw)
+        } else if raw.starts_with(NSFE_MAGIC) {
+            Nsf::from_nsfe(raw)
+        } else {
+            Err("File is not in NSF or NSFe format".to_string())
+        }
+    }
+
+    fn from_nsf(raw: &[u8]) -> Result<Nsf, String> {
+        if raw.len() < NSF_HEADER_SIZE {
+            return Err("NSF header is truncated".to_string());
+        }
+        let word = |offset: usize| u16::from_le_bytes([raw[offset], raw[offset + 1]]);
+        let banks: [u8; 8] = raw[0x70..0x78].try_into().unwrap();
+
+        // NSF2 can give the length of the data and follow it with NSFe
+        // metadata chunks.
+        let data_length = raw[0x7D] as usize | (raw[0x7E] as usize) << 8 | (raw[0x7F] as usize) << 16;
+        let (data, metadata) = if raw[5] >= 2 && data_length > 0 {
+            let end = (NSF_HEADER_SIZE + data_length).min(raw.len());
+            (&raw[NSF_HEADER_SIZE..end], &raw[end..])
+        } else {
+            (&raw[NSF_HEADER_SIZE..], &[][..])
+        };
+
+        let mut nsf = Nsf {
+            load_address: word(0x08),
+            init_address: word(0x0A),
+            play_address: word(0x0C),
+            tracks: raw[0x06],
+            starting_track: raw[0x07].saturating_sub(1),
+            title: header_string(&raw[0x0E..0x2E]),
+            artist: header_string(&raw[0x2E..0x4E]),
+            copyright: header_string(&raw[0x4E..0x6E]),
+            play_speed_ntsc: word(0x6E),
+            play_speed_pal: word(0x78),
+            region: region(raw[0x7A]),
+            banks: if banks.iter().any(|&bank| bank != 0) { Some(banks) } else { None },
+            expansion: raw[0x7B],
+            data: data.to_vec(),
+            track_titles: Vec::new(),
+            track_durations: Vec::new(),
+            track_fades: Vec::new(),
+        };
+        for (id, chunk) in chunks(metadata)? {
+            nsf.read_metadata(id, chunk)?;
+        }
+        Ok(nsf)
+    }
+
+    fn from_nsfe(raw: &[u8]) -> Result<Nsf, String> {
+        let mut nsf = Nsf {
+            load_address: 0,
+            init_address: 0,
+            play_address: 0,
+            tracks: 1,
+            starting_track: 0,
+            title: String::new(),
+            artist: String::new(),
+            copyright: String::new(),
+            play_speed_ntsc: 0,
+            play_speed_pal: 0,
+            region: Region::Ntsc,
+            banks: None,
+            expansion: 0,
+            data: Vec::new(),
+            track_titles: Vec::new(),
+            track_durations: Vec::new(),
+            track_fades: Vec::new(),
+        };
+        let mut info = false;
+        for (id, chunk) in chunks(&raw[NSFE_MAGIC.len()..])? {
+            match id {
+                b"INFO" => {
+                    if chunk.len() < 10 {
+                        return Err("NSFe INFO chunk is truncated".to_string());
+                    }
+                    let word = |offset: usize| u16::from_le_bytes([chunk[offset], chunk[offset + 1]]);
+                    nsf.load_address = word(0);
+                    nsf.init_address = word(2);
+                    nsf.play_address = word(4);
+                    nsf.region = region(chunk[6]);
+                    nsf.expansion = chunk[7];
+                    nsf.tracks = chunk.get(8).copied().unwrap_or(1);
+                    nsf.starting_track = chunk.get(9).copied().unwrap_or(0);
+                    info = true;
+                }
+                b"DATA" => nsf.data = chunk.to_vec(),
+                b"BANK" => {
+                    let mut banks = [0; 8];
+                    for (bank, &value) in banks.iter_mut().zip(chunk) {
+                        *bank = value;
+                    }
+                    nsf.banks = Some(banks);
+                }
+                b"RATE" => {
+                    let word = |offset: usize| {
+                        chunk.get(offset..offset + 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+                    };
+                    nsf.play_speed_ntsc = word(0).unwrap_or(0);
+                    nsf.play_speed_pal = word(2).unwrap_or(0);
+                }
+                b"auth" => {
+                    let mut strings = chunk.split(|&byte| byte == 0).map(text);
+                    nsf.title = strings.next().unwrap_or_default();
+                    nsf.artist = strings.next().unwrap_or_default();
+                    nsf.copyright = strings.next().unwrap_or_default();
+                }
+                b"NEND" => break,
+                _ => nsf.read_metadata(id, chunk)?,
+            }
+        }
+        if !info {
+            return Err("NSFe file has no INFO chunk".to_string());
+        }
+        if nsf.data.is_empty() {
+            return Err("NSFe file has no DATA chunk".to_string());
+        }
+        Ok(nsf)
+    }
+
+    /// Chunks that NSFe and NSF2 share.
+    fn read_metadata(&mut self, id: &[u8], chunk: &[u8]) -> Result<(), String> {
+        match id {
+            b"tlbl" => {
+                self.track_titles = chunk
+                    .split(|&byte| byte == 0)
+                    .take(self.tracks as usize)
+                    .map(|title| Some(text(title)).filter(|title| !title.is_empty()))
+                    .collect();
+            }
+            b"time" => self.track_durations = milliseconds(chunk),
+            b"fade" => self.track_fades = milliseconds(chunk),
+            b"NEND" => {}
+            // Chunks whose id starts with a capital letter must be
+            // understood to play the file; the rest are optional.
+            _ if id[0].is_ascii_uppercase() => {
+                return Err(format!("Unsupported NSFe chunk {}", String::from_utf8_lossy(id)));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Microseconds between PLAY calls on `region`.
+    pub fn play_speed(&self, region: Region) -> u16 {
+        match region {
+            Region::Pal if self.play_speed_pal != 0 => self.play_speed_pal,
+            Region::Pal => 19997,
+            Region::Ntsc | Region::Dendy if self.play_speed_ntsc != 0 => self.play_speed_ntsc,
+            Region::Ntsc | Region::Dendy => 16639,
+        }
+    }
+
+    pub fn track_title(&self, track: u8) -> Option<&str> {
+        self.track_titles.get(track as usize)?.as_deref()
+    }
+
+    pub fn track_duration(&self, track: u8) -> Option<Duration> {
+        *self.track_durations.get(track as usize)?
+    }
+
+    pub fn track_fade(&self, track: u8) -> Option<Duration> {
+        *self.track_fades.get(track as usize)?
+    }
+}
+
+fn region(flags: u8) -> Region {
+    // Bit 1 marks tunes that play on both; those get NTSC.
+    if flags & 0b11 == 0b01 {
+        Region::Pal
+    } else {
+        Region::Ntsc
+    }
+}
+
+fn header_string(field: &[u8]) -> String {
+    text(field.split(|&byte| byte == 0).next().unwrap_or_default())
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).trim().to_string()
+}
+
+/// The id and data of an NSFe chunk.
+type Chunk<'a> = (&'a [u8], &'a [u8]);
+
+/// Splits NSFe chunks: a 32-bit length, a 4-byte id, then the data.
+fn chunks(mut raw: &[u8]) -> Result<Vec<Chunk<'_>>, String> {
+    let mut chunks = Vec::new();
+    while raw.len() >= 8 {
+        let length = u32::from_le_bytes(raw[0..4].try_into().unwrap()) as usize;
+        let id = &raw[4..8];
+        let chunk = raw
+            .get(8..8 + length)
+            .ok_or_else(|| format!("NSFe chunk {} is truncated", String::from_utf8_lossy(id)))?;
+        chunks.push((id, chunk));
+        raw = &raw[8 + length..];
+    }
+    Ok(chunks)
+}
+
+/// Signed 32-bit millisecond counts, where negative means unknown.
+fn milliseconds(chunk: &[u8]) -> Vec<Option<Duration>> {
+    chunk
+        .chunks_exact(4)
+        .map(|bytes| {
+            let ms = i32::from_le_bytes(bytes.try_into().unwrap());
+            (ms >= 0).then(|| Duration::from_millis(ms as u64))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[path = "nsf_tests.rs"]
+mod nsf_tests;
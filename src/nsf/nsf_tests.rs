@@ -0,0 +1,286 @@
+#[cfg(test)]
+mod test {
+    use crate::cpu::Mem;
+    use crate::mapper::nsf::NsfMapper;
+    use crate::mapper::Mapper;
+    use crate::nsf::player::*;
+    use crate::nsf::*;
+    use crate::region::Region;
+    use crate::wav::WavRecorder;
+    use std::time::Duration;
+
+    // INIT ($8000): STA $00, RTS. PLAY ($8003): INC $01, RTS.
+    const CODE: [u8; 6] = [0x85, 0x00, 0x60, 0xe6, 0x01, 0x60];
+
+    fn nsf_file(data: &[u8], banks: [u8; 8], expansion: u8) -> Vec<u8> {
+        let mut raw = vec![0; 0x80];
+        raw[0..5].copy_from_slice(b"NESM\x1A");
+        raw[5] = 1;
+        raw[6] = 3;
+        raw[7] = 2;
+        raw[8..10].copy_from_slice(&0x8000u16.to_le_bytes());
+        raw[10..12].copy_from_slice(&0x8000u16.to_le_bytes());
+        raw[12..14].copy_from_slice(&0x8003u16.to_le_bytes());
+        raw[0x0E..0x13].copy_from_slice(b"Title");
+        raw[0x2E..0x34].copy_from_slice(b"Artist");
+        raw[0x4E..0x52].copy_from_slice(b"2026");
+        raw[0x6E..0x70].copy_from_slice(&16639u16.to_le_bytes());
+        raw[0x70..0x78].copy_from_slice(&banks);
+        raw[0x78..0x7A].copy_from_slice(&19997u16.to_le_bytes());
+        raw[0x7B] = expansion;
+        raw.extend_from_slice(data);
+        raw
+    }
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_le_bytes().to_vec();
+        chunk.extend_from_slice(id);
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    fn metadata() -> Vec<u8> {
+        let mut raw = chunk(b"tlbl", b"Opening\0\0Ending\0");
+        let times: Vec<u8> = [90_000i32, -1, 30_500].iter().flat_map(|ms| ms.to_le_bytes()).collect();
+        raw.extend(chunk(b"time", &times));
+        raw.extend(chunk(b"fade", &5_000i32.to_le_bytes()));
+        raw
+    }
+
+    #[test]
+    fn test_nsf_header() {
+        let nsf = Nsf::new(&nsf_file(&CODE, [0; 8], 0)).unwrap();
+        assert_eq!(nsf.load_address, 0x8000);
+        assert_eq!(nsf.init_address, 0x8000);
+        assert_eq!(nsf.play_address, 0x8003);
+        assert_eq!(nsf.tracks, 3);
+        assert_eq!(nsf.starting_track, 1);
+        assert_eq!(nsf.title, "Title");
+        assert_eq!(nsf.artist, "Artist");
+        assert_eq!(nsf.copyright, "2026");
+        assert_eq!(nsf.play_speed(Region::Ntsc), 16639);
+        assert_eq!(nsf.play_speed(Region::Pal), 19997);
+        assert_eq!(nsf.region, Region::Ntsc);
+        assert_eq!(nsf.banks, None);
+        assert_eq!(nsf.data, CODE.to_vec());
+        assert_eq!(nsf.track_duration(0), None);
+
+        assert!(Nsf::new(b"NESM\x1A").is_err());
+        assert!(Nsf::new(&[0; 0x100]).is_err());
+    }
+
+    #[test]
+    fn test_nsf2_metadata() {
+        let mut raw = nsf_file(&CODE, [0; 8], 0);
+        raw[5] = 2;
+        raw[0x7D] = CODE.len() as u8;
+        raw.extend(metadata());
+        let nsf = Nsf::new(&raw).unwrap();
+        assert_eq!(nsf.data, CODE.to_vec());
+        assert_eq!(nsf.track_title(0), Some("Opening"));
+        assert_eq!(nsf.track_title(1), None);
+        assert_eq!(nsf.track_title(2), Some("Ending"));
+        assert_eq!(nsf.track_duration(0), Some(Duration::from_millis(90_000)));
+        assert_eq!(nsf.track_duration(1), None);
+        assert_eq!(nsf.track_duration(2), Some(Duration::from_millis(30_500)));
+        assert_eq!(nsf.track_fade(0), Some(Duration::from_secs(5)));
+        assert_eq!(nsf.track_fade(1), None);
+    }
+
+    fn nsfe_file(extra: &[u8]) -> Vec<u8> {
+        let mut raw = b"NSFE".to_vec();
+        let mut info = Vec::new();
+        for word in [0x8000u16, 0x8000, 0x8003] {
+            info.extend_from_slice(&word.to_le_bytes());
+        }
+        info.extend_from_slice(&[0x01, EXPANSION_VRC6, 3, 1]);
+        raw.extend(chunk(b"INFO", &info));
+        raw.extend(chunk(b"DATA", &CODE));
+        raw.extend(chunk(b"BANK", &[0, 1]));
+        raw.extend(chunk(b"RATE", &10000u16.to_le_bytes()));
+        raw.extend(chunk(b"auth", b"Game\0Composer\0Company\0Ripper\0"));
+        raw.extend_from_slice(extra);
+        raw.extend(metadata());
+        raw.extend(chunk(b"NEND", &[]));
+        raw
+    }
+
+    #[test]
+    fn test_nsfe() {
+        let nsf = Nsf::new(&nsfe_file(&[])).unwrap();
+        assert_eq!(nsf.play_address, 0x8003);
+        assert_eq!(nsf.region, Region::Pal);
+        assert_eq!(nsf.expansion, EXPANSION_VRC6);
+        assert_eq!(nsf.tracks, 3);
+        assert_eq!(nsf.starting_track, 1);
+        assert_eq!(nsf.banks, Some([0, 1, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(nsf.play_speed(Region::Ntsc), 10000);
+        assert_eq!(nsf.play_speed(Region::Pal), 19997);
+        assert_eq!((nsf.title.as_str(), nsf.artist.as_str()), ("Game", "Composer"));
+        assert_eq!(nsf.copyright, "Company");
+        assert_eq!(nsf.track_title(2), Some("Ending"));
+        assert_eq!(nsf.track_duration(2), Some(Duration::from_millis(30_500)));
+
+        // Unknown lowercase chunks are skipped, unknown uppercase ones refused.
+        assert!(Nsf::new(&nsfe_file(&chunk(b"xtra", &[1, 2]))).is_ok());
+        assert!(Nsf::new(&nsfe_file(&chunk(b"XTRA", &[1, 2]))).is_err());
+    }
+
+    #[test]
+    fn test_bankswitching() {
+        // Bank 0 holds the code; banks 1 and 2 are filled with their number.
+        let mut data = CODE.to_vec();
+        data.resize(0x1000, 0);
+        data.extend(vec![1; 0x1000]);
+        data.extend(vec![2; 0x1000]);
+        let nsf = Nsf::new(&nsf_file(&data, [0, 1, 0, 0, 0, 0, 0, 0], 0)).unwrap();
+        let mut mapper = NsfMapper::new(&nsf);
+        assert_eq!(mapper.read_prg(0x8000), CODE[0]);
+        assert_eq!(mapper.read_prg(0x9000), 1);
+        mapper.write_prg(0x5FF9, 2);
+        assert_eq!(mapper.read_prg(0x9000), 2);
+        mapper.write_prg(0x5FF8, 1);
+        assert_eq!(mapper.read_prg(0x8000), 1);
+
+        mapper.write_prg(0x6123, 0x55);
+        assert_eq!(mapper.read_prg(0x6123), 0x55);
+        assert_eq!(mapper.read_prg(0x4100), 0);
+    }
+
+    #[test]
+    fn test_load_address_without_bankswitching() {
+        let mut raw = nsf_file(&CODE, [0; 8], 0);
+        raw[8..10].copy_from_slice(&0xC000u16.to_le_bytes());
+        let nsf = Nsf::new(&raw).unwrap();
+        let mut mapper = NsfMapper::new(&nsf);
+        assert_eq!(mapper.read_prg(0x8000), 0);
+        assert_eq!(mapper.read_prg(0xC000), CODE[0]);
+        assert_eq!(mapper.read_prg(0xC005), CODE[5]);
+    }
+
+    #[test]
+    fn test_expansion_audio() {
+        let nsf = Nsf::new(&nsf_file(&CODE, [0; 8], EXPANSION_VRC6)).unwrap();
+        let mut mapper = NsfMapper::new(&nsf);
+        assert_eq!(mapper.audio_output(), 0.0);
+        // VRC6 pulse 1 in digitized mode at full volume.
+        mapper.write_prg(0x9000, 0x8F);
+        mapper.write_prg(0x9002, 0x80);
+        mapper.cpu_clock();
+        assert!(mapper.audio_output() > 0.0);
+    }
+
+    #[test]
+    fn test_n163_and_5b_expansions() {
+        let nsf = Nsf::new(&nsf_file(&CODE, [0; 8], EXPANSION_N163 | EXPANSION_SUNSOFT_5B)).unwrap();
+        let mut mapper = NsfMapper::new(&nsf);
+        // N163 sound RAM through the data port, with auto-increment.
+        mapper.write_prg(0xF800, 0x80);
+        mapper.write_prg(0x4800, 0x12);
+        mapper.write_prg(0x4800, 0x34);
+        mapper.write_prg(0xF800, 0x80);
+        assert_eq!(mapper.read_prg(0x4800), 0x12);
+        assert_eq!(mapper.read_prg(0x4800), 0x34);
+        // The 5B register ports do not hide the tune's code.
+        assert_eq!(mapper.read_prg(0xC000), 0);
+
+        // 5B channel A as a constant level at full volume.
+        mapper.write_prg(0xC000, 0x07);
+        mapper.write_prg(0xE000, 0b0011_1111);
+        mapper.write_prg(0xC000, 0x08);
+        mapper.write_prg(0xE000, 0x0F);
+        assert!(mapper.audio_output() > 0.0);
+    }
+
+    #[test]
+    fn test_fds_expansion_runs_from_ram() {
+        let mut data = vec![0; 0x2000];
+        data[0x1000] = 0x42;
+        let nsf = Nsf::new(&nsf_file(&data, [0, 0, 0, 0, 0, 0, 0, 1], EXPANSION_FDS)).unwrap();
+        let mut mapper = NsfMapper::new(&nsf);
+        // $7000 starts with the bank of $F000.
+        assert_eq!(mapper.read_prg(0x7000), 0x42);
+        assert_eq!(mapper.read_prg(0xF000), 0x42);
+
+        mapper.write_prg(0x8000, 0x99);
+        assert_eq!(mapper.read_prg(0x8000), 0x99);
+        mapper.write_prg(0xF000, 0x99);
+        assert_eq!(mapper.read_prg(0xF000), 0x42);
+        // A bank switch copies the bank back in.
+        mapper.write_prg(0x5FF8, 0);
+        assert_eq!(mapper.read_prg(0x8000), 0);
+
+        mapper.write_prg(0x4089, 0x80);
+        mapper.write_prg(0x4040, 0x3F);
+        assert_eq!(mapper.read_prg(0x4040), 0x3F);
+    }
+
+    #[test]
+    fn test_init_and_play() {
+        let nsf = Nsf::new(&nsf_file(&CODE, [0; 8], 0)).unwrap();
+        let mut player = NsfPlayer::new(nsf, Region::Ntsc);
+        player.start_track(2);
+        assert_eq!(player.cpu.mem_read(0x00), 2);
+        assert_eq!(player.cpu.mem_read(0x01), 0);
+
+        // 10 play periods: PLAY runs at the start of each.
+        let period = 16639.0 * Region::Ntsc.cpu_clock_hz() / 1_000_000.0;
+        player.run((period * 10.0) as usize);
+        assert_eq!(player.cpu.mem_read(0x01), 10);
+        assert!((player.elapsed().as_secs_f64() - 0.16639).abs() < 0.001);
+
+        // Starting a track clears RAM and calls INIT again.
+        player.start_track(0);
+        assert_eq!(player.cpu.mem_read(0x00), 0);
+        assert_eq!(player.cpu.mem_read(0x01), 0);
+        assert_eq!(player.track(), 0);
+    }
+
+    #[test]
+    fn test_play_speed_follows_region() {
+        let nsf = Nsf::new(&nsf_file(&CODE, [0; 8], 0)).unwrap();
+        let mut player = NsfPlayer::new(nsf, Region::Pal);
+        player.start_track(0);
+        player.run(Region::Pal.cpu_clock_hz() as usize);
+        // 50 Hz, plus the call at the very start.
+        assert_eq!(player.cpu.mem_read(0x01), 51);
+    }
+
+    #[test]
+    fn test_render() {
+        // INIT: LDA #$BF, STA $4000, LDA #$FD, STA $4002, LDA #$08, STA $4003, RTS.
+        let code = [
+            0xa9, 0xbf, 0x8d, 0x00, 0x40, 0xa9, 0xfd, 0x8d, 0x02, 0x40, 0xa9, 0x08, 0x8d, 0x03, 0x40, 0x60,
+        ];
+        let mut raw = nsf_file(&code, [0; 8], 0);
+        raw[12..14].copy_from_slice(&0x800Fu16.to_le_bytes());
+        let nsf = Nsf::new(&raw).unwrap();
+        let mut player = NsfPlayer::new(nsf, Region::Ntsc);
+
+        let dir = std::env::temp_dir().join(format!("emu_nsf_render_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("track.wav");
+        let mut recorder = WavRecorder::create(&path, 44100, 1).unwrap();
+        player
+            .render(0, Duration::from_millis(200), Duration::from_millis(100), &mut recorder)
+            .unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        let samples: Vec<i16> = data[44..].chunks(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])).collect();
+        assert_eq!(samples.len(), 44100 * 3 / 10);
+        let peak = |range: std::ops::Range<usize>| samples[range].iter().map(|s| s.unsigned_abs()).max().unwrap();
+        assert!(peak(4410..8820) > 1000);
+        // Faded out by the end.
+        assert!(peak(13180..13230) < 100);
+    }
+
+    #[test]
+    fn test_fade_volume() {
+        let length = Duration::from_secs(10);
+        let fade = Duration::from_secs(2);
+        assert_eq!(fade_volume(Duration::from_secs(5), length, fade), 1.0);
+        assert_eq!(fade_volume(Duration::from_secs(11), length, fade), 0.5);
+        assert_eq!(fade_volume(Duration::from_secs(12), length, fade), 0.0);
+        assert_eq!(fade_volume(Duration::from_secs(11), length, Duration::ZERO), 0.0);
+    }
+}
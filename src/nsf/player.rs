@@ -0,0 +1,171 @@
+use crate::bus::Bus;
+use crate::cpu::{CpuFlags, Mem, CPU};
+use crate::mapper::nsf::NsfMapper;
+use crate::nsf::{Nsf, EXPANSION_FDS};
+use crate::region::Region;
+use crate::wav::WavRecorder;
+use std::io;
+use std::time::Duration;
+
+/// INIT and PLAY return here: the NSF hardware reads 0, a BRK, which ends
+/// `CPU::run_with_callback`.
+const RETURN_ADDRESS: u16 = 0x4100;
+/// A PLAY that runs longer than this many periods is cut short.
+const PLAY_TIMEOUT_PERIODS: f64 = 4.0;
+
+/// # NSF player https://www.nesdev.org/wiki/NSF#Initializing_a_tune
+///
+/// Drives an NSF the way a player ROM would: resets RAM and the APU, calls
+/// INIT with the track, then calls PLAY every `play_speed` microseconds and
+/// lets the CPU idle in between.
+pub struct NsfPlayer {
+    pub cpu: CPU,
+    pub nsf: Nsf,
+    region: Region,
+    track: u8,
+    // CPU cycles between PLAY calls, and when the next one is due.
+    play_period: f64,
+    next_play: f64,
+    track_start: usize,
+}
+
+impl NsfPlayer {
+    pub fn new(nsf: Nsf, region: Region) -> Self {
+        let mut cpu = CPU::with_bus(Bus::with_cartridge(Box::new(NsfMapper::new(&nsf))));
+        cpu.bus.set_region(region);
+        let play_period = nsf.play_speed(region) as f64 * region.cpu_clock_hz() / 1_000_000.0;
+        NsfPlayer {
+            cpu,
+            nsf,
+            region,
+            track: 0,
+            play_period,
+            next_play: 0.0,
+            track_start: 0,
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// The current track, 0-based.
+    pub fn track(&self) -> u8 {
+        self.track
+    }
+
+    pub fn start_track(&mut self, track: u8) {
+        let cpu = &mut self.cpu;
+        for addr in (0x0000..0x0800).chain(0x6000..0x8000) {
+            cpu.mem_write(addr, 0);
+        }
+        for addr in 0x4000..=0x4013 {
+            cpu.mem_write(addr, 0);
+        }
+        cpu.mem_write(0x4015, 0x00);
+        cpu.mem_write(0x4015, 0x0F);
+        cpu.mem_write(0x4017, 0x40);
+        if let Some(banks) = self.nsf.banks {
+            for (index, &bank) in banks.iter().enumerate() {
+                cpu.mem_write(0x5FF8 + index as u16, bank);
+            }
+            if self.nsf.expansion & EXPANSION_FDS != 0 {
+                cpu.mem_write(0x5FF6, banks[6]);
+                cpu.mem_write(0x5FF7, banks[7]);
+            }
+        }
+
+        cpu.register_a = track;
+        cpu.register_x = (self.region == Region::Pal) as u8;
+        cpu.register_y = 0;
+        cpu.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        let timeout = self.region.cpu_clock_hz() as usize;
+        self.call(self.nsf.init_address, timeout);
+
+        self.track = track;
+        self.track_start = self.cpu.bus.cycles();
+        self.next_play = self.track_start as f64;
+    }
+
+    /// How long the current track has been playing.
+    pub fn elapsed(&self) -> Duration {
+        let cycles = self.cpu.bus.cycles() - self.track_start;
+        Duration::from_secs_f64(cycles as f64 / self.region.cpu_clock_hz())
+    }
+
+    /// Runs the tune for `cycles` CPU cycles.
+    pub fn run(&mut self, cycles: usize) {
+        let end = self.cpu.bus.cycles() + cycles;
+        loop {
+            let now = self.cpu.bus.cycles();
+            if now >= end {
+                break;
+            }
+            if now as f64 >= self.next_play {
+                // A PLAY that overran its period delays the next one rather
+                // than having them pile up.
+                self.next_play = (self.next_play + self.play_period).max(now as f64);
+                let timeout = (self.play_period * PLAY_TIMEOUT_PERIODS) as usize;
+                self.call(self.nsf.play_address, timeout);
+            } else {
+                let idle = (self.next_play.ceil() as usize).min(end) - now;
+                self.cpu.bus.tick(idle.clamp(1, u8::MAX as usize) as u8);
+            }
+        }
+    }
+
+    /// Runs the routine at `addr` until it returns, or for at most `timeout`
+    /// cycles.
+    fn call(&mut self, addr: u16, timeout: usize) {
+        let cpu = &mut self.cpu;
+        let return_address = RETURN_ADDRESS - 1;
+        cpu.mem_write(0x01FF, (return_address >> 8) as u8);
+        cpu.mem_write(0x01FE, return_address as u8);
+        cpu.stack_pointer = 0xFD;
+        cpu.program_counter = addr;
+        let deadline = cpu.bus.cycles() + timeout;
+        cpu.run_with_callback(move |cpu| {
+            if cpu.bus.cycles() >= deadline {
+                cpu.program_counter = RETURN_ADDRESS;
+            }
+        });
+    }
+
+    /// Plays `track` for `length`, then fades it out over `fade`, into
+    /// `recorder`.
+    pub fn render(&mut self, track: u8, length: Duration, fade: Duration, recorder: &mut WavRecorder) -> io::Result<()> {
+        let sample_rate = recorder.sample_rate();
+        self.cpu.bus.enable_audio(sample_rate);
+        self.start_track(track);
+
+        let total = ((length + fade).as_secs_f64() * sample_rate as f64) as usize;
+        let chunk = (self.region.cpu_clock_hz() / self.region.frame_rate()) as usize;
+        let mut written = 0;
+        let mut samples = Vec::new();
+        while written < total {
+            self.run(chunk);
+            self.cpu.bus.read_audio(&mut samples);
+            samples.truncate(total - written);
+            for (index, sample) in samples.iter_mut().enumerate() {
+                let time = Duration::from_secs_f64((written + index) as f64 / sample_rate as f64);
+                *sample *= fade_volume(time, length, fade);
+            }
+            recorder.write(&samples)?;
+            written += samples.len();
+            samples.clear();
+        }
+        Ok(())
+    }
+}
+
+/// Volume at `time` into a track that plays for `length`, then fades out
+/// over `fade`.
+pub fn fade_volume(time: Duration, length: Duration, fade: Duration) -> f32 {
+    if time <= length {
+        1.0
+    } else if time >= length + fade {
+        0.0
+    } else {
+        1.0 - (time - length).as_secs_f32() / fade.as_secs_f32()
+    }
+}
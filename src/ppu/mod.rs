@@ -0,0 +1,410 @@
+pub mod registers;
+
+use crate::cartridge::Mirroring;
+use crate::mapper::SharedMapper;
+use crate::render;
+use crate::render::frame::Frame;
+use crate::render::palette::Palette;
+use crate::region::Region;
+use registers::control::ControlRegister;
+use registers::loopy::Loopy;
+use registers::mask::MaskRegister;
+use registers::status::StatusRegister;
+
+//  _______________ $4000  _______________
+// | Mirrors       |       |               |
+// | $3F00-$3F1F   |       |               |
+// |_ _ _ _ _ _ _ _| $3F20 | Palettes      |
+// | Palette RAM   |       |               |
+// |_______________| $3F00 |_______________|
+// | Mirrors       |       |               |
+// | $2000-$2EFF   |       |               |
+// |_ _ _ _ _ _ _ _| $3000 | Name Tables   |
+// | Name Tables   |       | (VRAM)        |
+// |_______________| $2000 |_______________|
+// | Pattern Table |       |               |
+// | 1             |       |               |
+// |_ _ _ _ _ _ _ _| $1000 | Pattern Tables|
+// | Pattern Table |       | (CHR)         |
+// | 0             |       |               |
+// |_______________| $0000 |_______________|
+
+/// # PPU https://www.nesdev.org/wiki/PPU
+///
+/// Pattern tables live on the cartridge; nametables live in the console's
+/// 2 KB of VRAM unless the cartridge decides to answer for them.
+pub struct NesPPU {
+    cartridge: Option<SharedMapper>,
+    pub region: Region,
+    pub palette_table: [u8; 32],
+    pub vram: [u8; 2048],
+    pub oam_addr: u8,
+    pub oam_data: [u8; 256],
+
+    pub ctrl: ControlRegister,
+    pub mask: MaskRegister,
+    pub status: StatusRegister,
+    pub loopy: Loopy,
+    internal_data_buf: u8,
+    // Last value driven onto the CPU-PPU data bus. Write-only registers and
+    // the unused bits of PPUSTATUS read back whatever is left on it.
+    io_latch: u8,
+
+    pub frame: Frame,
+    /// Colors used for the frame; can be swapped at any time.
+    pub palette: Palette,
+    frame_ready: bool,
+    scanline: u16,
+    cycles: u16,
+    odd_frame: bool,
+    sprite_zero_dot: Option<u16>,
+    pub pipeline: render::Pipeline,
+    nmi_interrupt: bool,
+    // Reading PPUSTATUS the dot before vblank starts keeps the flag (and
+    // the NMI) from being set for that frame.
+    suppress_vblank: bool,
+}
+
+impl NesPPU {
+    pub fn new(cartridge: Option<SharedMapper>) -> Self {
+        NesPPU {
+            cartridge,
+            region: Region::Ntsc,
+            palette_table: [0; 32],
+            vram: [0; 2048],
+            oam_addr: 0,
+            oam_data: [0; 64 * 4],
+
+            ctrl: ControlRegister::new(),
+            mask: MaskRegister::new(),
+            status: StatusRegister::new(),
+            loopy: Loopy::new(),
+            internal_data_buf: 0,
+            io_latch: 0,
+
+            frame: Frame::new(),
+            palette: Palette::default(),
+            frame_ready: false,
+            scanline: 0,
+            cycles: 0,
+            odd_frame: false,
+            sprite_zero_dot: None,
+            pipeline: render::Pipeline::default(),
+            nmi_interrupt: false,
+            suppress_vblank: false,
+        }
+    }
+
+    /// Advances the PPU by `cycles` dots. Memory reads happen on the dots
+    /// the hardware makes them; each group of 8 pixels is drawn as soon as
+    /// the tiles under it have been fetched.
+    pub fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.cycles += 1;
+            // Odd frames skip the last dot of the pre-render line while
+            // rendering.
+            let mut skipped_dot = false;
+            let pre_render_line = self.region.pre_render_line();
+            if self.cycles == 340
+                && self.scanline == pre_render_line
+                && self.odd_frame
+                && self.region.skips_odd_frame_dot()
+                && self.mask.rendering_enabled()
+            {
+                self.cycles = 341;
+                skipped_dot = true;
+            }
+            if self.cycles == 341 {
+                self.cycles = 0;
+                self.scanline = (self.scanline + 1) % self.region.scanlines();
+                if self.scanline == 0 {
+                    self.odd_frame = !self.odd_frame;
+                    // Each dot is 8 of the 12 subcarrier phases long, so a
+                    // whole frame moves the phase by 4, or by 8 with the
+                    // skipped dot.
+                    self.frame.phase = (self.frame.phase + if skipped_dot { 8 } else { 4 }) % 12;
+                }
+                self.sprite_zero_dot = None;
+                self.pipeline.start_line();
+            }
+
+            let scanline = self.scanline as usize;
+            let visible = scanline < Frame::HEIGHT;
+            if self.is_rendering() {
+                let next_scanline = if scanline == pre_render_line as usize { 0 } else { scanline + 1 };
+                let dot = self.cycles as usize;
+                // Every fetch takes two dots; the reads land on odd dots.
+                match dot {
+                    1..=256 if dot % 2 == 1 => render::fetch_tile(self, dot / 8 + 2, dot % 8 / 2),
+                    257..=320 if dot % 2 == 1 => {
+                        if dot == 257 {
+                            self.loopy.copy_horizontal();
+                            render::select_sprites(self, next_scanline);
+                        }
+                        render::fetch_sprite(self, (dot - 257) / 8, next_scanline, (dot - 257) % 8 / 2);
+                    }
+                    321..=336 if dot % 2 == 1 => render::fetch_tile(self, (dot - 321) / 8, (dot - 321) % 8 / 2),
+                    337 | 339 => {
+                        self.read_nametable(0x2000 | (self.loopy.v & 0x0fff));
+                    }
+                    _ => {}
+                }
+                match self.cycles {
+                    256 => self.loopy.increment_y(),
+                    280..=304 if scanline == pre_render_line as usize => self.loopy.copy_vertical(),
+                    _ => {}
+                }
+            }
+            if visible && (1..=256).contains(&self.cycles) && self.cycles % 8 == 1 {
+                let hit = render::draw_pixels(self, scanline, self.cycles as usize / 8);
+                // Pixel x leaves the PPU on dot x + 1.
+                if self.sprite_zero_dot.is_none() {
+                    self.sprite_zero_dot = hit.map(|x| x as u16 + 1);
+                }
+            }
+
+            if self.sprite_zero_dot == Some(self.cycles) {
+                self.status.set_sprite_zero_hit(true);
+            }
+            if self.cycles == 1 {
+                match self.scanline {
+                    line if line == self.region.vblank_line() => {
+                        if !std::mem::take(&mut self.suppress_vblank) {
+                            self.status.set_vblank_status(true);
+                            if self.ctrl.generate_vblank_nmi() {
+                                self.nmi_interrupt = true;
+                            }
+                        }
+                        self.frame_ready = true;
+                    }
+                    line if line == pre_render_line => {
+                        self.status.reset_vblank_status();
+                        self.status.set_sprite_zero_hit(false);
+                        self.status.set_sprite_overflow(false);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub fn poll_nmi_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_interrupt)
+    }
+
+    /// Returns true once per completed frame.
+    pub fn poll_frame(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
+    pub fn new_empty_rom() -> Self {
+        NesPPU::new(None)
+    }
+
+    /// Value seen when the CPU reads a write-only register.
+    pub fn open_bus(&self) -> u8 {
+        self.io_latch
+    }
+
+    pub fn write_to_ctl(&mut self, value: u8) {
+        self.io_latch = value;
+        let before_nmi_status = self.ctrl.generate_vblank_nmi();
+        self.ctrl.update(value);
+        self.loopy.write_ctrl(value);
+        // Enabling NMI during vblank fires it right away.
+        if !before_nmi_status && self.ctrl.generate_vblank_nmi() && self.status.is_in_vblank() {
+            self.nmi_interrupt = true;
+        }
+    }
+
+    pub fn write_to_mask(&mut self, value: u8) {
+        self.io_latch = value;
+        self.mask.update(value);
+    }
+
+    pub fn read_status(&mut self) -> u8 {
+        let data = self.status.snapshot() | (self.io_latch & 0b1_1111);
+        if self.scanline == self.region.vblank_line() {
+            match self.cycles {
+                0 => self.suppress_vblank = true,
+                1 | 2 => self.nmi_interrupt = false,
+                _ => {}
+            }
+        }
+        self.status.reset_vblank_status();
+        self.loopy.reset_latch();
+        self.io_latch = data;
+        data
+    }
+
+    pub fn write_to_oam_addr(&mut self, value: u8) {
+        self.io_latch = value;
+        self.oam_addr = value;
+    }
+
+    pub fn write_to_oam_data(&mut self, value: u8) {
+        self.io_latch = value;
+        // Bits 2-4 of the attribute byte don't exist and read back as 0.
+        self.oam_data[self.oam_addr as usize] = if self.oam_addr & 0b11 == 2 { value & 0b1110_0011 } else { value };
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    pub fn read_oam_data(&mut self) -> u8 {
+        self.io_latch = self.oam_data[self.oam_addr as usize];
+        self.io_latch
+    }
+
+    pub fn write_to_scroll(&mut self, value: u8) {
+        self.io_latch = value;
+        self.loopy.write_scroll(value);
+    }
+
+    pub fn write_to_ppu_addr(&mut self, value: u8) {
+        self.io_latch = value;
+        self.loopy.write_addr(value);
+    }
+
+    fn increment_vram_addr(&mut self) {
+        if self.is_rendering() {
+            // While rendering, $2007 accesses bump v through the same
+            // increments the fetches use.
+            self.loopy.increment_x();
+            self.loopy.increment_y();
+        } else {
+            self.loopy.increment(self.ctrl.vram_addr_increment());
+        }
+    }
+
+    /// PPUMASK's emphasis bits in palette order: red, green, blue.
+    pub fn emphasis(&self) -> u8 {
+        let emphasis = self.mask.emphasis();
+        if self.region.swaps_red_green_emphasis() {
+            (emphasis & 0b100) | ((emphasis & 0b001) << 1) | ((emphasis & 0b010) >> 1)
+        } else {
+            emphasis
+        }
+    }
+
+    fn is_rendering(&self) -> bool {
+        self.mask.rendering_enabled() && (self.scanline < 240 || self.scanline == self.region.pre_render_line())
+    }
+
+    pub fn write_to_data(&mut self, value: u8) {
+        self.io_latch = value;
+        let addr = self.loopy.vram_addr();
+        match addr {
+            0..=0x1fff => self.write_chr(addr, value),
+            0x2000..=0x3eff => self.write_nametable(addr, value),
+            0x3f00..=0x3fff => self.palette_table[palette_index(addr)] = value & 0b11_1111,
+            _ => unreachable!("unexpected access to mirrored space {:x}", addr),
+        }
+        self.increment_vram_addr();
+    }
+
+    pub fn read_data(&mut self) -> u8 {
+        let addr = self.loopy.vram_addr();
+        self.increment_vram_addr();
+
+        self.io_latch = match addr {
+            0..=0x1fff => {
+                let result = self.internal_data_buf;
+                self.internal_data_buf = self.read_chr(addr);
+                result
+            }
+            0x2000..=0x3eff => {
+                let result = self.internal_data_buf;
+                self.internal_data_buf = self.read_nametable(addr);
+                result
+            }
+            // Palette reads skip the buffer, but the buffer still picks up
+            // the nametable byte "underneath" the palette.
+            0x3f00..=0x3fff => {
+                self.internal_data_buf = self.read_nametable(addr - 0x1000);
+                (self.palette_table[palette_index(addr)] & self.mask.color_mask()) | (self.io_latch & 0b1100_0000)
+            }
+            _ => unreachable!("unexpected access to mirrored space {:x}", addr),
+        };
+        self.io_latch
+    }
+
+    pub fn read_chr(&mut self, addr: u16) -> u8 {
+        match &self.cartridge {
+            Some(cartridge) => cartridge.borrow_mut().read_chr(addr),
+            None => 0,
+        }
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        if let Some(cartridge) = &self.cartridge {
+            cartridge.borrow_mut().write_chr(addr, value);
+        }
+    }
+
+    pub fn read_nametable(&mut self, addr: u16) -> u8 {
+        let addr = addr & 0b10_1111_1111_1111; // mirror down 0x3000-0x3eff to 0x2000-0x2eff
+        if let Some(cartridge) = &self.cartridge {
+            if let Some(value) = cartridge.borrow_mut().read_nametable(addr, &self.vram) {
+                return value;
+            }
+        }
+        self.vram[self.mirror_vram_addr(addr) as usize]
+    }
+
+    fn write_nametable(&mut self, addr: u16, value: u8) {
+        let addr = addr & 0b10_1111_1111_1111;
+        if let Some(cartridge) = &self.cartridge {
+            if cartridge.borrow_mut().write_nametable(addr, value, &mut self.vram) {
+                return;
+            }
+        }
+        self.vram[self.mirror_vram_addr(addr) as usize] = value;
+    }
+
+    // Horizontal:
+    //   [ A ] [ a ]
+    //   [ B ] [ b ]
+
+    // Vertical:
+    //   [ A ] [ B ]
+    //   [ a ] [ b ]
+    /// Boards can switch the layout at any time, so it is looked up on every
+    /// access.
+    pub fn mirroring(&self) -> Mirroring {
+        match &self.cartridge {
+            Some(cartridge) => cartridge.borrow().mirroring(),
+            None => Mirroring::Horizontal,
+        }
+    }
+
+    pub fn mirror_vram_addr(&self, addr: u16) -> u16 {
+        let mirrored_vram = addr & 0b10_1111_1111_1111; // mirror down 0x3000-0x3eff to 0x2000-0x2eff
+        let vram_index = mirrored_vram - 0x2000; // to vram vector
+        let name_table = vram_index / 0x400; // to the name table index
+        match (self.mirroring(), name_table) {
+            (Mirroring::Vertical, 2) | (Mirroring::Vertical, 3) => vram_index - 0x800,
+            (Mirroring::Horizontal, 2) => vram_index - 0x400,
+            (Mirroring::Horizontal, 1) => vram_index - 0x400,
+            (Mirroring::Horizontal, 3) => vram_index - 0x800,
+            (Mirroring::SingleScreenLower, _) => vram_index & 0x3ff,
+            (Mirroring::SingleScreenUpper, _) => 0x400 | (vram_index & 0x3ff),
+            // Four-screen boards answer $2800-$2FFF from their own VRAM, so
+            // only the first two nametables ever get here.
+            (Mirroring::FourScreen, 2) | (Mirroring::FourScreen, 3) => vram_index - 0x800,
+            _ => vram_index,
+        }
+    }
+}
+
+/// $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C.
+fn palette_index(addr: u16) -> usize {
+    let index = (addr & 0x1f) as usize;
+    if index >= 0x10 && index & 0b11 == 0 {
+        index - 0x10
+    } else {
+        index
+    }
+}
+
+#[cfg(test)]
+#[path = "ppu_tests.rs"]
+mod ppu_tests;
@@ -0,0 +1,591 @@
+#[cfg(test)]
+mod test {
+    use crate::cartridge::Rom;
+    use crate::region::Region;
+    use crate::mapper;
+    use crate::mapper::SharedMapper;
+    use crate::ppu::registers::status::StatusRegister;
+    use crate::bus::Bus;
+    use crate::cpu::Mem;
+    use crate::ppu::*;
+    use crate::render::palette::SYSTEM_PALLETE;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn ppu_with_chr_ram() -> NesPPU {
+        let rom = Rom::test_rom(0, vec![0; 0x8000], vec![]);
+        NesPPU::new(Some(Rc::new(RefCell::new(mapper::from_rom(rom).unwrap()))))
+    }
+
+    #[test]
+    fn test_ppu_vram_writes() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ppu_addr(0x23);
+        ppu.write_to_ppu_addr(0x05);
+        ppu.write_to_data(0x66);
+
+        assert_eq!(ppu.vram[0x0305], 0x66);
+    }
+
+    #[test]
+    fn test_ppu_vram_reads() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ctl(0);
+        ppu.vram[0x0305] = 0x66;
+
+        ppu.write_to_ppu_addr(0x23);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(); //load_into_buffer
+        assert_eq!(ppu.loopy.vram_addr(), 0x2306);
+        assert_eq!(ppu.read_data(), 0x66);
+    }
+
+    #[test]
+    fn test_ppu_vram_reads_cross_page() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ctl(0);
+        ppu.vram[0x01ff] = 0x66;
+        ppu.vram[0x0200] = 0x77;
+
+        ppu.write_to_ppu_addr(0x21);
+        ppu.write_to_ppu_addr(0xff);
+
+        ppu.read_data(); //load_into_buffer
+        assert_eq!(ppu.read_data(), 0x66);
+        assert_eq!(ppu.read_data(), 0x77);
+    }
+
+    #[test]
+    fn test_ppu_vram_reads_step_32() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ctl(0b100);
+        ppu.vram[0x01ff] = 0x66;
+        ppu.vram[0x01ff + 32] = 0x77;
+        ppu.vram[0x01ff + 64] = 0x88;
+
+        ppu.write_to_ppu_addr(0x21);
+        ppu.write_to_ppu_addr(0xff);
+
+        ppu.read_data(); //load_into_buffer
+        assert_eq!(ppu.read_data(), 0x66);
+        assert_eq!(ppu.read_data(), 0x77);
+        assert_eq!(ppu.read_data(), 0x88);
+    }
+
+    // Horizontal: https://wiki.nesdev.com/w/index.php/Mirroring
+    //   [0x2000 A ] [0x2400 a ]
+    //   [0x2800 B ] [0x2C00 b ]
+    #[test]
+    fn test_vram_horizontal_mirror() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ppu_addr(0x24);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.write_to_data(0x66); //write to a
+
+        ppu.write_to_ppu_addr(0x28);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.write_to_data(0x77); //write to B
+
+        ppu.write_to_ppu_addr(0x20);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(); //load into buffer
+        assert_eq!(ppu.read_data(), 0x66); //read from A
+
+        ppu.write_to_ppu_addr(0x2C);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(); //load into buffer
+        assert_eq!(ppu.read_data(), 0x77); //read from b
+    }
+
+    // Vertical: https://wiki.nesdev.com/w/index.php/Mirroring
+    //   [0x2000 A ] [0x2400 B ]
+    //   [0x2800 a ] [0x2C00 b ]
+    #[test]
+    fn test_vram_vertical_mirror() {
+        let mut ppu = ppu_with_chr_ram();
+
+        ppu.write_to_ppu_addr(0x20);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.write_to_data(0x66); //write to A
+
+        ppu.write_to_ppu_addr(0x2C);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.write_to_data(0x77); //write to b
+
+        ppu.write_to_ppu_addr(0x28);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(); //load into buffer
+        assert_eq!(ppu.read_data(), 0x66); //read from a
+
+        ppu.write_to_ppu_addr(0x24);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(); //load into buffer
+        assert_eq!(ppu.read_data(), 0x77); //read from B
+    }
+
+    #[test]
+    fn test_mirroring_follows_the_cartridge() {
+        let rom = Rom {
+            submapper: 1,
+            ..Rom::test_rom(7, vec![0; 0x8000], vec![])
+        };
+        let cartridge: SharedMapper = Rc::new(RefCell::new(mapper::from_rom(rom).unwrap()));
+        let mut ppu = NesPPU::new(Some(cartridge.clone()));
+
+        ppu.write_to_ppu_addr(0x2c);
+        ppu.write_to_ppu_addr(0x05);
+        ppu.write_to_data(0x66);
+        assert_eq!(ppu.vram[0x0005], 0x66);
+
+        // AxROM picks the upper page with bit 4 of its bank register.
+        cartridge.borrow_mut().write_prg(0x8000, 0b0001_0000);
+        ppu.write_to_ppu_addr(0x20);
+        ppu.write_to_ppu_addr(0x05);
+        ppu.write_to_data(0x77);
+        assert_eq!(ppu.vram[0x0405], 0x77);
+    }
+
+    #[test]
+    fn test_read_status_resets_latch() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.vram[0x0305] = 0x66;
+
+        ppu.write_to_ppu_addr(0x21);
+        ppu.write_to_ppu_addr(0x23);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(); //load_into_buffer
+        assert_ne!(ppu.read_data(), 0x66);
+
+        ppu.read_status();
+
+        ppu.write_to_ppu_addr(0x23);
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(); //load_into_buffer
+        assert_eq!(ppu.read_data(), 0x66);
+    }
+
+    #[test]
+    fn test_ppu_vram_mirroring() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ctl(0);
+        ppu.vram[0x0305] = 0x66;
+
+        ppu.write_to_ppu_addr(0x63); //0x6305 -> 0x2305
+        ppu.write_to_ppu_addr(0x05);
+
+        ppu.read_data(); //load into_buffer
+        assert_eq!(ppu.read_data(), 0x66);
+    }
+
+    #[test]
+    fn test_read_status_resets_vblank() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.status.set_vblank_status(true);
+
+        let status = ppu.read_status();
+
+        assert_eq!(status >> 7, 1);
+        assert_eq!(ppu.status.snapshot() >> 7, 0);
+    }
+
+    #[test]
+    fn test_read_status_low_bits_are_open_bus() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.status.set_sprite_zero_hit(true);
+        ppu.write_to_mask(0b0001_1010);
+
+        assert_eq!(ppu.read_status(), 0b0101_1010);
+        assert_eq!(ppu.open_bus(), 0b0101_1010);
+    }
+
+    #[test]
+    fn test_oam_read_write() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_oam_addr(0x10);
+        ppu.write_to_oam_data(0x66);
+        ppu.write_to_oam_data(0x77);
+
+        ppu.write_to_oam_addr(0x10);
+        assert_eq!(ppu.read_oam_data(), 0x66);
+
+        ppu.write_to_oam_addr(0x11);
+        assert_eq!(ppu.read_oam_data(), 0x77);
+    }
+
+    #[test]
+    fn test_oam_attribute_unused_bits() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_oam_addr(0x02);
+        ppu.write_to_oam_data(0xff);
+
+        ppu.write_to_oam_addr(0x02);
+        assert_eq!(ppu.read_oam_data(), 0b1110_0011);
+    }
+
+    #[test]
+    fn test_palette_reads_are_not_buffered() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.vram[0x0705] = 0x66; // $2F05 under horizontal mirroring
+        ppu.palette_table[0x05] = 0x21;
+
+        ppu.write_to_ppu_addr(0x3f);
+        ppu.write_to_ppu_addr(0x05);
+
+        assert_eq!(ppu.read_data(), 0x21);
+        // The buffer picked up the nametable byte under the palette.
+        ppu.write_to_ppu_addr(0x20);
+        ppu.write_to_ppu_addr(0x00);
+        assert_eq!(ppu.read_data(), 0x66);
+    }
+
+    #[test]
+    fn test_palette_mirrors() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ppu_addr(0x3f);
+        ppu.write_to_ppu_addr(0x10);
+        ppu.write_to_data(0x2c);
+
+        assert_eq!(ppu.palette_table[0x00], 0x2c);
+
+        ppu.write_to_ppu_addr(0x3f);
+        ppu.write_to_ppu_addr(0x20);
+        assert_eq!(ppu.read_data(), 0x2c);
+    }
+
+    #[test]
+    fn test_chr_ram_through_cartridge() {
+        let mut ppu = ppu_with_chr_ram();
+        ppu.write_to_ppu_addr(0x12);
+        ppu.write_to_ppu_addr(0x34);
+        ppu.write_to_data(0x55);
+
+        ppu.write_to_ppu_addr(0x12);
+        ppu.write_to_ppu_addr(0x34);
+        ppu.read_data(); //load into buffer
+        assert_eq!(ppu.read_data(), 0x55);
+    }
+
+    fn tick(ppu: &mut NesPPU, dots: usize) {
+        for _ in 0..dots {
+            ppu.tick(1);
+        }
+    }
+
+    #[test]
+    fn test_vblank_timing() {
+        let mut ppu = NesPPU::new_empty_rom();
+        tick(&mut ppu, 241 * 341);
+        assert!(!ppu.status.is_in_vblank());
+        tick(&mut ppu, 1);
+        assert!(ppu.status.is_in_vblank());
+        assert!(ppu.poll_frame());
+        assert!(!ppu.poll_frame());
+
+        tick(&mut ppu, 20 * 341);
+        assert!(!ppu.status.is_in_vblank());
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_timing() {
+        let mut ppu = ppu_with_chr_ram();
+        ppu.write_to_ppu_addr(0x00);
+        ppu.write_to_ppu_addr(0x10);
+        for _ in 0..16 {
+            ppu.write_to_data(0xff);
+        }
+        ppu.write_to_ppu_addr(0x20);
+        ppu.write_to_ppu_addr(0x00);
+        for _ in 0..0x3c0 {
+            ppu.write_to_data(0x01);
+        }
+        ppu.write_to_ppu_addr(0x00);
+        ppu.write_to_ppu_addr(0x00);
+        ppu.oam_data = [0xff; 256];
+        ppu.oam_data[0..4].copy_from_slice(&[9, 1, 0, 20]);
+        ppu.write_to_mask(0b0001_1110);
+
+        // Sprite 0 starts on line 10; pixel 20 comes out on dot 21.
+        tick(&mut ppu, 10 * 341 + 20);
+        assert!(!ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+        tick(&mut ppu, 1);
+        assert!(ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+
+        // Cleared at dot 1 of the pre-render line.
+        tick(&mut ppu, 251 * 341);
+        assert!(!ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+    }
+
+    // https://www.nesdev.org/wiki/PPU_scrolling#Summary
+    #[test]
+    fn test_loopy_register_writes() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ctl(0b11);
+        assert_eq!(ppu.loopy.t, 0x0c00);
+        ppu.write_to_ctl(0);
+        ppu.read_status();
+
+        ppu.write_to_scroll(0x7d);
+        assert_eq!((ppu.loopy.t, ppu.loopy.x, ppu.loopy.w), (0x000f, 5, true));
+        ppu.write_to_scroll(0x5e);
+        assert_eq!((ppu.loopy.t, ppu.loopy.w), (0x616f, false));
+
+        ppu.write_to_ppu_addr(0x3d);
+        assert_eq!((ppu.loopy.t, ppu.loopy.w), (0x3d6f, true));
+        ppu.write_to_ppu_addr(0xf0);
+        assert_eq!((ppu.loopy.t, ppu.loopy.v, ppu.loopy.w), (0x3df0, 0x3df0, false));
+    }
+
+    #[test]
+    fn test_loopy_shared_write_toggle() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_scroll(0x00);
+        // Second write of the pair, so this one loads v.
+        ppu.write_to_ppu_addr(0x05);
+        assert_eq!(ppu.loopy.v & 0xff, 0x05);
+    }
+
+    #[test]
+    fn test_loopy_increments() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.loopy.v = 0x001f;
+        ppu.loopy.increment_x();
+        assert_eq!(ppu.loopy.v, 0x0400);
+
+        ppu.loopy.v = 0x7000 | (29 << 5);
+        ppu.loopy.increment_y();
+        assert_eq!(ppu.loopy.v, 0x0800);
+
+        // Coarse Y 31 wraps without switching nametables.
+        ppu.loopy.v = 0x7000 | (31 << 5);
+        ppu.loopy.increment_y();
+        assert_eq!(ppu.loopy.v, 0x0000);
+    }
+
+    #[test]
+    fn test_data_access_while_rendering() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ppu_addr(0x20);
+        ppu.write_to_ppu_addr(0x00);
+        ppu.write_to_mask(0b0000_1000);
+
+        ppu.read_data();
+        assert_eq!(ppu.loopy.v, 0x3001);
+    }
+
+    #[test]
+    fn test_mid_frame_scroll_split() {
+        let mut ppu = ppu_with_chr_ram();
+        // Tile 0 has color 1 in its leftmost column only.
+        ppu.write_to_ppu_addr(0x00);
+        ppu.write_to_ppu_addr(0x00);
+        for _ in 0..8 {
+            ppu.write_to_data(0x80);
+        }
+        ppu.write_to_ppu_addr(0x3f);
+        ppu.write_to_ppu_addr(0x00);
+        ppu.write_to_data(0x0f);
+        ppu.write_to_data(0x30);
+        ppu.write_to_ppu_addr(0x00);
+        ppu.write_to_ppu_addr(0x00);
+        ppu.write_to_mask(0b0000_1010);
+
+        tick(&mut ppu, 50 * 341 + 100);
+        ppu.read_status();
+        ppu.write_to_scroll(4);
+        ppu.write_to_scroll(0);
+        tick(&mut ppu, 10 * 341);
+
+        let white = SYSTEM_PALLETE[0x30];
+        assert_eq!(ppu.frame.pixel(8, 50), white);
+        assert_ne!(ppu.frame.pixel(4, 50), white);
+        // The new X scroll is copied into v at dot 257 of line 50.
+        assert_eq!(ppu.frame.pixel(4, 51), white);
+        assert_ne!(ppu.frame.pixel(8, 51), white);
+    }
+
+    #[test]
+    fn test_vblank_nmi() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ctl(0b1000_0000);
+        tick(&mut ppu, 241 * 341 + 1);
+        assert!(ppu.poll_nmi_interrupt());
+        assert!(!ppu.poll_nmi_interrupt());
+    }
+
+    #[test]
+    fn test_enabling_nmi_during_vblank() {
+        let mut ppu = NesPPU::new_empty_rom();
+        tick(&mut ppu, 241 * 341 + 10);
+        assert!(!ppu.poll_nmi_interrupt());
+
+        ppu.write_to_ctl(0b1000_0000);
+        assert!(ppu.poll_nmi_interrupt());
+        // Only the 0 -> 1 edge counts.
+        ppu.write_to_ctl(0b1000_0000);
+        assert!(!ppu.poll_nmi_interrupt());
+    }
+
+    #[test]
+    fn test_status_read_races_vblank() {
+        // One dot early: the flag never gets set and no NMI fires.
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ctl(0b1000_0000);
+        tick(&mut ppu, 241 * 341);
+        assert_eq!(ppu.read_status() >> 7, 0);
+        tick(&mut ppu, 5);
+        assert!(!ppu.status.is_in_vblank());
+        assert!(!ppu.poll_nmi_interrupt());
+
+        // On the dot it's set: the read sees it, but the NMI is cancelled.
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ctl(0b1000_0000);
+        tick(&mut ppu, 241 * 341 + 1);
+        assert_eq!(ppu.read_status() >> 7, 1);
+        assert!(!ppu.poll_nmi_interrupt());
+
+        // Later reads don't affect the NMI.
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_ctl(0b1000_0000);
+        tick(&mut ppu, 241 * 341 + 3);
+        assert_eq!(ppu.read_status() >> 7, 1);
+        assert!(ppu.poll_nmi_interrupt());
+    }
+
+    fn dots_to_next_frame(ppu: &mut NesPPU) -> usize {
+        let mut dots = 0;
+        while !ppu.poll_frame() {
+            ppu.tick(1);
+            dots += 1;
+        }
+        dots
+    }
+
+    #[test]
+    fn test_odd_frame_skips_a_dot() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_mask(0b0000_1000);
+        dots_to_next_frame(&mut ppu);
+        let first = dots_to_next_frame(&mut ppu);
+        let second = dots_to_next_frame(&mut ppu);
+        assert_eq!(first.max(second), 341 * 262);
+        assert_eq!(first.min(second), 341 * 262 - 1);
+
+        // Without rendering every frame is full length.
+        ppu.write_to_mask(0);
+        assert_eq!(dots_to_next_frame(&mut ppu), 341 * 262);
+        assert_eq!(dots_to_next_frame(&mut ppu), 341 * 262);
+    }
+
+    #[test]
+    fn test_pal_and_dendy_frames() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.region = Region::Pal;
+        ppu.write_to_mask(0b0000_1000);
+        tick(&mut ppu, 241 * 341 + 1);
+        assert!(ppu.status.is_in_vblank());
+        assert!(ppu.poll_frame());
+        // 70 lines of vblank, and no skipped dot.
+        tick(&mut ppu, 70 * 341 - 1);
+        assert!(ppu.status.is_in_vblank());
+        tick(&mut ppu, 1);
+        assert!(!ppu.status.is_in_vblank());
+        dots_to_next_frame(&mut ppu);
+        assert_eq!(dots_to_next_frame(&mut ppu), 341 * 312);
+        assert_eq!(dots_to_next_frame(&mut ppu), 341 * 312);
+
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.region = Region::Dendy;
+        tick(&mut ppu, 291 * 341);
+        assert!(!ppu.status.is_in_vblank());
+        tick(&mut ppu, 1);
+        assert!(ppu.status.is_in_vblank());
+        tick(&mut ppu, 20 * 341);
+        assert!(!ppu.status.is_in_vblank());
+    }
+
+    #[test]
+    fn test_pal_emphasis_swaps_red_and_green() {
+        let mut ppu = NesPPU::new_empty_rom();
+        ppu.write_to_mask(0b0010_0000);
+        assert_eq!(ppu.emphasis(), 0b001);
+        ppu.region = Region::Pal;
+        assert_eq!(ppu.emphasis(), 0b010);
+        ppu.write_to_mask(0b1100_0000);
+        assert_eq!(ppu.emphasis(), 0b101);
+    }
+
+    #[test]
+    fn test_pal_cpu_ppu_ratio() {
+        let mut bus = Bus::new();
+        bus.set_region(Region::Pal);
+        // 5 CPU cycles are 16 dots: the first vblank starts after 25681.875
+        // cycles.
+        for _ in 0..25681 {
+            bus.tick(1);
+        }
+        assert!(!bus.ppu.status.is_in_vblank());
+        bus.tick(1);
+        assert!(bus.ppu.status.is_in_vblank());
+    }
+
+    #[test]
+    fn test_fetch_sequence_clocks_mmc5_scanline_irq() {
+        let rom = Rom::test_rom(5, vec![0; 0x8000], vec![0; 0x2000]);
+        let cartridge: SharedMapper = Rc::new(RefCell::new(mapper::from_rom(rom).unwrap()));
+        let mut ppu = NesPPU::new(Some(cartridge.clone()));
+        cartridge.borrow_mut().write_prg(0x5204, 0x80);
+        ppu.write_to_mask(0b0001_1000);
+
+        // The MMC5 drops out of frame when the CPU runs for a few cycles
+        // without PPU reads, so clock it alongside the PPU.
+        let run = |ppu: &mut NesPPU, dots: usize| {
+            for dot in 0..dots {
+                ppu.tick(1);
+                if dot % 3 == 2 {
+                    cartridge.borrow_mut().cpu_clock();
+                }
+            }
+        };
+        // The counter only lines up with the PPU once the pre-render line
+        // has run, so set the compare value after the first frame.
+        run(&mut ppu, 262 * 341);
+        cartridge.borrow_mut().write_prg(0x5203, 100);
+        run(&mut ppu, 100 * 341);
+        assert!(!cartridge.borrow().irq());
+        run(&mut ppu, 3);
+        assert!(cartridge.borrow().irq());
+    }
+
+    #[test]
+    fn test_mmc5_scanline_irq_through_the_bus() {
+        let rom = Rom::test_rom(5, vec![0; 0x8000], vec![0; 0x2000]);
+        let mut bus = Bus::with_cartridge(mapper::from_rom(rom).unwrap());
+        bus.mem_write(0x5204, 0x80);
+        bus.mem_write(0x2001, 0b0001_1000);
+
+        // Instructions tick the bus several cycles at a time.
+        let run = |bus: &mut Bus, cycles: usize| {
+            for _ in 0..cycles / 3 {
+                bus.tick(3);
+            }
+        };
+        run(&mut bus, 29781);
+        bus.mem_write(0x5203, 100);
+        run(&mut bus, 11331);
+        assert!(!bus.cartridge().unwrap().irq());
+        run(&mut bus, 114);
+        assert!(bus.cartridge().unwrap().irq());
+        assert_eq!(bus.mem_read(0x5204) & 0xc0, 0xc0);
+    }
+}
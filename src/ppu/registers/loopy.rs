@@ -0,0 +1,111 @@
+/// # Internal scroll registers https://www.nesdev.org/wiki/PPU_scrolling
+///
+/// `v` is the current VRAM address, `t` the temporary address that the
+/// scroll and address writes assemble, `x` the fine X scroll and `w` the
+/// write toggle shared by $2005 and $2006.
+///
+///  yyy NN YYYYY XXXXX
+///  ||| || ||||| +++++-- coarse X scroll
+///  ||| || +++++-------- coarse Y scroll
+///  ||| ++-------------- nametable select
+///  +++----------------- fine Y scroll
+pub struct Loopy {
+    pub v: u16,
+    pub t: u16,
+    pub x: u8,
+    pub w: bool,
+}
+
+impl Loopy {
+    pub fn new() -> Self {
+        Loopy {
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+        }
+    }
+
+    /// $2000 write: nametable select goes to t.
+    pub fn write_ctrl(&mut self, data: u8) {
+        self.t = (self.t & !0x0c00) | ((data as u16 & 0b11) << 10);
+    }
+
+    /// $2005 write: X scroll first, then Y scroll.
+    pub fn write_scroll(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & !0x001f) | (data as u16 >> 3);
+            self.x = data & 0b111;
+        } else {
+            self.t = (self.t & !0x73e0) | ((data as u16 & 0b111) << 12) | ((data as u16 >> 3) << 5);
+        }
+        self.w = !self.w;
+    }
+
+    /// $2006 write: high byte first, then low byte, which also loads v.
+    pub fn write_addr(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & 0x00ff) | ((data as u16 & 0b11_1111) << 8);
+        } else {
+            self.t = (self.t & 0xff00) | data as u16;
+            self.v = self.t;
+        }
+        self.w = !self.w;
+    }
+
+    pub fn reset_latch(&mut self) {
+        self.w = false;
+    }
+
+    pub fn vram_addr(&self) -> u16 {
+        self.v & 0x3fff
+    }
+
+    /// $2007 access outside of rendering.
+    pub fn increment(&mut self, inc: u8) {
+        self.v = (self.v + inc as u16) & 0x7fff;
+    }
+
+    pub fn increment_x(&mut self) {
+        if self.v & 0x001f == 31 {
+            self.v &= !0x001f;
+            self.v ^= 0x0400; // switch horizontal nametable
+        } else {
+            self.v += 1;
+        }
+    }
+
+    pub fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut y = (self.v & 0x03e0) >> 5;
+        if y == 29 {
+            y = 0;
+            self.v ^= 0x0800; // switch vertical nametable
+        } else if y == 31 {
+            // Rows 30 and 31 are the attribute table; wrapping from there
+            // doesn't switch nametables.
+            y = 0;
+        } else {
+            y += 1;
+        }
+        self.v = (self.v & !0x03e0) | (y << 5);
+    }
+
+    pub fn copy_horizontal(&mut self) {
+        self.v = (self.v & !0x041f) | (self.t & 0x041f);
+    }
+
+    pub fn copy_vertical(&mut self) {
+        self.v = (self.v & !0x7be0) | (self.t & 0x7be0);
+    }
+}
+
+impl Default for Loopy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
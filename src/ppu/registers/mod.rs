@@ -0,0 +1,4 @@
+pub mod control;
+pub mod loopy;
+pub mod mask;
+pub mod status;
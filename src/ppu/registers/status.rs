@@ -0,0 +1,60 @@
+bitflags! {
+    /// # Status Register ($2002) https://www.nesdev.org/wiki/PPU_registers#PPUSTATUS
+    ///
+    ///  7  bit  0
+    ///  ---- ----
+    ///  VSO. ....
+    ///  |||| ||||
+    ///  |||+-++++- Least significant bits previously written into a PPU register
+    ///  |||        (due to register not being updated for this address)
+    ///  ||+------- Sprite overflow
+    ///  |+-------- Sprite 0 Hit
+    ///  +--------- Vertical blank has started (0: not in vblank; 1: in vblank).
+    ///             Cleared after reading $2002 and at dot 1 of the pre-render line.
+    pub struct StatusRegister: u8 {
+        const NOTUSED          = 0b00000001;
+        const NOTUSED2         = 0b00000010;
+        const NOTUSED3         = 0b00000100;
+        const NOTUSED4         = 0b00001000;
+        const NOTUSED5         = 0b00010000;
+        const SPRITE_OVERFLOW  = 0b00100000;
+        const SPRITE_ZERO_HIT  = 0b01000000;
+        const VBLANK_STARTED   = 0b10000000;
+    }
+}
+
+impl StatusRegister {
+    pub fn new() -> Self {
+        StatusRegister::from_bits_truncate(0b00000000)
+    }
+
+    pub fn set_vblank_status(&mut self, status: bool) {
+        self.set(StatusRegister::VBLANK_STARTED, status);
+    }
+
+    pub fn set_sprite_zero_hit(&mut self, status: bool) {
+        self.set(StatusRegister::SPRITE_ZERO_HIT, status);
+    }
+
+    pub fn set_sprite_overflow(&mut self, status: bool) {
+        self.set(StatusRegister::SPRITE_OVERFLOW, status);
+    }
+
+    pub fn reset_vblank_status(&mut self) {
+        self.remove(StatusRegister::VBLANK_STARTED);
+    }
+
+    pub fn is_in_vblank(&self) -> bool {
+        self.contains(StatusRegister::VBLANK_STARTED)
+    }
+
+    pub fn snapshot(&self) -> u8 {
+        self.bits
+    }
+}
+
+impl Default for StatusRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,77 @@
+/// # Console regions https://www.nesdev.org/wiki/Cycle_reference_chart
+///
+/// |                       | NTSC (2C02) | PAL (2C07) | Dendy  |
+/// |-----------------------|-------------|------------|--------|
+/// | CPU clock             | master / 12 | master / 16| / 15   |
+/// | PPU dots per CPU cycle| 3           | 3.2        | 3      |
+/// | Scanlines per frame   | 262         | 312        | 312    |
+/// | Vblank starts on line | 241         | 241        | 291    |
+/// | Odd frames skip a dot | yes         | no         | no     |
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    /// Parses the name used on the command line.
+    pub fn from_name(name: &str) -> Option<Region> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Some(Region::Ntsc),
+            "pal" => Some(Region::Pal),
+            "dendy" => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    pub fn cpu_clock_hz(&self) -> f64 {
+        match self {
+            Region::Ntsc => 1_789_773.0,
+            Region::Pal => 1_662_607.0,
+            Region::Dendy => 1_773_448.0,
+        }
+    }
+
+    /// PPU dots per CPU cycle as a fraction: `(dots, cycles)`.
+    pub fn ppu_dots(&self) -> (u16, u16) {
+        match self {
+            Region::Pal => (16, 5),
+            Region::Ntsc | Region::Dendy => (3, 1),
+        }
+    }
+
+    pub fn scanlines(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    pub fn vblank_line(&self) -> u16 {
+        match self {
+            Region::Dendy => 291,
+            Region::Ntsc | Region::Pal => 241,
+        }
+    }
+
+    pub fn pre_render_line(&self) -> u16 {
+        self.scanlines() - 1
+    }
+
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        *self == Region::Ntsc
+    }
+
+    /// The PAL PPU swaps the red and green emphasis bits.
+    pub fn swaps_red_green_emphasis(&self) -> bool {
+        *self != Region::Ntsc
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        let (dots, cycles) = self.ppu_dots();
+        let dots_per_frame = 341.0 * self.scanlines() as f64 - if self.skips_odd_frame_dot() { 0.5 } else { 0.0 };
+        self.cpu_clock_hz() * dots as f64 / cycles as f64 / dots_per_frame
+    }
+}
@@ -0,0 +1,47 @@
+pub struct Frame {
+    pub data: Vec<u8>,
+    /// What the PPU put out for each pixel before the palette lookup: the
+    /// palette RAM value in bits 0-5, the emphasis bits in bits 6-8.
+    pub indices: Vec<u16>,
+    /// Color subcarrier phase (0-11) at the start of the first scanline.
+    pub phase: u8,
+}
+
+impl Frame {
+    pub const WIDTH: usize = 256;
+    pub const HEIGHT: usize = 240;
+
+    pub fn new() -> Self {
+        Frame {
+            data: vec![0; Frame::WIDTH * Frame::HEIGHT * 3],
+            indices: vec![0; Frame::WIDTH * Frame::HEIGHT],
+            phase: 0,
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
+        let base = y * 3 * Frame::WIDTH + x * 3;
+        if base + 2 < self.data.len() {
+            self.data[base] = rgb.0;
+            self.data[base + 1] = rgb.1;
+            self.data[base + 2] = rgb.2;
+        }
+    }
+
+    pub fn set_index(&mut self, x: usize, y: usize, index: u16) {
+        if let Some(pixel) = self.indices.get_mut(y * Frame::WIDTH + x) {
+            *pixel = index;
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let base = y * 3 * Frame::WIDTH + x * 3;
+        (self.data[base], self.data[base + 1], self.data[base + 2])
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
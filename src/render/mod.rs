@@ -0,0 +1,264 @@
+pub mod frame;
+pub mod ntsc;
+pub mod palette;
+
+use crate::ppu::registers::mask::MaskRegister;
+use crate::ppu::NesPPU;
+
+/// Secondary OAM holds at most this many sprites per scanline.
+const SPRITES_PER_LINE: usize = 8;
+
+fn bg_pallette(attribute_byte: u8, tile_column: usize, tile_row: usize) -> u8 {
+    // Each attribute byte covers a 4x4 tile area, split into 2x2 quadrants.
+    let shift = ((tile_row & 0b10) << 1) | (tile_column & 0b10);
+    (attribute_byte >> shift) & 0b11
+}
+
+/// A sprite picked for a scanline, with its pattern row fetched.
+struct LineSprite {
+    index: usize,
+    x: u8,
+    attributes: u8,
+    upper: u8,
+    lower: u8,
+}
+
+impl LineSprite {
+    fn pixel(&self, x: usize) -> Option<u8> {
+        let column = x.checked_sub(self.x as usize).filter(|column| *column < 8)?;
+        let bit = if self.attributes & 0b0100_0000 != 0 { column } else { 7 - column };
+        let value = (((self.lower >> bit) & 1) << 1) | ((self.upper >> bit) & 1);
+        if value == 0 {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    fn behind_background(&self) -> bool {
+        self.attributes & 0b0010_0000 != 0
+    }
+
+    fn palette(&self) -> u8 {
+        self.attributes & 0b11
+    }
+}
+
+/// Pattern row and palette of one fetched background tile.
+#[derive(Clone, Copy, Default)]
+struct TileRow {
+    upper: u8,
+    lower: u8,
+    palette: u8,
+}
+
+impl TileRow {
+    fn pixel(&self, column: usize) -> u8 {
+        let bit = 7 - column;
+        let value = (((self.lower >> bit) & 1) << 1) | ((self.upper >> bit) & 1);
+        if value == 0 {
+            0
+        } else {
+            self.palette * 4 + value
+        }
+    }
+}
+
+/// What the PPU has fetched so far for the current and the next scanline.
+pub struct Pipeline {
+    // Tiles 0 and 1 are prefetched at the end of the previous scanline.
+    tiles: [TileRow; 34],
+    // The tile or sprite row whose reads are in progress.
+    latch: TileRow,
+    tile_idx: u8,
+    sprites: Vec<LineSprite>,
+    next_sprites: Vec<LineSprite>,
+    selected: Vec<usize>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Pipeline {
+            tiles: [TileRow::default(); 34],
+            latch: TileRow::default(),
+            tile_idx: 0,
+            sprites: Vec::new(),
+            next_sprites: Vec::new(),
+            selected: Vec::new(),
+        }
+    }
+}
+
+impl Pipeline {
+    /// Makes the sprites fetched during the previous scanline current.
+    pub fn start_line(&mut self) {
+        self.sprites = std::mem::take(&mut self.next_sprites);
+    }
+}
+
+/// Makes read `step` of the 8-dot fetch for the tile at the current VRAM
+/// address: nametable, attribute, then the two pattern planes. The last one
+/// stores the tile in `slot` and steps to the next tile.
+pub fn fetch_tile(ppu: &mut NesPPU, slot: usize, step: usize) {
+    let v = ppu.loopy.v;
+    let tile_addr = ppu.ctrl.bknd_pattern_addr() + ppu.pipeline.tile_idx as u16 * 16 + (v >> 12);
+    match step {
+        0 => ppu.pipeline.tile_idx = ppu.read_nametable(0x2000 | (v & 0x0fff)),
+        1 => {
+            let coarse_x = (v & 0x001f) as usize;
+            let coarse_y = ((v >> 5) & 0x001f) as usize;
+            let attribute_byte = ppu.read_nametable(0x23c0 | (v & 0x0c00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07));
+            ppu.pipeline.latch.palette = bg_pallette(attribute_byte, coarse_x, coarse_y);
+        }
+        2 => ppu.pipeline.latch.upper = ppu.read_chr(tile_addr),
+        _ => {
+            ppu.pipeline.latch.lower = ppu.read_chr(tile_addr + 8);
+            ppu.pipeline.tiles[slot] = ppu.pipeline.latch;
+            ppu.loopy.increment_x();
+        }
+    }
+}
+
+/// # Sprite evaluation https://www.nesdev.org/wiki/PPU_sprite_evaluation
+///
+/// Returns the OAM indices of the first eight sprites on `scanline` and the
+/// sprite overflow flag. Once secondary OAM is full the hardware keeps
+/// scanning, but increments the byte offset along with the sprite index, so
+/// it compares tile numbers, attributes and X positions as if they were Y
+/// coordinates.
+pub fn evaluate_sprites(ppu: &NesPPU, scanline: usize) -> (Vec<usize>, bool) {
+    let height = ppu.ctrl.sprite_size() as usize;
+    // OAM holds the sprite's top line minus one.
+    let on_line = |y: u8| (scanline.wrapping_sub(y as usize + 1)) < height;
+
+    let mut found = Vec::with_capacity(SPRITES_PER_LINE);
+    let mut n = 0;
+    while n < 64 && found.len() < SPRITES_PER_LINE {
+        if on_line(ppu.oam_data[n * 4]) {
+            found.push(n);
+        }
+        n += 1;
+    }
+
+    let mut m = 0;
+    while n < 64 {
+        if on_line(ppu.oam_data[n * 4 + m]) {
+            return (found, true);
+        }
+        n += 1;
+        m = (m + 1) & 0b11;
+    }
+    (found, false)
+}
+
+/// Picks the sprites for `scanline` at the end of the line before it and
+/// sets the sprite overflow flag.
+pub fn select_sprites(ppu: &mut NesPPU, scanline: usize) {
+    let (selected, overflow) = evaluate_sprites(ppu, scanline);
+    if overflow {
+        ppu.status.set_sprite_overflow(true);
+    }
+    ppu.pipeline.selected = selected;
+}
+
+/// Makes read `step` of the 8-dot fetch for one secondary OAM slot on
+/// `scanline`: two garbage nametable reads, then the two pattern planes.
+/// Empty slots still fetch tile $FF like the hardware does, which boards
+/// watching PPU A12 rely on.
+pub fn fetch_sprite(ppu: &mut NesPPU, slot: usize, scanline: usize, step: usize) {
+    let height = ppu.ctrl.sprite_size() as usize;
+    let (index, y, tile, attributes, x) = match ppu.pipeline.selected.get(slot) {
+        Some(&index) => {
+            let oam = &ppu.oam_data[index * 4..index * 4 + 4];
+            (Some(index), oam[0], oam[1], oam[2], oam[3])
+        }
+        None => (None, 0xff, 0xff, 0xff, 0xff),
+    };
+
+    let mut row = scanline.wrapping_sub(y as usize + 1) % height;
+    if attributes & 0b1000_0000 != 0 {
+        row = height - 1 - row;
+    }
+    let tile_addr = if height == 16 {
+        let bank = (tile as u16 & 1) * 0x1000;
+        let tile = (tile & 0xfe) as u16 + (row / 8) as u16;
+        bank + tile * 16 + (row % 8) as u16
+    } else {
+        ppu.ctrl.sprt_pattern_addr() + tile as u16 * 16 + row as u16
+    };
+
+    match step {
+        0 | 1 => {
+            ppu.read_nametable(0x2000 | (ppu.loopy.v & 0x0fff));
+        }
+        2 => ppu.pipeline.latch.upper = ppu.read_chr(tile_addr),
+        _ => {
+            let lower = ppu.read_chr(tile_addr + 8);
+            if let Some(index) = index {
+                let upper = ppu.pipeline.latch.upper;
+                ppu.pipeline.next_sprites.push(LineSprite {
+                    index,
+                    x,
+                    attributes,
+                    upper,
+                    lower,
+                });
+            }
+        }
+    }
+}
+
+/// Draws the 8 pixels of tile column `column` on a visible scanline and
+/// returns the X coordinate of the first sprite 0 hit among them, if any.
+pub fn draw_pixels(ppu: &mut NesPPU, scanline: usize, column: usize) -> Option<usize> {
+    let show_background = ppu.mask.show_background();
+    let show_sprites = ppu.mask.show_sprites();
+    let clip_background = !ppu.mask.contains(MaskRegister::LEFTMOST_8PXL_BACKGROUND);
+    let clip_sprites = !ppu.mask.contains(MaskRegister::LEFTMOST_8PXL_SPRITE);
+    let fine_x = ppu.loopy.x as usize;
+    let color_mask = ppu.mask.color_mask();
+    let emphasis = ppu.emphasis();
+
+    let pipeline = &ppu.pipeline;
+    // Sprite 0 is always evaluated first, so it can only sit in slot 0.
+    let sprite_zero = pipeline.sprites.first().filter(|sprite| sprite.index == 0);
+    let mut sprite_zero_hit = None;
+    for x in column * 8..column * 8 + 8 {
+        let bg = if !show_background || (clip_background && x < 8) {
+            0
+        } else {
+            let offset = x + fine_x;
+            pipeline.tiles[offset / 8].pixel(offset % 8)
+        };
+        let sprites_visible = show_sprites && !(clip_sprites && x < 8);
+        let sprite = if sprites_visible {
+            pipeline.sprites.iter().find_map(|sprite| sprite.pixel(x).map(|value| (sprite, value)))
+        } else {
+            None
+        };
+
+        if sprite_zero_hit.is_none()
+            && sprites_visible
+            && bg != 0
+            && x != 255
+            && sprite_zero.is_some_and(|sprite| sprite.pixel(x).is_some())
+        {
+            sprite_zero_hit = Some(x);
+        }
+
+        let color = match sprite {
+            Some((sprite, value)) if bg == 0 || !sprite.behind_background() => {
+                ppu.palette_table[0x10 + sprite.palette() as usize * 4 + value as usize]
+            }
+            _ => ppu.palette_table[bg as usize],
+        };
+        let color = color & color_mask;
+        ppu.frame.set_pixel(x, scanline, ppu.palette.color(color, emphasis));
+        ppu.frame.set_index(x, scanline, (emphasis as u16) << 6 | color as u16);
+    }
+    sprite_zero_hit
+}
+
+#[cfg(test)]
+#[path = "render_tests.rs"]
+mod render_tests;
@@ -0,0 +1,170 @@
+use crate::render::frame::Frame;
+use std::f32::consts::PI;
+
+/// The PPU puts out 8 samples of its 12-phase color generator per pixel.
+const SAMPLES_PER_PIXEL: usize = 8;
+const PHASES: usize = 12;
+/// Every output pixel covers this many samples.
+const SAMPLES_PER_OUTPUT: usize = 4;
+/// Where the decoder's color reference sits relative to the PPU's phase 0,
+/// picked so the plain colors come out close to the 2C02 palette.
+const HUE_OFFSET: f32 = 135.0;
+
+// Signal voltages relative to sync: low and high level of the square wave
+// for luma levels 0-3, and the attenuation of the emphasis bits.
+const LEVELS_LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const LEVELS_HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const BLACK: f32 = 0.518;
+const WHITE: f32 = 1.962;
+const ATTENUATION: f32 = 0.746;
+
+pub struct NtscSettings {
+    /// 0.0 averages luma over a whole subcarrier cycle, 1.0 over a third of
+    /// one: sharper edges, more fringing.
+    pub sharpness: f32,
+    pub saturation: f32,
+    /// Rotation of all hues, in degrees.
+    pub hue: f32,
+}
+
+impl Default for NtscSettings {
+    fn default() -> Self {
+        NtscSettings {
+            sharpness: 0.5,
+            saturation: 1.0,
+            hue: 0.0,
+        }
+    }
+}
+
+/// # NTSC video https://www.nesdev.org/wiki/NTSC_video
+///
+/// Rebuilds the composite signal the PPU would send to the TV from the
+/// palette indices and emphasis bits of a frame, then decodes it the way a
+/// TV does. Colors bleed into each other at the edges (artifact colors), and
+/// as the subcarrier phase moves from line to line and frame to frame the
+/// fringes crawl (dot crawl).
+pub struct NtscFilter {
+    pub settings: NtscSettings,
+    // Signal level of each of the 512 PPU outputs at each phase.
+    levels: Vec<[f32; PHASES]>,
+    samples: Vec<f32>,
+    output: Vec<u8>,
+}
+
+impl NtscFilter {
+    pub const WIDTH: usize = Frame::WIDTH * SAMPLES_PER_PIXEL / SAMPLES_PER_OUTPUT;
+    pub const HEIGHT: usize = Frame::HEIGHT;
+
+    pub fn new(settings: NtscSettings) -> Self {
+        let levels = (0..512)
+            .map(|pixel| {
+                let mut levels = [0.0; PHASES];
+                for (phase, level) in levels.iter_mut().enumerate() {
+                    *level = signal(pixel, phase);
+                }
+                levels
+            })
+            .collect();
+        NtscFilter {
+            settings,
+            levels,
+            samples: vec![0.0; Frame::WIDTH * SAMPLES_PER_PIXEL],
+            output: vec![0; NtscFilter::WIDTH * NtscFilter::HEIGHT * 3],
+        }
+    }
+
+    /// Runs `frame` through the filter; returns RGB24 data of
+    /// `WIDTH` x `HEIGHT` pixels.
+    pub fn apply(&mut self, frame: &Frame) -> &[u8] {
+        let hue = (HUE_OFFSET + self.settings.hue) * PI / 180.0;
+        // Demodulating a square wave picks up half its amplitude.
+        let gain = 2.0 * self.settings.saturation;
+        let carrier: Vec<(f32, f32)> = (0..PHASES)
+            .map(|phase| {
+                let angle = PI * phase as f32 / 6.0 + hue;
+                (angle.cos() * gain, angle.sin() * gain)
+            })
+            .collect();
+        let luma_window = (12.0 - 8.0 * self.settings.sharpness.clamp(0.0, 1.0)).round() as usize;
+
+        let line_samples = self.samples.len();
+        let mut y_sum = vec![0.0; line_samples + 1];
+        let mut i_sum = vec![0.0; line_samples + 1];
+        let mut q_sum = vec![0.0; line_samples + 1];
+
+        for line in 0..Frame::HEIGHT {
+            // A scanline is 341 dots of 8 phases, and pixel 0 leaves the PPU
+            // on dot 1.
+            let line_phase = (frame.phase as usize + line * 4 + SAMPLES_PER_PIXEL) % PHASES;
+            let pixels = &frame.indices[line * Frame::WIDTH..(line + 1) * Frame::WIDTH];
+            for (x, &pixel) in pixels.iter().enumerate() {
+                let levels = &self.levels[pixel as usize & 0x1ff];
+                for k in 0..SAMPLES_PER_PIXEL {
+                    let sample = x * SAMPLES_PER_PIXEL + k;
+                    self.samples[sample] = levels[(line_phase + sample) % PHASES];
+                }
+            }
+
+            // Running sums make every window two lookups.
+            for (sample, &level) in self.samples.iter().enumerate() {
+                let (cos, sin) = carrier[(line_phase + sample) % PHASES];
+                y_sum[sample + 1] = y_sum[sample] + level;
+                i_sum[sample + 1] = i_sum[sample] + level * cos;
+                q_sum[sample + 1] = q_sum[sample] + level * sin;
+            }
+            let window = |sums: &[f32], center: usize, width: usize| {
+                let start = center.saturating_sub(width / 2);
+                let end = (center + width - width / 2).min(line_samples);
+                (sums[end] - sums[start]) / (end - start) as f32
+            };
+
+            for x in 0..NtscFilter::WIDTH {
+                let center = x * SAMPLES_PER_OUTPUT + SAMPLES_PER_OUTPUT / 2;
+                let y = window(&y_sum, center, luma_window);
+                let i = window(&i_sum, center, PHASES);
+                let q = window(&q_sum, center, PHASES);
+
+                let base = (line * NtscFilter::WIDTH + x) * 3;
+                self.output[base] = to_byte(y + 0.946882 * i + 0.623557 * q);
+                self.output[base + 1] = to_byte(y - 0.274788 * i - 0.635691 * q);
+                self.output[base + 2] = to_byte(y - 1.108545 * i + 1.709007 * q);
+            }
+        }
+        &self.output
+    }
+}
+
+/// Normalized signal level of PPU output `pixel` (palette index plus
+/// emphasis bits) at color generator phase `phase`.
+fn signal(pixel: usize, phase: usize) -> f32 {
+    let color = pixel & 0x0f;
+    // Columns $xE and $xF are black, at level 1.
+    let level = if color > 13 { 1 } else { (pixel >> 4) & 0b11 };
+    let emphasis = pixel >> 6;
+
+    let in_color_phase = |color: usize| (color + phase) % PHASES < 6;
+    let mut low = LEVELS_LOW[level];
+    let mut high = LEVELS_HIGH[level];
+    if color == 0 {
+        low = high;
+    }
+    if color > 12 {
+        high = low;
+    }
+    let mut signal = if in_color_phase(color) { high } else { low };
+
+    if (emphasis & 0b001 != 0 && in_color_phase(0))
+        || (emphasis & 0b010 != 0 && in_color_phase(4))
+        || (emphasis & 0b100 != 0 && in_color_phase(8))
+    {
+        signal *= ATTENUATION;
+    }
+    (signal - BLACK) / (WHITE - BLACK)
+}
+
+fn to_byte(value: f32) -> u8 {
+    // The TV's gamma is a little higher than the one sRGB expects.
+    let corrected = if value <= 0.0 { 0.0 } else { value.powf(2.2 / 1.8) };
+    (corrected * 255.95).clamp(0.0, 255.0) as u8
+}
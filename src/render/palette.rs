@@ -0,0 +1,73 @@
+/// # 2C02 system palette https://www.nesdev.org/wiki/PPU_palettes
+///
+/// Palette RAM holds indices into this table.
+#[rustfmt::skip]
+pub static SYSTEM_PALLETE: [(u8, u8, u8); 64] = [
+   (0x80, 0x80, 0x80), (0x00, 0x3D, 0xA6), (0x00, 0x12, 0xB0), (0x44, 0x00, 0x96), (0xA1, 0x00, 0x5E),
+   (0xC7, 0x00, 0x28), (0xBA, 0x06, 0x00), (0x8C, 0x17, 0x00), (0x5C, 0x2F, 0x00), (0x10, 0x45, 0x00),
+   (0x05, 0x4A, 0x00), (0x00, 0x47, 0x2E), (0x00, 0x41, 0x66), (0x00, 0x00, 0x00), (0x05, 0x05, 0x05),
+   (0x05, 0x05, 0x05), (0xC7, 0xC7, 0xC7), (0x00, 0x77, 0xFF), (0x21, 0x55, 0xFF), (0x82, 0x37, 0xFA),
+   (0xEB, 0x2F, 0xB5), (0xFF, 0x29, 0x50), (0xFF, 0x22, 0x00), (0xD6, 0x32, 0x00), (0xC4, 0x62, 0x00),
+   (0x35, 0x80, 0x00), (0x05, 0x8F, 0x00), (0x00, 0x8A, 0x55), (0x00, 0x99, 0xCC), (0x21, 0x21, 0x21),
+   (0x09, 0x09, 0x09), (0x09, 0x09, 0x09), (0xFF, 0xFF, 0xFF), (0x0F, 0xD7, 0xFF), (0x69, 0xA2, 0xFF),
+   (0xD4, 0x80, 0xFF), (0xFF, 0x45, 0xF3), (0xFF, 0x61, 0x8B), (0xFF, 0x88, 0x33), (0xFF, 0x9C, 0x12),
+   (0xFA, 0xBC, 0x20), (0x9F, 0xE3, 0x0E), (0x2B, 0xF0, 0x35), (0x0C, 0xF0, 0xA4), (0x05, 0xFB, 0xFF),
+   (0x5E, 0x5E, 0x5E), (0x0D, 0x0D, 0x0D), (0x0D, 0x0D, 0x0D), (0xFF, 0xFF, 0xFF), (0xA6, 0xFC, 0xFF),
+   (0xB3, 0xEC, 0xFF), (0xDA, 0xAB, 0xEB), (0xFF, 0xA8, 0xF9), (0xFF, 0xAB, 0xB3), (0xFF, 0xD2, 0xB0),
+   (0xFF, 0xEF, 0xA6), (0xFF, 0xF7, 0x9C), (0xD7, 0xE8, 0x95), (0xA6, 0xED, 0xAF), (0xA2, 0xF2, 0xDA),
+   (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11),
+];
+
+/// How much each emphasis bit darkens the two color channels it doesn't
+/// emphasize, for palettes that don't list the emphasized colors.
+const EMPHASIS_ATTENUATION: f32 = 0.816328;
+
+/// # Palette files https://www.nesdev.org/wiki/.pal
+///
+/// Maps a palette RAM value plus the three PPUMASK emphasis bits to RGB. A
+/// `.pal` file is either 64 RGB triples, or 512 triples with one block of 64
+/// per emphasis combination.
+pub struct Palette {
+    colors: Vec<(u8, u8, u8)>,
+}
+
+impl Palette {
+    /// Builds the emphasized variants of a 64-color palette by dimming the
+    /// channels that aren't emphasized.
+    pub fn from_colors(colors: &[(u8, u8, u8)]) -> Self {
+        let mut expanded = Vec::with_capacity(512);
+        for emphasis in 0..8 {
+            let scale = |channel: u8, bit: u8| {
+                let dimming = (emphasis & !bit).count_ones() as i32;
+                (channel as f32 * EMPHASIS_ATTENUATION.powi(dimming)).round() as u8
+            };
+            expanded.extend(colors.iter().map(|&(r, g, b)| (scale(r, 0b001), scale(g, 0b010), scale(b, 0b100))));
+        }
+        Palette { colors: expanded }
+    }
+
+    pub fn from_pal(data: &[u8]) -> Result<Self, String> {
+        let colors: Vec<(u8, u8, u8)> = data.chunks_exact(3).map(|rgb| (rgb[0], rgb[1], rgb[2])).collect();
+        match data.len() {
+            192 => Ok(Palette::from_colors(&colors)),
+            1536 => Ok(Palette { colors }),
+            len => Err(format!("Palette file has {} bytes, expected 192 or 1536", len)),
+        }
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        Palette::from_pal(&data)
+    }
+
+    /// `emphasis` holds the PPUMASK emphasis bits shifted down to bits 0-2.
+    pub fn color(&self, index: u8, emphasis: u8) -> (u8, u8, u8) {
+        self.colors[((emphasis & 0b111) as usize) << 6 | (index & 0x3f) as usize]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::from_colors(&SYSTEM_PALLETE)
+    }
+}
@@ -0,0 +1,314 @@
+#[cfg(test)]
+mod test {
+    use crate::cartridge::{Mirroring, Rom};
+    use crate::mapper;
+    use crate::ppu::NesPPU;
+    use crate::render::frame::Frame;
+    use crate::render::palette::{Palette, SYSTEM_PALLETE};
+    use crate::ppu::registers::status::StatusRegister;
+    use crate::render::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn write_vram(ppu: &mut NesPPU, addr: u16, data: &[u8]) {
+        // $2007 only steps by 1 while rendering is off.
+        let mask = ppu.mask.bits();
+        ppu.write_to_mask(0);
+        ppu.write_to_ppu_addr((addr >> 8) as u8);
+        ppu.write_to_ppu_addr(addr as u8);
+        for byte in data {
+            ppu.write_to_data(*byte);
+        }
+        // Point the VRAM address back at the top-left corner, like a game
+        // does before turning rendering on.
+        ppu.write_to_ppu_addr(0x00);
+        ppu.write_to_ppu_addr(0x00);
+        ppu.write_to_mask(mask);
+    }
+
+    // Runs the PPU until it has drawn a whole frame from the top.
+    fn render(ppu: &mut NesPPU) -> &Frame {
+        for _ in 0..2 {
+            while !ppu.poll_frame() {
+                ppu.tick(1);
+            }
+        }
+        &ppu.frame
+    }
+
+    // Tile 1 is solid color 3, tile 2 has color 1 in its leftmost column.
+    // Every nametable entry uses tile 1, the top-left attribute quadrant
+    // selects palette 1.
+    fn test_ppu() -> NesPPU {
+        let rom = Rom {
+            screen_mirroring: Mirroring::Horizontal,
+            ..Rom::test_rom(0, vec![0; 0x8000], vec![])
+        };
+        let mut ppu = NesPPU::new(Some(Rc::new(RefCell::new(mapper::from_rom(rom).unwrap()))));
+        write_vram(&mut ppu, 0x0010, &[0xff; 16]);
+        write_vram(&mut ppu, 0x0020, &[0x80; 8]);
+        write_vram(&mut ppu, 0x2000, &[1; 0x3c0]);
+        write_vram(&mut ppu, 0x23c0, &[0b01; 64]);
+        write_vram(&mut ppu, 0x3f00, &[0x0f, 0x01, 0x02, 0x03, 0x0f, 0x11, 0x12, 0x13]);
+        ppu.write_to_mask(0b0000_1010);
+        ppu
+    }
+
+    #[test]
+    fn test_render_background_tiles() {
+        let mut ppu = test_ppu();
+        let frame = render(&mut ppu);
+
+        // Top-left quadrant uses palette 1, the one next to it palette 0.
+        assert_eq!(frame.pixel(0, 0), SYSTEM_PALLETE[0x13]);
+        assert_eq!(frame.pixel(16, 0), SYSTEM_PALLETE[0x03]);
+        assert_eq!(frame.pixel(255, 239), SYSTEM_PALLETE[0x03]);
+    }
+
+    #[test]
+    fn test_render_pixel_order_and_backdrop() {
+        let mut ppu = test_ppu();
+        write_vram(&mut ppu, 0x2000, &[2]);
+        let frame = render(&mut ppu);
+
+        assert_eq!(frame.pixel(0, 0), SYSTEM_PALLETE[0x11]);
+        assert_eq!(frame.pixel(1, 0), SYSTEM_PALLETE[0x0f]);
+    }
+
+    #[test]
+    fn test_render_left_column_clipping() {
+        let mut ppu = test_ppu();
+        ppu.write_to_mask(0b0000_1000);
+        let frame = render(&mut ppu);
+
+        assert_eq!(frame.pixel(7, 0), SYSTEM_PALLETE[0x0f]);
+        assert_eq!(frame.pixel(8, 0), SYSTEM_PALLETE[0x13]);
+    }
+
+    #[test]
+    fn test_render_background_disabled() {
+        let mut ppu = test_ppu();
+        ppu.write_to_mask(0);
+        let frame = render(&mut ppu);
+
+        assert_eq!(frame.pixel(100, 100), SYSTEM_PALLETE[0x0f]);
+    }
+
+    fn set_sprite(ppu: &mut NesPPU, index: usize, y: u8, tile: u8, attributes: u8, x: u8) {
+        ppu.oam_data[index * 4..index * 4 + 4].copy_from_slice(&[y, tile, attributes, x]);
+    }
+
+    fn sprite_ppu() -> NesPPU {
+        let mut ppu = test_ppu();
+        // Move every sprite off screen, use a blank background.
+        ppu.oam_data = [0xff; 256];
+        write_vram(&mut ppu, 0x2000, &[0; 0x3c0]);
+        write_vram(&mut ppu, 0x3f10, &[0x0f, 0x21, 0x22, 0x23, 0x0f, 0x31, 0x32, 0x33]);
+        ppu.write_to_mask(0b0001_1110);
+        ppu
+    }
+
+    #[test]
+    fn test_render_sprite() {
+        let mut ppu = sprite_ppu();
+        set_sprite(&mut ppu, 0, 9, 1, 0b01, 20);
+        let frame = render(&mut ppu);
+
+        // OAM Y is the line above the sprite's top row.
+        assert_eq!(frame.pixel(20, 9), SYSTEM_PALLETE[0x0f]);
+        assert_eq!(frame.pixel(20, 10), SYSTEM_PALLETE[0x33]);
+        assert_eq!(frame.pixel(27, 17), SYSTEM_PALLETE[0x33]);
+        assert_eq!(frame.pixel(28, 10), SYSTEM_PALLETE[0x0f]);
+        assert_eq!(frame.pixel(20, 18), SYSTEM_PALLETE[0x0f]);
+    }
+
+    #[test]
+    fn test_render_sprite_flipping() {
+        let mut ppu = sprite_ppu();
+        // Tile 3: color 1 in the top-left pixel only.
+        write_vram(&mut ppu, 0x0030, &[0x80]);
+        set_sprite(&mut ppu, 0, 9, 3, 0b0100_0000, 20);
+        set_sprite(&mut ppu, 1, 29, 3, 0b1000_0000, 20);
+        let frame = render(&mut ppu);
+
+        assert_eq!(frame.pixel(27, 10), SYSTEM_PALLETE[0x21]);
+        assert_eq!(frame.pixel(20, 10), SYSTEM_PALLETE[0x0f]);
+        assert_eq!(frame.pixel(20, 37), SYSTEM_PALLETE[0x21]);
+        assert_eq!(frame.pixel(20, 30), SYSTEM_PALLETE[0x0f]);
+    }
+
+    #[test]
+    fn test_render_sprite_8x16() {
+        let mut ppu = sprite_ppu();
+        ppu.write_to_ctl(0b0010_0000);
+        // Odd tile numbers select the $1000 pattern table.
+        write_vram(&mut ppu, 0x1020, &[0xff; 8]);
+        write_vram(&mut ppu, 0x1030, &[0; 8]);
+        write_vram(&mut ppu, 0x1038, &[0xff; 8]);
+        set_sprite(&mut ppu, 0, 9, 3, 0, 20);
+        let frame = render(&mut ppu);
+
+        assert_eq!(frame.pixel(20, 10), SYSTEM_PALLETE[0x21]);
+        assert_eq!(frame.pixel(20, 18), SYSTEM_PALLETE[0x22]);
+        assert_eq!(frame.pixel(20, 25), SYSTEM_PALLETE[0x22]);
+        assert_eq!(frame.pixel(20, 26), SYSTEM_PALLETE[0x0f]);
+    }
+
+    #[test]
+    fn test_render_sprite_priority() {
+        let mut ppu = sprite_ppu();
+        write_vram(&mut ppu, 0x2000, &[2]); // color 1 in column 0 of the first tile
+        ppu.write_to_mask(0b0001_1110);
+        set_sprite(&mut ppu, 0, 0xff, 0, 0, 0);
+        set_sprite(&mut ppu, 1, 0, 1, 0b0010_0000, 0);
+        set_sprite(&mut ppu, 2, 0, 1, 0b01, 0);
+        let frame = render(&mut ppu);
+
+        // Sprite 1 wins over sprite 2 and hides behind the opaque background
+        // pixel, but still covers the transparent ones.
+        assert_eq!(frame.pixel(0, 1), SYSTEM_PALLETE[0x11]);
+        assert_eq!(frame.pixel(1, 1), SYSTEM_PALLETE[0x23]);
+    }
+
+    #[test]
+    fn test_sprite_per_line_limit() {
+        let mut ppu = sprite_ppu();
+        for i in 0..9 {
+            set_sprite(&mut ppu, i, 9, 1, (i % 2) as u8, 10 * i as u8);
+        }
+        let frame = render(&mut ppu);
+
+        assert_eq!(frame.pixel(70, 10), SYSTEM_PALLETE[0x33]);
+        assert_eq!(frame.pixel(80, 10), SYSTEM_PALLETE[0x0f]);
+        assert!(ppu.status.contains(StatusRegister::SPRITE_OVERFLOW));
+    }
+
+    #[test]
+    fn test_sprite_overflow_bug() {
+        let mut ppu = sprite_ppu();
+        for i in 0..8 {
+            set_sprite(&mut ppu, i, 9, 1, 0, 0);
+        }
+        // Only eight sprites are on line 10, but after the eighth the
+        // evaluation reads sprite 9's tile number as its Y coordinate.
+        set_sprite(&mut ppu, 9, 0xff, 9, 0, 0);
+        assert_eq!(evaluate_sprites(&ppu, 10), ((0..8).collect(), true));
+
+        // With an off-screen tile number there is no false positive.
+        set_sprite(&mut ppu, 9, 0xff, 0xff, 0, 0);
+        assert_eq!(evaluate_sprites(&ppu, 10), ((0..8).collect(), false));
+    }
+
+    #[test]
+    fn test_sprite_zero_hit() {
+        let mut ppu = test_ppu();
+        ppu.oam_data = [0xff; 256];
+        ppu.write_to_mask(0b0001_1110);
+        set_sprite(&mut ppu, 0, 9, 3, 0, 20);
+        write_vram(&mut ppu, 0x0030, &[0, 0, 0, 0x10]);
+
+        render(&mut ppu);
+        assert!(ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+
+        // Transparent sprite pixels never hit.
+        write_vram(&mut ppu, 0x0030, &[0; 4]);
+        render(&mut ppu);
+        assert!(!ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+
+        // No hit at x = 255 or in the clipped left column.
+        write_vram(&mut ppu, 0x0030, &[0, 0, 0, 0x01]);
+        set_sprite(&mut ppu, 0, 9, 3, 0, 248);
+        render(&mut ppu);
+        assert!(!ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+        write_vram(&mut ppu, 0x0030, &[0, 0, 0, 0x10]);
+        set_sprite(&mut ppu, 0, 9, 3, 0, 4);
+        ppu.write_to_mask(0b0001_1100);
+        render(&mut ppu);
+        assert!(!ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+    }
+
+    #[test]
+    fn test_greyscale_and_emphasis() {
+        let mut ppu = test_ppu();
+        ppu.write_to_mask(0b0000_1011);
+        let frame = render(&mut ppu);
+        assert_eq!(frame.pixel(0, 0), SYSTEM_PALLETE[0x10]);
+        assert_eq!(frame.pixel(16, 0), SYSTEM_PALLETE[0x00]);
+
+        // Emphasizing red dims green and blue.
+        ppu.write_to_mask(0b0010_1010);
+        let (r, g, b) = SYSTEM_PALLETE[0x13];
+        let (er, eg, eb) = render(&mut ppu).pixel(0, 0);
+        assert_eq!(er, r);
+        assert!(eg < g && eb < b);
+    }
+
+    #[test]
+    fn test_pal_files() {
+        let data: Vec<u8> = (0..192).map(|i| i as u8).collect();
+        let palette = Palette::from_pal(&data).unwrap();
+        assert_eq!(palette.color(1, 0), (3, 4, 5));
+        assert_eq!(palette.color(0x41, 0), (3, 4, 5));
+
+        // 512-entry files list every emphasis combination themselves.
+        let data: Vec<u8> = (0..1536).map(|i| (i / 192) as u8).collect();
+        let palette = Palette::from_pal(&data).unwrap();
+        assert_eq!(palette.color(0x3f, 0), (0, 0, 0));
+        assert_eq!(palette.color(0, 0b101), (5, 5, 5));
+
+        assert!(Palette::from_pal(&[0; 100]).is_err());
+    }
+
+    #[test]
+    fn test_swapping_the_palette() {
+        let mut ppu = test_ppu();
+        ppu.palette = Palette::from_pal(&[0x42; 192]).unwrap();
+        assert_eq!(render(&mut ppu).pixel(0, 0), (0x42, 0x42, 0x42));
+    }
+
+    fn ntsc_pixel(frame: &Frame, x: usize, y: usize) -> (u8, u8, u8) {
+        let mut filter = ntsc::NtscFilter::new(ntsc::NtscSettings::default());
+        let data = filter.apply(frame);
+        let base = (y * ntsc::NtscFilter::WIDTH + x) * 3;
+        (data[base], data[base + 1], data[base + 2])
+    }
+
+    fn solid_frame(index: u16) -> Frame {
+        let mut frame = Frame::new();
+        frame.indices = vec![index; Frame::WIDTH * Frame::HEIGHT];
+        frame
+    }
+
+    #[test]
+    fn test_ntsc_filter_colors() {
+        assert_eq!(ntsc::NtscFilter::WIDTH, 512);
+        let (r, g, b) = ntsc_pixel(&solid_frame(0x30), 200, 100);
+        assert!(r > 250 && g > 250 && b > 250);
+        let (r, g, b) = ntsc_pixel(&solid_frame(0x0f), 200, 100);
+        assert_eq!((r, g, b), (0, 0, 0));
+
+        let (r, g, b) = ntsc_pixel(&solid_frame(0x16), 200, 100);
+        assert!(r > 2 * g && r > 2 * b);
+        let (r, g, b) = ntsc_pixel(&solid_frame(0x12), 200, 100);
+        assert!(b > 2 * r && b > 2 * g);
+
+        // Emphasizing blue darkens a white screen's red and green.
+        let (r, g, b) = ntsc_pixel(&solid_frame(0b100 << 6 | 0x30), 200, 100);
+        assert!(b > r && b > g);
+    }
+
+    #[test]
+    fn test_ntsc_dot_crawl() {
+        // A white/black column edge picks up colored fringes that move with
+        // the subcarrier phase, while flat areas stay the same.
+        let mut frame = solid_frame(0x0f);
+        for line in frame.indices.chunks_mut(Frame::WIDTH) {
+            line[128..].fill(0x30);
+        }
+        let edge = ntsc_pixel(&frame, 256, 50);
+        let flat = ntsc_pixel(&frame, 400, 50);
+        frame.phase = 4;
+        assert_ne!(ntsc_pixel(&frame, 256, 50), edge);
+        assert_eq!(ntsc_pixel(&frame, 400, 50), flat);
+    }
+}
@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod test {
+    use crate::wav::*;
+
+    fn wav_path(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("emu_wav_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("out.wav")
+    }
+
+    fn u16_at(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([data[offset], data[offset + 1]])
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_header() {
+        let path = wav_path("header");
+        let mut recorder = WavRecorder::create(&path, 48000, 2).unwrap();
+        recorder.write(&[0.0, 1.0, -1.0, 0.5]).unwrap();
+        recorder.write(&[2.0, 0.25]).unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32_at(&data, 4), data.len() as u32 - 8);
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!(u16_at(&data, 20), 1);
+        assert_eq!(u16_at(&data, 22), 2);
+        assert_eq!(u32_at(&data, 24), 48000);
+        assert_eq!(u32_at(&data, 28), 48000 * 4);
+        assert_eq!(u16_at(&data, 32), 4);
+        assert_eq!(u16_at(&data, 34), 16);
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(u32_at(&data, 40), 12);
+        assert_eq!(data.len(), 44 + 12);
+    }
+
+    #[test]
+    fn test_samples() {
+        let path = wav_path("samples");
+        let mut recorder = WavRecorder::create(&path, 44100, 1).unwrap();
+        // Out-of-range samples are clipped.
+        recorder.write(&[0.0, 1.0, -1.0, 0.5, 2.0]).unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        let samples: Vec<i16> = data[44..]
+            .chunks(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        assert_eq!(samples, vec![0, 32767, -32767, 16383, 32767]);
+    }
+}